@@ -0,0 +1,178 @@
+/*
+ * Copyright (c) 2014 Cameron Hart
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a
+ * copy of this software and associated documentation files (the "Software"),
+ * to deal in the Software without restriction, including without limitation
+ * the rights to use, copy, modify, merge, publish, distribute, sublicense,
+ * and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+ * THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+ * FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+ * DEALINGS IN THE SOFTWARE.
+ */
+
+use convert;
+use convert::{ConvertError, Options};
+use image::Image;
+use ktx;
+use ktx::{KeyValueData, KtxImage};
+use std::cmp;
+
+/// The key holding an atlas's table of UV rectangles. Each line of the
+/// value is a name followed by the rectangle's u0, v0, u1 and v1.
+pub const KEY_ATLAS: &'static str = "sb6Atlas";
+
+/// The largest atlas that will be built
+const MAX_SIZE: usize = 16384;
+
+/// Where an image was placed in an atlas, in OpenGL texture coordinates
+/// with the origin at the bottom left
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rect {
+    pub name: String,
+    pub u0: f32,
+    pub v0: f32,
+    pub u1: f32,
+    pub v1: f32
+}
+
+fn next_power_of_two(n: usize) -> usize {
+    let mut p = 1;
+    while p < n {
+        p *= 2;
+    }
+    p
+}
+
+/// Packs rectangles into shelves of the given width, tallest first.
+/// Returns the height used and the position of each rectangle.
+fn pack_shelves(sizes: &[(usize, usize)], width: usize) -> (usize, Vec<(usize, usize)>) {
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by(|&a, &b| sizes[b].1.cmp(&sizes[a].1));
+    let mut positions = vec![(0, 0); sizes.len()];
+    let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+    for i in order {
+        let (w, h) = sizes[i];
+        if x + w > width {
+            x = 0;
+            y += shelf_height;
+            shelf_height = 0;
+        }
+        positions[i] = (x, y);
+        x += w;
+        shelf_height = cmp::max(shelf_height, h);
+    }
+    (y + shelf_height, positions)
+}
+
+/// Chooses a roughly square power of two atlas size for the given
+/// rectangles and packs them. Returns the atlas width and height and the
+/// position of each rectangle, or None if they won't fit in the largest
+/// atlas.
+pub fn pack(sizes: &[(usize, usize)]) -> Option<(usize, usize, Vec<(usize, usize)>)> {
+    let area = sizes.iter().fold(0, |total, &(w, h)| total + w * h);
+    let widest = sizes.iter().fold(1, |widest, &(w, _)| cmp::max(widest, w));
+    let mut width = next_power_of_two(cmp::max(widest, (area as f64).sqrt().ceil() as usize));
+    while width <= MAX_SIZE {
+        let (height, positions) = pack_shelves(sizes, width);
+        let height = next_power_of_two(cmp::max(height, 1));
+        if height <= width {
+            return Some((width, height, positions))
+        }
+        width *= 2;
+    }
+    None
+}
+
+/// Packs named images of any size into a single 2D texture, with each image
+/// surrounded by `padding` pixels copied from its edges so filtering and
+/// mipmapping don't bleed between neighbours. The UV rectangle of every
+/// image is stored under `KEY_ATLAS`.
+pub fn build(images: &[(&str, &Image)], padding: usize, options: &Options)
+             -> Result<KtxImage, ConvertError> {
+    if images.is_empty() {
+        return Err(ConvertError::SizeError(String::from("an atlas needs an image")))
+    }
+    if images.iter().any(|&(name, _)| name.is_empty() || name.contains(char::is_whitespace)) {
+        return Err(ConvertError::SizeError(String::from(
+            "atlas image names must be non-empty with no whitespace")))
+    }
+    let sizes: Vec<(usize, usize)> = images.iter()
+        .map(|&(_, image)| (image.width + padding * 2, image.height + padding * 2))
+        .collect();
+    let (width, height, positions) = match pack(&sizes) {
+        Some(packed) => packed,
+        None => return Err(ConvertError::SizeError(format!(
+            "images don't fit in a {}x{} atlas", MAX_SIZE, MAX_SIZE)))
+    };
+
+    let mut atlas = Image::new(width, height, images[0].1.linear);
+    let mut table = String::new();
+    for (&(name, image), &(x, y)) in images.iter().zip(positions.iter()) {
+        let (x, y) = (x + padding, y + padding);
+        let p = padding as isize;
+        for dy in -p..image.height as isize + p {
+            for dx in -p..image.width as isize + p {
+                let sx = cmp::max(0, cmp::min(dx, image.width as isize - 1)) as usize;
+                let sy = cmp::max(0, cmp::min(dy, image.height as isize - 1)) as usize;
+                atlas.set((x as isize + dx) as usize, (y as isize + dy) as usize,
+                          image.get(sx, sy));
+            }
+        }
+        // the atlas is stored top to bottom and flipped on upload
+        table.push_str(&format!("{} {} {} {} {}\n", name,
+            x as f32 / width as f32,
+            1.0 - (y + image.height) as f32 / height as f32,
+            (x + image.width) as f32 / width as f32,
+            1.0 - y as f32 / height as f32));
+    }
+
+    let mut header = options.format.header(width, height);
+    header.faces = 1;
+    header.mip_levels = 1;
+    let mut key_values = KeyValueData::new();
+    key_values.insert_str(ktx::KEY_ORIENTATION, "S=r,T=d");
+    key_values.insert_str(KEY_ATLAS, &table);
+    convert::texture(header, key_values, &[atlas], options)
+}
+
+/// Reads the UV rectangle table from an atlas's key value data. Returns
+/// None if there is no table or it is malformed.
+pub fn rects(key_values: &KeyValueData) -> Option<Vec<Rect>> {
+    let table = match key_values.get_str(KEY_ATLAS) {
+        Some(t) => t,
+        None => return None
+    };
+    let mut rects = Vec::new();
+    for line in table.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() != 5 {
+            return None
+        }
+        let mut uv = [0.0; 4];
+        for (i, field) in fields[1..].iter().enumerate() {
+            uv[i] = match field.parse::<f32>() {
+                Ok(v) => v,
+                Err(_) => return None
+            };
+        }
+        rects.push(Rect {
+            name: String::from(fields[0]),
+            u0: uv[0],
+            v0: uv[1],
+            u1: uv[2],
+            v1: uv[3]
+        });
+    }
+    Some(rects)
+}
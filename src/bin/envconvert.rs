@@ -0,0 +1,205 @@
+/*
+ * Copyright (c) 2014 Cameron Hart
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a
+ * copy of this software and associated documentation files (the "Software"),
+ * to deal in the Software without restriction, including without limitation
+ * the rights to use, copy, modify, merge, publish, distribute, sublicense,
+ * and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+ * THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+ * FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+ * DEALINGS IN THE SOFTWARE.
+ */
+
+extern crate sb6;
+
+use sb6::convert;
+use sb6::convert::{Format, Options};
+use sb6::envmap;
+use sb6::envmap::{CubeOptions, Projection};
+use sb6::image;
+use sb6::image::Image;
+use sb6::ktx::KtxImage;
+use sb6::mipmap::Filter;
+use std::cmp;
+use std::env;
+use std::fs;
+use std::io::Read;
+use std::process;
+
+fn usage(program: &str) -> ! {
+    println!("usage: {} [options] input output.ktx", program);
+    println!("Converts between equirectangular, sphere map and cube map environments");
+    println!("");
+    println!("  -i, --input TYPE     equirect, sphere or cube, defaults to cube for cube");
+    println!("                       map KTX files and equirect otherwise");
+    println!("  -o, --output TYPE    equirect, sphere or cube, defaults to cube unless");
+    println!("                       the input is a cube map");
+    println!("  -s, --size SIZE      the cube face size, or the height of a 2D output");
+    println!("  -f, --format FORMAT  rgba8, srgb8_alpha8, rgba16f or rgb9_e5, defaults");
+    println!("                       to rgba16f for HDR sources, srgb8_alpha8 otherwise");
+    println!("  -m, --mipmaps        generate a full mip chain");
+    println!("      --prefilter      prefilter cube map mip levels with increasing GGX");
+    println!("                       roughness for specular image based lighting");
+    println!("      --samples N      samples per texel when prefiltering, default 256");
+    process::exit(2);
+}
+
+/// Parses a projection name, None means a cube map
+fn parse_type(name: &str, program: &str) -> Option<Projection> {
+    match name {
+        "equirect" => Some(Projection::Equirectangular),
+        "sphere" => Some(Projection::SphereMap),
+        "cube" => None,
+        _ => {
+            println!("unknown environment type '{}'", name);
+            usage(program)
+        }
+    }
+}
+
+fn fail(message: String) -> ! {
+    println!("{}", message);
+    process::exit(1);
+}
+
+/// Reads a KTX file or an image, returning its layers and faces
+fn read_input(filename: &str) -> Vec<Image> {
+    let mut bytes = Vec::new();
+    if let Err(e) = fs::File::open(filename).and_then(|mut f| f.read_to_end(&mut bytes)) {
+        fail(format!("Error reading '{}': {}", filename, e));
+    }
+    if bytes.starts_with(b"\xABKTX") {
+        KtxImage::parse(bytes)
+            .map_err(convert::ConvertError::from)
+            .and_then(|ktx| convert::ktx_images(&ktx, 0))
+            .unwrap_or_else(|e| fail(format!("Error reading '{}': {}", filename, e)))
+    }
+    else {
+        match image::decode(&bytes) {
+            Ok(image) => vec![image],
+            Err(e) => fail(format!("Error reading '{}': {}", filename, e))
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let program = &args[0];
+    let mut input_type = None;
+    let mut output_type = None;
+    let mut size = None;
+    let mut format = None;
+    let mut mipmaps = false;
+    let mut prefilter = false;
+    let mut samples = 256;
+    let mut files = Vec::new();
+
+    let mut i = 1;
+    while i < args.len() {
+        let has_value = i + 1 < args.len();
+        match &args[i][..] {
+            "-i" | "--input" if has_value => {
+                i += 1;
+                input_type = Some(parse_type(&args[i], program));
+            },
+            "-o" | "--output" if has_value => {
+                i += 1;
+                output_type = Some(parse_type(&args[i], program));
+            },
+            "-s" | "--size" if has_value => {
+                i += 1;
+                size = match args[i].parse::<usize>() {
+                    Ok(n) if n > 0 => Some(n),
+                    _ => usage(program)
+                };
+            },
+            "-f" | "--format" if has_value => {
+                i += 1;
+                format = match Format::parse(&args[i]) {
+                    Some(f) => Some(f),
+                    None => {
+                        println!("unknown format '{}'", args[i]);
+                        usage(program)
+                    }
+                };
+            },
+            "--samples" if has_value => {
+                i += 1;
+                samples = match args[i].parse::<u32>() {
+                    Ok(n) if n > 0 => n,
+                    _ => usage(program)
+                };
+            },
+            "-m" | "--mipmaps" => mipmaps = true,
+            "--prefilter" => prefilter = true,
+            arg if arg.starts_with("-") => usage(program),
+            arg => files.push(arg)
+        }
+        i += 1;
+    }
+    if files.len() != 2 {
+        usage(program);
+    }
+
+    let images = read_input(files[0]);
+    let input_type = input_type.unwrap_or(
+        if images.len() == 6 { None } else { Some(Projection::Equirectangular) });
+    let output_type = output_type.unwrap_or(
+        if input_type.is_none() { Some(Projection::Equirectangular) } else { None });
+    let format = format.unwrap_or(
+        if images[0].linear { Format::Rgba16f } else { Format::Srgb8Alpha8 });
+
+    // gather the source as a cube map
+    let faces = match input_type {
+        None if images.len() >= 6 => images[..6].to_vec(),
+        None => fail(format!("'{}' isn't a cube map", files[0])),
+        Some(projection) => {
+            let src = &images[0];
+            let face_size = size.unwrap_or(match projection {
+                Projection::Equirectangular => cmp::max(1, src.height / 2),
+                Projection::SphereMap => cmp::max(1, src.width / 2)
+            });
+            envmap::to_cube(src, projection, face_size)
+        }
+    };
+
+    let ktx = match output_type {
+        None => {
+            let options = CubeOptions {
+                format: format,
+                mipmaps: mipmaps || prefilter,
+                prefilter: prefilter,
+                samples: samples
+            };
+            envmap::cube_texture(&faces, &options)
+        },
+        Some(projection) => {
+            let height = size.unwrap_or(faces[0].width * 2);
+            let width = match projection {
+                Projection::Equirectangular => height * 2,
+                Projection::SphereMap => height
+            };
+            let options = Options {
+                format: format,
+                mipmaps: if mipmaps { Some(Filter::Kaiser) } else { None }
+            };
+            convert::texture_2d(&envmap::from_cube(&faces, projection, width, height),
+                                &options)
+        }
+    };
+    let ktx = ktx.unwrap_or_else(|e| fail(format!("Error converting: {}", e)));
+    if let Err(e) = sb6::ktx::save(files[1], &ktx) {
+        fail(format!("Error writing '{}': {}", files[1], e));
+    }
+}
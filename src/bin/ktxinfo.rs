@@ -0,0 +1,157 @@
+/*
+ * Copyright (c) 2014 Cameron Hart
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a
+ * copy of this software and associated documentation files (the "Software"),
+ * to deal in the Software without restriction, including without limitation
+ * the rights to use, copy, modify, merge, publish, distribute, sublicense,
+ * and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+ * THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+ * FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+ * DEALINGS IN THE SOFTWARE.
+ */
+
+extern crate gl;
+extern crate sb6;
+
+use gl::types::*;
+use sb6::ktx::{Header, KtxImage};
+use std::env;
+use std::process;
+use std::str;
+
+fn target_name(target: GLenum) -> &'static str {
+    match target {
+        gl::TEXTURE_1D => "GL_TEXTURE_1D",
+        gl::TEXTURE_1D_ARRAY => "GL_TEXTURE_1D_ARRAY",
+        gl::TEXTURE_2D => "GL_TEXTURE_2D",
+        gl::TEXTURE_2D_ARRAY => "GL_TEXTURE_2D_ARRAY",
+        gl::TEXTURE_3D => "GL_TEXTURE_3D",
+        gl::TEXTURE_CUBE_MAP => "GL_TEXTURE_CUBE_MAP",
+        gl::TEXTURE_CUBE_MAP_ARRAY => "GL_TEXTURE_CUBE_MAP_ARRAY",
+        _ => "unknown"
+    }
+}
+
+fn print_header(h: &Header) {
+    println!("glType:                0x{:04x}", h.gl_type);
+    println!("glTypeSize:            {}", h.gl_type_size);
+    println!("glFormat:              0x{:04x}", h.gl_format);
+    println!("glInternalFormat:      0x{:04x}", h.gl_internal_format);
+    println!("glBaseInternalFormat:  0x{:04x}", h.gl_base_internal_format);
+    println!("pixelWidth:            {}", h.pixel_width);
+    println!("pixelHeight:           {}", h.pixel_height);
+    println!("pixelDepth:            {}", h.pixel_depth);
+    println!("numberOfArrayElements: {}", h.array_elements);
+    println!("numberOfFaces:         {}", h.faces);
+    println!("numberOfMipmapLevels:  {}", h.mip_levels);
+    println!("bytesOfKeyValueData:   {}", h.key_pair_bytes);
+}
+
+/// Formats a key value for display, printing text values as strings and
+/// anything else as hex
+fn format_value(value: &[u8]) -> String {
+    let text = match value.last() {
+        Some(&0) => &value[..value.len() - 1],
+        _ => value
+    };
+    match str::from_utf8(text) {
+        Ok(s) if s.chars().all(|c| !c.is_control() || c == '\n') => format!("\"{}\"", s),
+        _ => value.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ")
+    }
+}
+
+/// Returns problems the parser tolerates but the KTX specification doesn't
+fn check(image: &KtxImage) -> Vec<String> {
+    let h = image.header();
+    let mut problems = Vec::new();
+    if h.is_compressed() {
+        if h.gl_format != 0 {
+            problems.push(format!("glFormat is 0x{:04x}, compressed textures must use 0",
+                h.gl_format));
+        }
+        if h.gl_type_size != 1 {
+            problems.push(format!("glTypeSize is {}, compressed textures must use 1",
+                h.gl_type_size));
+        }
+    }
+    else if h.gl_format != h.gl_base_internal_format {
+        problems.push(format!("glBaseInternalFormat 0x{:04x} doesn't match glFormat 0x{:04x}",
+            h.gl_base_internal_format, h.gl_format));
+    }
+    if h.faces != 1 && h.faces != 6 {
+        problems.push(format!("numberOfFaces is {}, must be 1 or 6", h.faces));
+    }
+    if h.image_size(0).is_none() {
+        problems.push(String::from("unknown format, image sizes can't be checked"));
+    }
+    if image.key_values().get(sb6::ktx::KEY_ORIENTATION).is_some() &&
+        image.key_values().orientation().is_none() {
+        problems.push(String::from("malformed KTXorientation value"));
+    }
+    problems
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        println!("usage: {} file.ktx...", args[0]);
+        process::exit(2);
+    }
+
+    let mut failed = false;
+    for filename in &args[1..] {
+        println!("{}", filename);
+        let image = match KtxImage::open(filename) {
+            Ok(image) => image,
+            Err(e) => {
+                println!("error: {}", e);
+                failed = true;
+                continue
+            }
+        };
+
+        let h = image.header();
+        print_header(h);
+        println!("target:                {}", target_name(image.target()));
+        if image.is_swapped() {
+            println!("byte order:            swapped");
+        }
+
+        for level in 0..image.num_levels() {
+            let (width, height, depth) = image.dimensions(level);
+            println!("level {}: {}x{}x{}, {} bytes", level, width, height, depth,
+                image.level(level).len());
+            for layer in 0..h.num_layers() {
+                for face in 0..h.num_faces() {
+                    println!("  layer {} face {}: {} bytes", layer, face,
+                        image.image(level, layer, face).len());
+                }
+            }
+        }
+
+        for &(ref key, ref value) in image.key_values().iter() {
+            println!("{} = {}", key, format_value(value));
+        }
+
+        for problem in check(&image) {
+            println!("error: {}", problem);
+            failed = true;
+        }
+        println!("");
+    }
+
+    if failed {
+        process::exit(1);
+    }
+}
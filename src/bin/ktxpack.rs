@@ -0,0 +1,140 @@
+/*
+ * Copyright (c) 2014 Cameron Hart
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a
+ * copy of this software and associated documentation files (the "Software"),
+ * to deal in the Software without restriction, including without limitation
+ * the rights to use, copy, modify, merge, publish, distribute, sublicense,
+ * and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+ * THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+ * FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+ * DEALINGS IN THE SOFTWARE.
+ */
+
+extern crate sb6;
+
+use sb6::atlas;
+use sb6::convert;
+use sb6::convert::{Format, Options};
+use sb6::image;
+use sb6::mipmap::Filter;
+use std::env;
+use std::path::Path;
+use std::process;
+
+fn usage(program: &str) -> ! {
+    println!("usage: {} [options] array|atlas output.ktx input...", program);
+    println!("Packs images into a 2D array texture, or an atlas of any sized images");
+    println!("with a table of UV rectangles keyed by file name");
+    println!("");
+    println!("  -f, --format FORMAT  rgba8, srgb8_alpha8, rgba16f or rgb9_e5, defaults");
+    println!("                       to srgb8_alpha8, or rgba16f for HDR images");
+    println!("  -m, --mipmaps        generate a full mip chain");
+    println!("      --filter FILTER  box, kaiser or lanczos, the mip filter, defaults");
+    println!("                       to kaiser");
+    println!("  -p, --padding N      pixels of edge padding around atlas images,");
+    println!("                       defaults to 2");
+    process::exit(2);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let program = &args[0];
+    let mut format = None;
+    let mut mipmaps = false;
+    let mut filter = Filter::Kaiser;
+    let mut padding = 2;
+    let mut files = Vec::new();
+
+    let mut i = 1;
+    while i < args.len() {
+        let has_value = i + 1 < args.len();
+        match &args[i][..] {
+            "-f" | "--format" if has_value => {
+                i += 1;
+                format = match Format::parse(&args[i]) {
+                    Some(f) => Some(f),
+                    None => {
+                        println!("unknown format '{}'", args[i]);
+                        usage(program)
+                    }
+                };
+            },
+            "--filter" if has_value => {
+                i += 1;
+                filter = match Filter::parse(&args[i]) {
+                    Some(f) => f,
+                    None => {
+                        println!("unknown filter '{}'", args[i]);
+                        usage(program)
+                    }
+                };
+            },
+            "-p" | "--padding" if has_value => {
+                i += 1;
+                padding = match args[i].parse::<usize>() {
+                    Ok(n) => n,
+                    Err(_) => usage(program)
+                };
+            },
+            "-m" | "--mipmaps" => mipmaps = true,
+            arg if arg.starts_with("-") => usage(program),
+            arg => files.push(arg)
+        }
+        i += 1;
+    }
+    if files.len() < 3 || (files[0] != "array" && files[0] != "atlas") {
+        usage(program);
+    }
+
+    let mut images = Vec::new();
+    for input in &files[2..] {
+        match image::open(input) {
+            Ok(image) => images.push(image),
+            Err(e) => {
+                println!("Error reading '{}': {}", input, e);
+                process::exit(1);
+            }
+        }
+    }
+
+    let options = Options {
+        format: format.unwrap_or(
+            if images[0].linear { Format::Rgba16f } else { Format::Srgb8Alpha8 }),
+        mipmaps: if mipmaps { Some(filter) } else { None }
+    };
+    let result = if files[0] == "array" {
+        convert::texture_2d_array(&images, &options)
+    }
+    else {
+        // name each image after its file
+        let names: Vec<String> = files[2..].iter().map(|f| {
+            Path::new(f).file_stem().map_or(String::from(*f),
+                |s| s.to_string_lossy().replace(char::is_whitespace, "_"))
+        }).collect();
+        let named: Vec<(&str, &image::Image)> = names.iter().map(|n| &n[..])
+            .zip(images.iter()).collect();
+        atlas::build(&named, padding, &options)
+    };
+    let ktx = match result {
+        Ok(ktx) => ktx,
+        Err(e) => {
+            println!("Error converting: {}", e);
+            process::exit(1);
+        }
+    };
+    if let Err(e) = sb6::ktx::save(files[1], &ktx) {
+        println!("Error writing '{}': {}", files[1], e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,210 @@
+/*
+ * Copyright (c) 2014 Cameron Hart
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a
+ * copy of this software and associated documentation files (the "Software"),
+ * to deal in the Software without restriction, including without limitation
+ * the rights to use, copy, modify, merge, publish, distribute, sublicense,
+ * and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+ * THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+ * FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+ * DEALINGS IN THE SOFTWARE.
+ */
+
+extern crate sb6;
+
+use sb6::ktx::KtxImage;
+use sb6::object::SbmMesh;
+use std::env;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::process;
+use std::time::Instant;
+
+/// The number of largest files of each kind picked from a directory
+const FILES_PER_DIRECTORY: usize = 3;
+
+fn usage(program: &str) -> ! {
+    println!("usage: {} [options] file-or-directory...", program);
+    println!("Compares loading KTX and SBM files by reading them into memory against");
+    println!("memory-mapping them. Directories are searched for their largest files.");
+    println!("");
+    println!("  -n, --iterations N  the number of times to load each file, defaults");
+    println!("                      to 10");
+    process::exit(2);
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Read,
+    Map
+}
+
+impl Mode {
+    fn name(&self) -> &'static str {
+        match *self {
+            Mode::Read => "read",
+            Mode::Map => "map"
+        }
+    }
+}
+
+/// Sums the bytes so that every page of a mapping is actually read
+fn touch(data: &[u8]) -> u64 {
+    data.iter().fold(0, |sum, &b| sum.wrapping_add(b as u64))
+}
+
+/// Loads a file once, calling `loaded` before the data is freed. Returns a
+/// checksum of the data.
+fn load<F: FnMut()>(filename: &str, mode: Mode, mut loaded: F) -> Result<u64, String> {
+    if filename.ends_with(".ktx") || filename.ends_with(".ktx2") {
+        let image = try!(match mode {
+            Mode::Read => fs::File::open(filename).map_err(From::from)
+                .and_then(KtxImage::read),
+            Mode::Map => KtxImage::open(filename)
+        }.map_err(|e| e.to_string()));
+        let sum = (0..image.num_levels())
+            .fold(0u64, |sum, i| sum.wrapping_add(touch(image.level(i))));
+        loaded();
+        Ok(sum)
+    }
+    else {
+        let mesh = try!(match mode {
+            Mode::Read => fs::File::open(filename).map_err(From::from)
+                .and_then(SbmMesh::read),
+            Mode::Map => SbmMesh::open(filename)
+        }.map_err(|e| e.to_string()));
+        let sum = touch(mesh.vertex_data()).wrapping_add(touch(mesh.index_data()));
+        loaded();
+        Ok(sum)
+    }
+}
+
+/// Returns the resident memory of this process that isn't backed by a file,
+/// in kilobytes, where the platform reports it. Mapped files don't count.
+fn anonymous_memory() -> Option<u64> {
+    let mut status = String::new();
+    if fs::File::open("/proc/self/status")
+            .and_then(|mut f| f.read_to_string(&mut status)).is_err() {
+        return None
+    }
+    status.lines()
+        .find(|line| line.starts_with("RssAnon:"))
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|kb| kb.parse().ok())
+}
+
+/// Runs a single load in a child process so its memory use isn't affected
+/// by other loads
+fn measure_memory(filename: &str, mode: Mode) -> Option<u64> {
+    env::current_exe().ok()
+        .and_then(|program| process::Command::new(program)
+            .args(&["--single", mode.name(), filename])
+            .output().ok())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .and_then(|out| out.trim().parse().ok())
+}
+
+/// Returns the largest KTX and SBM files in a directory
+fn largest_files(dir: &Path) -> Vec<String> {
+    let mut files: Vec<(u64, String)> = match fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(|e| e.ok())
+            .filter_map(|e| e.metadata().ok().map(|m| (m.len(), e.path())))
+            .filter_map(|(size, path)| path.to_str().map(|p| (size, String::from(p))))
+            .collect(),
+        Err(e) => {
+            println!("Error reading '{}': {}", dir.display(), e);
+            process::exit(1);
+        }
+    };
+    files.sort_by(|a, b| b.0.cmp(&a.0));
+    let mut out = Vec::new();
+    for extension in &[".ktx", ".sbm"] {
+        out.extend(files.iter().filter(|f| f.1.ends_with(extension))
+            .take(FILES_PER_DIRECTORY).map(|f| f.1.clone()));
+    }
+    out
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    if args.len() == 4 && args[1] == "--single" {
+        let mode = if args[2] == "map" { Mode::Map } else { Mode::Read };
+        let mut memory = None;
+        if let Err(e) = load(&args[3], mode, || memory = anonymous_memory()) {
+            println!("{}", e);
+            process::exit(1);
+        }
+        match memory {
+            Some(kb) => println!("{}", kb),
+            None => process::exit(1)
+        }
+        return
+    }
+
+    let mut iterations = 10;
+    let mut files = Vec::new();
+    let mut i = 1;
+    while i < args.len() {
+        match &args[i][..] {
+            "-n" | "--iterations" if i + 1 < args.len() => {
+                i += 1;
+                iterations = match args[i].parse() {
+                    Ok(n) if n > 0 => n,
+                    _ => usage(&args[0])
+                };
+            },
+            arg if arg.starts_with("-") => usage(&args[0]),
+            arg if Path::new(arg).is_dir() => files.extend(largest_files(Path::new(arg))),
+            arg => files.push(String::from(arg))
+        }
+        i += 1;
+    }
+    if files.is_empty() {
+        usage(&args[0]);
+    }
+
+    println!("{:<40} {:>10} {:>6} {:>10} {:>10} {:>10}",
+        "file", "size (KB)", "mode", "mean (ms)", "MB/s", "heap (KB)");
+    for filename in &files {
+        let size = fs::metadata(filename).map(|m| m.len()).unwrap_or(0);
+        let mut checksums = Vec::new();
+        for &mode in &[Mode::Read, Mode::Map] {
+            let start = Instant::now();
+            for _ in 0..iterations {
+                match load(filename, mode, || ()) {
+                    Ok(sum) => checksums.push(sum),
+                    Err(e) => {
+                        println!("Error loading '{}': {}", filename, e);
+                        process::exit(1);
+                    }
+                }
+            }
+            let elapsed = start.elapsed();
+            let seconds = (elapsed.as_secs() as f64 +
+                           elapsed.subsec_nanos() as f64 * 1e-9) / iterations as f64;
+            let heap = measure_memory(filename, mode)
+                .map_or(String::from("n/a"), |kb| kb.to_string());
+            println!("{:<40} {:>10} {:>6} {:>10.3} {:>10.1} {:>10}",
+                filename, size / 1024, mode.name(), seconds * 1000.0,
+                size as f64 / seconds / (1024.0 * 1024.0), heap);
+        }
+        // both ways of loading must see the same data
+        if checksums.iter().any(|&sum| sum != checksums[0]) {
+            println!("Error loading '{}': mapped data differs from read data", filename);
+            process::exit(1);
+        }
+    }
+}
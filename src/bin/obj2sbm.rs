@@ -0,0 +1,85 @@
+/*
+ * Copyright (c) 2014 Cameron Hart
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a
+ * copy of this software and associated documentation files (the "Software"),
+ * to deal in the Software without restriction, including without limitation
+ * the rights to use, copy, modify, merge, publish, distribute, sublicense,
+ * and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+ * THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+ * FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+ * DEALINGS IN THE SOFTWARE.
+ */
+
+extern crate sb6;
+
+use sb6::obj;
+use sb6::object;
+use std::env;
+use std::process;
+
+fn usage(program: &str) -> ! {
+    println!("usage: {} [options] input.obj output.sbm", program);
+    println!("Converts Wavefront OBJ models to SBM");
+    println!("");
+    println!("  -n, --normals  generate smooth normals, replacing any in the file");
+    process::exit(2);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let mut normals = false;
+    let mut files = Vec::new();
+
+    for arg in &args[1..] {
+        match &arg[..] {
+            "-n" | "--normals" => normals = true,
+            arg if arg.starts_with("-") => usage(&args[0]),
+            arg => files.push(arg)
+        }
+    }
+    if files.len() != 2 {
+        usage(&args[0]);
+    }
+
+    let mut model = match obj::open(files[0]) {
+        Ok(model) => model,
+        Err(e) => {
+            println!("Error reading '{}': {}", files[0], e);
+            process::exit(1);
+        }
+    };
+    if normals {
+        model.mesh.generate_normals();
+    }
+    let mesh = match model.to_sbm() {
+        Ok(mesh) => mesh,
+        Err(e) => {
+            println!("Error converting: {}", e);
+            process::exit(1);
+        }
+    };
+    if let Err(e) = object::save(files[1], &mesh) {
+        println!("Error writing '{}': {}", files[1], e);
+        process::exit(1);
+    }
+
+    println!("{} vertices, {} triangles", mesh.total_vertices(), mesh.index_count() / 3);
+    for (sub_object, group) in mesh.sub_objects().iter().zip(&model.groups) {
+        match group.material {
+            Some(ref material) => println!("  {}: {} triangles, material {}", group.name,
+                                           sub_object.count / 3, material),
+            None => println!("  {}: {} triangles", group.name, sub_object.count / 3)
+        }
+    }
+}
@@ -23,10 +23,10 @@
  */
 
 extern crate gl;
-#[macro_use]
 extern crate sb6;
 
 use gl::types::*;
+use std::env;
 use vmath::Mat4;
 
 mod vmath;
@@ -114,7 +114,10 @@ impl sb6::App for SampleApp {
             self.proj_location = sb6::program::get_uniform_location(
                 self.program, "proj_matrix").unwrap();
 
-            load_object_or_panic!(&mut self.object, "media/objects/bunny_1k.sbm");
+            // any model format can be given on the command line
+            let filename = env::args().nth(1).unwrap_or(String::from("media/objects/bunny_1k.sbm"));
+            self.object = sb6::mesh::load_object(&filename)
+                .unwrap_or_else(|e| panic!("Error loading '{}': {}", filename, e));
 
             gl::Enable(gl::DEPTH_TEST);
             gl::DepthFunc(gl::LEQUAL);
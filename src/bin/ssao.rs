@@ -28,6 +28,7 @@ extern crate rand;
 extern crate sb6;
 
 use gl::types::*;
+use std::env;
 use std::mem;
 use rand::{ Rng };
 
@@ -207,7 +208,10 @@ impl sb6::App for SampleApp {
             gl::BindVertexArray(self.quad_vao);
         }
 
-        load_object_or_panic!(&mut self.object, "media/objects/dragon.sbm");
+        // any model format can be given on the command line
+        let filename = env::args().nth(1).unwrap_or(String::from("media/objects/dragon.sbm"));
+        self.object = sb6::mesh::load_object(&filename)
+            .unwrap_or_else(|e| panic!("Error loading '{}': {}", filename, e));
         load_object_or_panic!(&mut self.cube, "media/objects/cube.sbm");
 
         unsafe {
@@ -0,0 +1,524 @@
+/*
+ * Copyright (c) 2014 Cameron Hart
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a
+ * copy of this software and associated documentation files (the "Software"),
+ * to deal in the Software without restriction, including without limitation
+ * the rights to use, copy, modify, merge, publish, distribute, sublicense,
+ * and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+ * THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+ * FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+ * DEALINGS IN THE SOFTWARE.
+ */
+
+extern crate gl;
+
+use decompress;
+use gl::types::*;
+use image::{Image, ImageError};
+use ktx;
+use ktx::{Header, KeyValueData, KtxImage, LoadError};
+use mipmap;
+use mipmap::Filter;
+use std::fmt;
+use std::io;
+
+/// The internal formats images can be converted to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Rgba8,
+    Srgb8Alpha8,
+    Rgba16f,
+    Rgb9E5
+}
+
+impl Format {
+    /// Parses a format name such as "rgba8" or "rgb9_e5"
+    pub fn parse(name: &str) -> Option<Format> {
+        match &name.to_lowercase()[..] {
+            "rgba8" => Some(Format::Rgba8),
+            "srgb8_alpha8" => Some(Format::Srgb8Alpha8),
+            "rgba16f" => Some(Format::Rgba16f),
+            "rgb9_e5" => Some(Format::Rgb9E5),
+            _ => None
+        }
+    }
+
+    /// Returns a header for a 2D texture of this format with the given size
+    pub fn header(&self, width: usize, height: usize) -> Header {
+        let (gl_type, gl_type_size, gl_format, gl_internal_format) = match *self {
+            Format::Rgba8 => (gl::UNSIGNED_BYTE, 1, gl::RGBA, gl::RGBA8),
+            Format::Srgb8Alpha8 => (gl::UNSIGNED_BYTE, 1, gl::RGBA, gl::SRGB8_ALPHA8),
+            Format::Rgba16f => (gl::HALF_FLOAT, 2, gl::RGBA, gl::RGBA16F),
+            Format::Rgb9E5 => (gl::UNSIGNED_INT_5_9_9_9_REV, 4, gl::RGB, gl::RGB9_E5)
+        };
+        Header {
+            gl_type: gl_type,
+            gl_type_size: gl_type_size,
+            gl_format: gl_format,
+            gl_internal_format: gl_internal_format,
+            gl_base_internal_format: gl_format,
+            pixel_width: width as i32,
+            pixel_height: height as i32,
+            .. Header::default()
+        }
+    }
+
+    pub fn pixel_size(&self) -> usize {
+        match *self {
+            Format::Rgba16f => 8,
+            _ => 4
+        }
+    }
+
+    /// Returns true if the format stores floating point values, which are
+    /// always linear
+    pub fn is_float(&self) -> bool {
+        match *self {
+            Format::Rgba16f | Format::Rgb9E5 => true,
+            _ => false
+        }
+    }
+
+    /// Encodes the image's pixels in this format. sRGB encoded images are
+    /// converted to linear for float formats and linear images are sRGB
+    /// encoded for `Srgb8Alpha8`.
+    pub fn encode(&self, image: &Image) -> Vec<u8> {
+        let mut out = Vec::with_capacity(image.width * image.height * self.pixel_size());
+        for p in image.pixels.chunks(4) {
+            let rgb = |f: fn(f32) -> f32| [f(p[0]), f(p[1]), f(p[2])];
+            match *self {
+                Format::Rgba8 | Format::Srgb8Alpha8 => {
+                    let c = if image.linear && *self == Format::Srgb8Alpha8 {
+                        rgb(linear_to_srgb)
+                    }
+                    else {
+                        [p[0], p[1], p[2]]
+                    };
+                    out.extend_from_slice(&[unorm8(c[0]), unorm8(c[1]), unorm8(c[2]),
+                        unorm8(p[3])]);
+                },
+                Format::Rgba16f | Format::Rgb9E5 => {
+                    let c = if image.linear { [p[0], p[1], p[2]] } else { rgb(srgb_to_linear) };
+                    if *self == Format::Rgba16f {
+                        for &v in &[c[0], c[1], c[2], p[3]] {
+                            let h = f32_to_f16(v);
+                            out.extend_from_slice(&[h as u8, (h >> 8) as u8]);
+                        }
+                    }
+                    else {
+                        let v = rgb9e5(c);
+                        out.extend_from_slice(&[v as u8, (v >> 8) as u8, (v >> 16) as u8,
+                            (v >> 24) as u8]);
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+fn unorm8(value: f32) -> u8 {
+    (value.max(0.0).min(1.0) * 255.0 + 0.5) as u8
+}
+
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    }
+    else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    }
+    else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Converts a float to a half float, rounding to nearest
+pub fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7fffff;
+    if exponent == 0xff {
+        // infinity or nan
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 }
+    }
+    let e = exponent - 127 + 15;
+    if e >= 0x1f {
+        return sign | 0x7c00
+    }
+    if e <= 0 {
+        // too small for a normal half, shift into a denormal
+        if e < -10 {
+            return sign
+        }
+        let m = mantissa | 0x800000;
+        let shift = (14 - e) as u32;
+        return sign | ((m >> shift) + ((m >> (shift - 1)) & 1)) as u16
+    }
+    // a carry out of the mantissa correctly bumps the exponent
+    sign | ((((e as u32) << 10) | (mantissa >> 13)) + ((mantissa >> 12) & 1)) as u16
+}
+
+/// Converts a half float to a float
+pub fn f16_to_f32(value: u16) -> f32 {
+    let sign = if value & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((value >> 10) & 0x1f) as i32;
+    let mantissa = (value & 0x3ff) as f32;
+    sign * match exponent {
+        0 => mantissa * 2.0f32.powi(-24),
+        0x1f if mantissa == 0.0 => ::std::f32::INFINITY,
+        0x1f => ::std::f32::NAN,
+        e => (1.0 + mantissa / 1024.0) * 2.0f32.powi(e - 15)
+    }
+}
+
+/// Packs a linear colour into the shared exponent format, following the
+/// EXT_texture_shared_exponent specification
+pub fn rgb9e5(rgb: [f32; 3]) -> u32 {
+    const MANTISSA_BITS: i32 = 9;
+    const BIAS: i32 = 15;
+    const MAX: f32 = 65408.0;
+    let clamp = |v: f32| if v > 0.0 { v.min(MAX) } else { 0.0 };
+    let (r, g, b) = (clamp(rgb[0]), clamp(rgb[1]), clamp(rgb[2]));
+    let max = r.max(g).max(b);
+    let mut exponent = ::std::cmp::max(-BIAS - 1, max.log2().floor() as i32) + 1 + BIAS;
+    let scale = |e: i32| 2.0f32.powi(e - BIAS - MANTISSA_BITS);
+    if (max / scale(exponent) + 0.5).floor() as i32 == 1 << MANTISSA_BITS {
+        exponent += 1;
+    }
+    let s = scale(exponent);
+    let m = |v: f32| (v / s + 0.5).floor() as u32;
+    m(r) | m(g) << 9 | m(b) << 18 | (exponent as u32) << 27
+}
+
+/// How the channels of an uncompressed format are stored
+pub struct PixelFormat {
+    pub channels: usize,
+    pub ty: GLenum,
+    pub pixel_size: usize,
+    /// True if the colour channels are sRGB encoded
+    pub srgb: bool,
+    /// The index of the alpha channel, if there is one
+    pub alpha: Option<usize>
+}
+
+/// Returns how the pixels of an uncompressed format are stored, or None if
+/// the format can't be converted to floats
+pub fn pixel_format(h: &Header) -> Option<PixelFormat> {
+    let (channels, alpha) = match h.gl_format {
+        gl::RED | gl::GREEN | gl::BLUE | gl::DEPTH_COMPONENT => (1, None),
+        gl::ALPHA => (1, Some(0)),
+        gl::RG => (2, None),
+        gl::RGB | gl::BGR => (3, None),
+        gl::RGBA | gl::BGRA => (4, Some(3)),
+        _ => return None
+    };
+    let pixel_size = match h.gl_type {
+        gl::UNSIGNED_BYTE => channels,
+        gl::UNSIGNED_SHORT | gl::HALF_FLOAT => channels * 2,
+        gl::FLOAT => channels * 4,
+        gl::UNSIGNED_INT_5_9_9_9_REV if channels == 3 => 4,
+        _ => return None
+    };
+    let srgb = match h.gl_internal_format {
+        gl::SRGB | gl::SRGB8 | gl::SRGB_ALPHA | gl::SRGB8_ALPHA8 => true,
+        _ => false
+    };
+    Some(PixelFormat {
+        channels: channels,
+        ty: h.gl_type,
+        pixel_size: pixel_size,
+        srgb: srgb,
+        alpha: alpha
+    })
+}
+
+fn read_u16(data: &[u8]) -> u16 {
+    (data[0] as u16) | (data[1] as u16) << 8
+}
+
+fn read_u32(data: &[u8]) -> u32 {
+    (data[0] as u32) | (data[1] as u32) << 8 | (data[2] as u32) << 16 |
+        (data[3] as u32) << 24
+}
+
+fn row_stride(format: &PixelFormat, width: usize) -> usize {
+    (format.pixel_size * width + 3) & !3
+}
+
+/// Unpacks an image with padded rows to floats, converting sRGB channels
+/// to linear
+pub fn decode_pixels(data: &[u8], format: &PixelFormat, size: [usize; 3]) -> Vec<f32> {
+    let stride = row_stride(format, size[0]);
+    let mut out = Vec::with_capacity(size[0] * size[1] * size[2] * format.channels);
+    for row in data.chunks(stride).take(size[1] * size[2]) {
+        for pixel in row[..size[0] * format.pixel_size].chunks(format.pixel_size) {
+            if format.ty == gl::UNSIGNED_INT_5_9_9_9_REV {
+                let v = read_u32(pixel);
+                let scale = 2.0f32.powi((v >> 27) as i32 - 24);
+                for c in 0..3 {
+                    out.push(((v >> (c * 9)) & 0x1ff) as f32 * scale);
+                }
+                continue
+            }
+            for c in 0..format.channels {
+                let v = match format.ty {
+                    gl::UNSIGNED_BYTE => pixel[c] as f32 / 255.0,
+                    gl::UNSIGNED_SHORT => read_u16(&pixel[c * 2..]) as f32 / 65535.0,
+                    gl::HALF_FLOAT => f16_to_f32(read_u16(&pixel[c * 2..])),
+                    _ => f32::from_bits(read_u32(&pixel[c * 4..]))
+                };
+                out.push(if format.srgb && format.alpha != Some(c) {
+                    srgb_to_linear(v)
+                }
+                else {
+                    v
+                });
+            }
+        }
+    }
+    out
+}
+
+/// Packs floats into an image with rows padded to four bytes, converting
+/// sRGB channels back from linear
+pub fn encode_pixels(values: &[f32], format: &PixelFormat, size: [usize; 3]) -> Vec<u8> {
+    let stride = row_stride(format, size[0]);
+    let mut out = Vec::with_capacity(stride * size[1] * size[2]);
+    let clamp = |v: f32| v.max(0.0).min(1.0);
+    for row in values.chunks(size[0] * format.channels) {
+        for pixel in row.chunks(format.channels) {
+            if format.ty == gl::UNSIGNED_INT_5_9_9_9_REV {
+                let v = rgb9e5([pixel[0], pixel[1], pixel[2]]);
+                out.extend_from_slice(&[v as u8, (v >> 8) as u8, (v >> 16) as u8,
+                    (v >> 24) as u8]);
+                continue
+            }
+            for (c, &v) in pixel.iter().enumerate() {
+                let v = if format.srgb && format.alpha != Some(c) {
+                    linear_to_srgb(clamp(v))
+                }
+                else {
+                    v
+                };
+                match format.ty {
+                    gl::UNSIGNED_BYTE => out.push((clamp(v) * 255.0 + 0.5) as u8),
+                    gl::UNSIGNED_SHORT => {
+                        let v = (clamp(v) * 65535.0 + 0.5) as u16;
+                        out.extend_from_slice(&[v as u8, (v >> 8) as u8]);
+                    },
+                    gl::HALF_FLOAT => {
+                        let v = f32_to_f16(v);
+                        out.extend_from_slice(&[v as u8, (v >> 8) as u8]);
+                    },
+                    _ => {
+                        let v = v.to_bits();
+                        out.extend_from_slice(&[v as u8, (v >> 8) as u8, (v >> 16) as u8,
+                            (v >> 24) as u8]);
+                    }
+                }
+            }
+        }
+        for _ in size[0] * format.pixel_size..stride {
+            out.push(0);
+        }
+    }
+    out
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Options {
+    pub format: Format,
+    /// Generate a full mip chain with the given filter
+    pub mipmaps: Option<Filter>
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            format: Format::Srgb8Alpha8,
+            mipmaps: None
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConvertError {
+    ImageError(ImageError),
+    KtxError(LoadError),
+    /// The source images can't form the requested texture
+    SizeError(String)
+}
+
+impl From<ImageError> for ConvertError {
+    fn from(e: ImageError) -> ConvertError {
+        ConvertError::ImageError(e)
+    }
+}
+
+impl From<io::Error> for ConvertError {
+    fn from(e: io::Error) -> ConvertError {
+        ConvertError::ImageError(ImageError::IoError(e))
+    }
+}
+
+impl From<LoadError> for ConvertError {
+    fn from(e: LoadError) -> ConvertError {
+        ConvertError::KtxError(e)
+    }
+}
+
+impl fmt::Display for ConvertError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &ConvertError::ImageError(ref e) => e.fmt(fmt),
+            &ConvertError::KtxError(ref e) => e.fmt(fmt),
+            &ConvertError::SizeError(ref s) => write!(fmt, "{}", s)
+        }
+    }
+}
+
+/// Builds a texture from images that make up its base level, stored one
+/// after another, then generates the rest of the mip chain if requested.
+/// The header must describe the images in `options.format`.
+pub fn texture(header: Header, key_values: KeyValueData, images: &[Image], options: &Options)
+               -> Result<KtxImage, ConvertError> {
+    let mut data = Vec::new();
+    for image in images {
+        data.extend(options.format.encode(image));
+    }
+    let base = try!(KtxImage::new(header, key_values, vec![data]));
+    match options.mipmaps {
+        Some(filter) => Ok(try!(mipmap::generate(&base, filter))),
+        None => Ok(base)
+    }
+}
+
+/// Converts an image to a 2D texture. The rows are kept top to bottom and
+/// marked with a `KTXorientation` of `S=r,T=d`.
+pub fn texture_2d(image: &Image, options: &Options) -> Result<KtxImage, ConvertError> {
+    let mut header = options.format.header(image.width, image.height);
+    header.faces = 1;
+    header.mip_levels = 1;
+    let mut key_values = KeyValueData::new();
+    key_values.insert_str(ktx::KEY_ORIENTATION, "S=r,T=d");
+    texture(header, key_values, &[image.clone()], options)
+}
+
+/// Converts images of the same size to a 2D array texture, one layer per
+/// image. The rows are kept top to bottom as with `texture_2d`.
+pub fn texture_2d_array(layers: &[Image], options: &Options) -> Result<KtxImage, ConvertError> {
+    if layers.is_empty() {
+        return Err(ConvertError::SizeError(String::from("an array texture needs a layer")))
+    }
+    let (width, height) = (layers[0].width, layers[0].height);
+    if layers.iter().any(|l| l.width != width || l.height != height) {
+        return Err(ConvertError::SizeError(String::from(
+            "array texture layers must be the same size")))
+    }
+    let mut header = options.format.header(width, height);
+    header.array_elements = layers.len() as i32;
+    header.faces = 1;
+    header.mip_levels = 1;
+    let mut key_values = KeyValueData::new();
+    key_values.insert_str(ktx::KEY_ORIENTATION, "S=r,T=d");
+    texture(header, key_values, layers, options)
+}
+
+/// Converts six square images of the same size to a cube map, in the order
+/// +X, -X, +Y, -Y, +Z, -Z
+pub fn cube_map(faces: &[Image], options: &Options) -> Result<KtxImage, ConvertError> {
+    if faces.len() != 6 {
+        return Err(ConvertError::SizeError(format!("a cube map needs 6 faces, got {}",
+            faces.len())))
+    }
+    let size = faces[0].width;
+    if faces.iter().any(|f| f.width != size || f.height != size) {
+        return Err(ConvertError::SizeError(String::from(
+            "cube map faces must be square and the same size")))
+    }
+    let mut header = options.format.header(size, size);
+    header.faces = 6;
+    header.mip_levels = 1;
+    texture(header, KeyValueData::new(), faces, options)
+}
+
+/// Decodes every array layer and cube face of a mip level to images, with
+/// the first row at the top. Compressed formats are decompressed first.
+pub fn ktx_images(image: &KtxImage, level: usize) -> Result<Vec<Image>, ConvertError> {
+    if image.header().is_compressed() {
+        return match decompress::decompress(image) {
+            Some(decompressed) => ktx_images(&decompressed, level),
+            None => Err(ConvertError::KtxError(LoadError::UnsupportedError(format!(
+                "compressed format 0x{:x}", image.header().gl_internal_format))))
+        }
+    }
+    let h = image.header();
+    let format = match pixel_format(h) {
+        Some(f) => f,
+        None => return Err(ConvertError::KtxError(LoadError::UnsupportedError(format!(
+            "conversion from format 0x{:x}", h.gl_internal_format))))
+    };
+    let (width, height, depth) = image.dimensions(level);
+    let size = [width as usize, ::std::cmp::max(height, 1) as usize,
+                ::std::cmp::max(depth, 1) as usize];
+    // 2D images are stored bottom to top unless marked otherwise, cube
+    // faces are always top to bottom
+    let flip = height > 0 && depth == 0 && h.num_faces() == 1 &&
+        image.key_values().orientation().map_or(true, |o| o.t != ktx::Direction::Down);
+    let linear = format.srgb || h.gl_type == gl::HALF_FLOAT || h.gl_type == gl::FLOAT ||
+        h.gl_type == gl::UNSIGNED_INT_5_9_9_9_REV;
+    let bgr = h.gl_format == gl::BGR || h.gl_format == gl::BGRA;
+
+    // the header's type size may not match its type
+    let needed = row_stride(&format, size[0]).checked_mul(size[1] * size[2]);
+
+    let mut images = Vec::with_capacity(h.num_layers() * h.num_faces());
+    for layer in 0..h.num_layers() {
+        for face in 0..h.num_faces() {
+            let data = image.image(level, layer, face);
+            if needed.map_or(true, |n| data.len() < n) {
+                return Err(ConvertError::SizeError(format!(
+                    "mip level {} holds {} bytes per image, too few for its size",
+                    level, data.len())))
+            }
+            let values = decode_pixels(data, &format, size);
+            let mut out = Image::new(size[0], size[1] * size[2], linear);
+            for (i, p) in values.chunks(format.channels).enumerate() {
+                // expand to RGBA the way OpenGL does
+                let mut rgba = [0.0, 0.0, 0.0, 1.0];
+                match format.alpha {
+                    Some(0) => rgba[3] = p[0],
+                    _ => rgba[..p.len()].copy_from_slice(p)
+                }
+                if bgr {
+                    rgba.swap(0, 2);
+                }
+                out.pixels[i * 4..i * 4 + 4].copy_from_slice(&rgba);
+            }
+            if flip {
+                out.flip_vertical();
+            }
+            images.push(out);
+        }
+    }
+    Ok(images)
+}
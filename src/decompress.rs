@@ -0,0 +1,513 @@
+/*
+ * Copyright (c) 2014 Cameron Hart
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a
+ * copy of this software and associated documentation files (the "Software"),
+ * to deal in the Software without restriction, including without limitation
+ * the rights to use, copy, modify, merge, publish, distribute, sublicense,
+ * and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+ * THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+ * FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+ * DEALINGS IN THE SOFTWARE.
+ */
+
+extern crate gl;
+
+use gl::types::*;
+use ktx;
+use ktx::{Header, KtxImage};
+use std::cmp;
+
+type Block = [[u8; 4]; 16];
+
+const ETC_MODIFIERS: [[i32; 4]; 8] = [
+    [2, 8, -2, -8],
+    [5, 17, -5, -17],
+    [9, 29, -9, -29],
+    [13, 42, -13, -42],
+    [18, 60, -18, -60],
+    [24, 80, -24, -80],
+    [33, 106, -33, -106],
+    [47, 183, -47, -183] ];
+
+const ETC_DISTANCES: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+
+const EAC_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8] ];
+
+fn clamp(value: i32) -> u8 {
+    cmp::min(cmp::max(value, 0), 255) as u8
+}
+
+fn extend_bits(value: u32, bits: u32) -> u8 {
+    ((value << (8 - bits)) | (value >> (2 * bits - 8))) as u8
+}
+
+fn read_u16(data: &[u8]) -> u32 {
+    data[0] as u32 | (data[1] as u32) << 8
+}
+
+fn read_u32(data: &[u8]) -> u32 {
+    read_u16(data) | read_u16(&data[2..]) << 16
+}
+
+fn rgb565(color: u32) -> [i32; 3] {
+    [extend_bits(color >> 11, 5) as i32,
+     extend_bits((color >> 5) & 0x3f, 6) as i32,
+     extend_bits(color & 0x1f, 5) as i32]
+}
+
+/// Decodes the colour half of a BC1, BC2 or BC3 block. Only BC1 blocks
+/// support the three colour plus transparent black mode.
+fn decode_bc1_colors(data: &[u8], out: &mut Block, bc1: bool) {
+    let c0 = read_u16(data);
+    let c1 = read_u16(&data[2..]);
+    let indices = read_u32(&data[4..]);
+    let (a, b) = (rgb565(c0), rgb565(c1));
+    let mut palette = [[0u8; 4]; 4];
+    for i in 0..3 {
+        palette[0][i] = a[i] as u8;
+        palette[1][i] = b[i] as u8;
+        if c0 > c1 || !bc1 {
+            palette[2][i] = ((2 * a[i] + b[i]) / 3) as u8;
+            palette[3][i] = ((a[i] + 2 * b[i]) / 3) as u8;
+        }
+        else {
+            palette[2][i] = ((a[i] + b[i]) / 2) as u8;
+            palette[3][i] = 0;
+        }
+    }
+    palette[0][3] = 255;
+    palette[1][3] = 255;
+    palette[2][3] = 255;
+    palette[3][3] = if c0 > c1 || !bc1 { 255 } else { 0 };
+    for i in 0..16 {
+        out[i] = palette[((indices >> (2 * i)) & 3) as usize];
+    }
+}
+
+/// Decodes a BC4 style block of eight bit values into the given channel
+fn decode_bc4_channel(data: &[u8], out: &mut Block, channel: usize, signed: bool) {
+    let mut palette = [0i32; 8];
+    let (a, b) = if signed {
+        // -128 is treated the same as -127
+        (cmp::max(data[0] as i8 as i32, -127), cmp::max(data[1] as i8 as i32, -127))
+    }
+    else {
+        (data[0] as i32, data[1] as i32)
+    };
+    palette[0] = a;
+    palette[1] = b;
+    if a > b {
+        for i in 1..7 {
+            palette[i + 1] = ((7 - i as i32) * a + i as i32 * b) / 7;
+        }
+    }
+    else {
+        for i in 1..5 {
+            palette[i + 1] = ((5 - i as i32) * a + i as i32 * b) / 5;
+        }
+        palette[6] = if signed { -127 } else { 0 };
+        palette[7] = if signed { 127 } else { 255 };
+    }
+    let mut bits = 0u64;
+    for i in 0..6 {
+        bits |= (data[2 + i] as u64) << (8 * i);
+    }
+    for i in 0..16 {
+        let value = palette[((bits >> (3 * i)) & 7) as usize];
+        out[i][channel] = value as u8;
+    }
+}
+
+/// Decodes a BC2 explicit alpha block
+fn decode_bc2_alpha(data: &[u8], out: &mut Block) {
+    for i in 0..16 {
+        let alpha = (data[i / 2] >> (4 * (i % 2))) & 0xf;
+        out[i][3] = alpha * 17;
+    }
+}
+
+/// Decodes an ETC1 or ETC2 RGB block. Punch-through alpha blocks are always
+/// differential and use the differential bit to mark the block as opaque,
+/// pixels with index 2 in other blocks are transparent black.
+fn decode_etc2_rgb(data: &[u8], out: &mut Block, punchthrough: bool) {
+    let (b0, b1, b2, b3) = (data[0] as u32, data[1] as u32, data[2] as u32,
+                            data[3] as u32);
+    let opaque = !punchthrough || b3 & 2 != 0;
+    let mut colors = [[0i32; 3]; 2];
+
+    if !punchthrough && b3 & 2 == 0 {
+        // individual mode
+        colors[0] = [extend_bits(b0 >> 4, 4) as i32, extend_bits(b1 >> 4, 4) as i32,
+                     extend_bits(b2 >> 4, 4) as i32];
+        colors[1] = [extend_bits(b0 & 0xf, 4) as i32, extend_bits(b1 & 0xf, 4) as i32,
+                     extend_bits(b2 & 0xf, 4) as i32];
+    }
+    else {
+        // differential mode, overflowing the second colour selects one of
+        // the ETC2 modes
+        let base = [(b0 >> 3) as i32, (b1 >> 3) as i32, (b2 >> 3) as i32];
+        let delta = |b: u32| -> i32 { ((b & 7) as i32) - (((b & 4) as i32) << 1) };
+        let second = [base[0] + delta(b0), base[1] + delta(b1), base[2] + delta(b2)];
+        if second[0] < 0 || second[0] > 31 {
+            decode_etc2_t(data, out, opaque);
+            return
+        }
+        if second[1] < 0 || second[1] > 31 {
+            decode_etc2_h(data, out, opaque);
+            return
+        }
+        if second[2] < 0 || second[2] > 31 {
+            decode_etc2_planar(data, out);
+            return
+        }
+        for i in 0..3 {
+            colors[0][i] = extend_bits(base[i] as u32, 5) as i32;
+            colors[1][i] = extend_bits(second[i] as u32, 5) as i32;
+        }
+    }
+
+    let tables = [(b3 >> 5) as usize, ((b3 >> 2) & 7) as usize];
+    let flip = b3 & 1 != 0;
+    for x in 0..4 {
+        for y in 0..4 {
+            let i = x * 4 + y;
+            let sub_block = if flip { y / 2 } else { x / 2 };
+            let index = etc_index(data, i);
+            if !opaque && index == 2 {
+                out[y * 4 + x] = [0; 4];
+                continue
+            }
+            // transparent blocks have no modifier for index 0
+            let modifier = if !opaque && index == 0 {
+                0
+            }
+            else {
+                ETC_MODIFIERS[tables[sub_block]][index]
+            };
+            let color = colors[sub_block];
+            out[y * 4 + x] = [clamp(color[0] + modifier), clamp(color[1] + modifier),
+                              clamp(color[2] + modifier), 255];
+        }
+    }
+}
+
+/// Returns the two bit pixel index of an ETC block, pixels are numbered
+/// column first
+fn etc_index(data: &[u8], i: usize) -> usize {
+    let msb = (data[5 - i / 8] >> (i % 8)) & 1;
+    let lsb = (data[7 - i / 8] >> (i % 8)) & 1;
+    (msb << 1 | lsb) as usize
+}
+
+fn etc2_paint(data: &[u8], out: &mut Block, paint: &[[i32; 3]; 4], opaque: bool) {
+    for x in 0..4 {
+        for y in 0..4 {
+            let index = etc_index(data, x * 4 + y);
+            let c = paint[index];
+            out[y * 4 + x] = if !opaque && index == 2 {
+                [0; 4]
+            }
+            else {
+                [clamp(c[0]), clamp(c[1]), clamp(c[2]), 255]
+            };
+        }
+    }
+}
+
+fn decode_etc2_t(data: &[u8], out: &mut Block, opaque: bool) {
+    let (b0, b1, b2, b3) = (data[0] as u32, data[1] as u32, data[2] as u32,
+                            data[3] as u32);
+    let c0 = [extend_bits(((b0 >> 3) & 3) << 2 | (b0 & 3), 4) as i32,
+              extend_bits(b1 >> 4, 4) as i32, extend_bits(b1 & 0xf, 4) as i32];
+    let c1 = [extend_bits(b2 >> 4, 4) as i32, extend_bits(b2 & 0xf, 4) as i32,
+              extend_bits(b3 >> 4, 4) as i32];
+    let d = ETC_DISTANCES[((((b3 >> 2) & 3) << 1) | (b3 & 1)) as usize];
+    let paint = [c0,
+                 [c1[0] + d, c1[1] + d, c1[2] + d],
+                 c1,
+                 [c1[0] - d, c1[1] - d, c1[2] - d]];
+    etc2_paint(data, out, &paint, opaque);
+}
+
+fn decode_etc2_h(data: &[u8], out: &mut Block, opaque: bool) {
+    let (b0, b1, b2, b3) = (data[0] as u32, data[1] as u32, data[2] as u32,
+                            data[3] as u32);
+    let r0 = (b0 >> 3) & 0xf;
+    let g0 = ((b0 & 7) << 1) | ((b1 >> 4) & 1);
+    let bl0 = (b1 & 8) | ((b1 & 3) << 1) | (b2 >> 7);
+    let r1 = (b2 >> 3) & 0xf;
+    let g1 = ((b2 & 7) << 1) | (b3 >> 7);
+    let bl1 = (b3 >> 3) & 0xf;
+    let order = if (r0 << 8 | g0 << 4 | bl0) >= (r1 << 8 | g1 << 4 | bl1) { 1 } else { 0 };
+    let d = ETC_DISTANCES[(((b3 >> 2) & 1) << 2 | (b3 & 1) << 1 | order) as usize];
+    let c0 = [extend_bits(r0, 4) as i32, extend_bits(g0, 4) as i32, extend_bits(bl0, 4) as i32];
+    let c1 = [extend_bits(r1, 4) as i32, extend_bits(g1, 4) as i32, extend_bits(bl1, 4) as i32];
+    let paint = [[c0[0] + d, c0[1] + d, c0[2] + d],
+                 [c0[0] - d, c0[1] - d, c0[2] - d],
+                 [c1[0] + d, c1[1] + d, c1[2] + d],
+                 [c1[0] - d, c1[1] - d, c1[2] - d]];
+    etc2_paint(data, out, &paint, opaque);
+}
+
+fn decode_etc2_planar(data: &[u8], out: &mut Block) {
+    let (b0, b1, b2, b3) = (data[0] as u32, data[1] as u32, data[2] as u32,
+                            data[3] as u32);
+    let w = (data[4] as u32) << 24 | (data[5] as u32) << 16 |
+        (data[6] as u32) << 8 | data[7] as u32;
+    let o = [extend_bits((b0 >> 1) & 0x3f, 6) as i32,
+             extend_bits(((b0 & 1) << 6) | ((b1 >> 1) & 0x3f), 7) as i32,
+             extend_bits(((b1 & 1) << 5) | (((b2 >> 3) & 3) << 3) |
+                         ((b2 & 3) << 1) | (b3 >> 7), 6) as i32];
+    let h = [extend_bits((((b3 >> 2) & 0x1f) << 1) | (b3 & 1), 6) as i32,
+             extend_bits((w >> 25) & 0x7f, 7) as i32,
+             extend_bits((w >> 19) & 0x3f, 6) as i32];
+    let v = [extend_bits((w >> 13) & 0x3f, 6) as i32,
+             extend_bits((w >> 6) & 0x7f, 7) as i32,
+             extend_bits(w & 0x3f, 6) as i32];
+    for y in 0..4 {
+        for x in 0..4 {
+            let mut c = [0u8; 4];
+            for i in 0..3 {
+                c[i] = clamp((x as i32 * (h[i] - o[i]) + y as i32 * (v[i] - o[i]) +
+                              4 * o[i] + 2) >> 2);
+            }
+            c[3] = 255;
+            out[y * 4 + x] = c;
+        }
+    }
+}
+
+/// Decodes an EAC block of eight bit values into the given channel
+fn decode_eac_channel(data: &[u8], out: &mut Block, channel: usize) {
+    let base = data[0] as i32;
+    let multiplier = (data[1] >> 4) as i32;
+    let table = &EAC_MODIFIERS[(data[1] & 0xf) as usize];
+    let mut bits = 0u64;
+    for i in 0..6 {
+        bits = bits << 8 | data[2 + i] as u64;
+    }
+    for x in 0..4 {
+        for y in 0..4 {
+            let i = x * 4 + y;
+            let index = ((bits >> (45 - 3 * i)) & 7) as usize;
+            out[y * 4 + x][channel] = clamp(base + table[index] * multiplier);
+        }
+    }
+}
+
+/// Returns the block size in bytes and a function that decodes a block of
+/// the given compressed format to RGBA8
+fn block_decoder(internal_format: GLenum) -> Option<(usize, fn(&[u8], &mut Block))> {
+    fn bc1(data: &[u8], out: &mut Block) {
+        decode_bc1_colors(data, out, true);
+    }
+    fn bc1_opaque(data: &[u8], out: &mut Block) {
+        decode_bc1_colors(data, out, true);
+        for pixel in out.iter_mut() {
+            pixel[3] = 255;
+        }
+    }
+    fn bc2(data: &[u8], out: &mut Block) {
+        decode_bc1_colors(&data[8..], out, false);
+        decode_bc2_alpha(data, out);
+    }
+    fn bc3(data: &[u8], out: &mut Block) {
+        decode_bc1_colors(&data[8..], out, false);
+        decode_bc4_channel(data, out, 3, false);
+    }
+    fn bc4(data: &[u8], out: &mut Block) {
+        *out = [[0, 0, 0, 255]; 16];
+        decode_bc4_channel(data, out, 0, false);
+    }
+    fn bc4_signed(data: &[u8], out: &mut Block) {
+        *out = [[0, 0, 0, 127]; 16];
+        decode_bc4_channel(data, out, 0, true);
+    }
+    fn bc5(data: &[u8], out: &mut Block) {
+        *out = [[0, 0, 0, 255]; 16];
+        decode_bc4_channel(data, out, 0, false);
+        decode_bc4_channel(&data[8..], out, 1, false);
+    }
+    fn bc5_signed(data: &[u8], out: &mut Block) {
+        *out = [[0, 0, 0, 127]; 16];
+        decode_bc4_channel(data, out, 0, true);
+        decode_bc4_channel(&data[8..], out, 1, true);
+    }
+    fn etc2_rgb(data: &[u8], out: &mut Block) {
+        decode_etc2_rgb(data, out, false);
+    }
+    fn etc2_punchthrough(data: &[u8], out: &mut Block) {
+        decode_etc2_rgb(data, out, true);
+    }
+    fn etc2_rgba(data: &[u8], out: &mut Block) {
+        decode_etc2_rgb(&data[8..], out, false);
+        decode_eac_channel(data, out, 3);
+    }
+
+    match internal_format {
+        ktx::COMPRESSED_RGB_S3TC_DXT1_EXT |
+        ktx::COMPRESSED_SRGB_S3TC_DXT1_EXT => Some((8, bc1_opaque)),
+        ktx::COMPRESSED_RGBA_S3TC_DXT1_EXT |
+        ktx::COMPRESSED_SRGB_ALPHA_S3TC_DXT1_EXT => Some((8, bc1)),
+        ktx::COMPRESSED_RGBA_S3TC_DXT3_EXT |
+        ktx::COMPRESSED_SRGB_ALPHA_S3TC_DXT3_EXT => Some((16, bc2)),
+        ktx::COMPRESSED_RGBA_S3TC_DXT5_EXT |
+        ktx::COMPRESSED_SRGB_ALPHA_S3TC_DXT5_EXT => Some((16, bc3)),
+        gl::COMPRESSED_RED_RGTC1 => Some((8, bc4)),
+        gl::COMPRESSED_SIGNED_RED_RGTC1 => Some((8, bc4_signed)),
+        gl::COMPRESSED_RG_RGTC2 => Some((16, bc5)),
+        gl::COMPRESSED_SIGNED_RG_RGTC2 => Some((16, bc5_signed)),
+        ktx::COMPRESSED_ETC1_RGB8_OES |
+        gl::COMPRESSED_RGB8_ETC2 |
+        gl::COMPRESSED_SRGB8_ETC2 => Some((8, etc2_rgb)),
+        gl::COMPRESSED_RGB8_PUNCHTHROUGH_ALPHA1_ETC2 |
+        gl::COMPRESSED_SRGB8_PUNCHTHROUGH_ALPHA1_ETC2 => Some((8, etc2_punchthrough)),
+        gl::COMPRESSED_RGBA8_ETC2_EAC |
+        gl::COMPRESSED_SRGB8_ALPHA8_ETC2_EAC => Some((16, etc2_rgba)),
+        _ => None
+    }
+}
+
+/// Returns the uncompressed internal format and type that a decompressed
+/// image should use
+fn decompressed_format(internal_format: GLenum) -> (GLenum, GLenum) {
+    match internal_format {
+        ktx::COMPRESSED_SRGB_S3TC_DXT1_EXT |
+        ktx::COMPRESSED_SRGB_ALPHA_S3TC_DXT1_EXT |
+        ktx::COMPRESSED_SRGB_ALPHA_S3TC_DXT3_EXT |
+        ktx::COMPRESSED_SRGB_ALPHA_S3TC_DXT5_EXT |
+        gl::COMPRESSED_SRGB8_ETC2 |
+        gl::COMPRESSED_SRGB8_PUNCHTHROUGH_ALPHA1_ETC2 |
+        gl::COMPRESSED_SRGB8_ALPHA8_ETC2_EAC => (gl::SRGB8_ALPHA8, gl::UNSIGNED_BYTE),
+        gl::COMPRESSED_SIGNED_RED_RGTC1 |
+        gl::COMPRESSED_SIGNED_RG_RGTC2 => (gl::RGBA8_SNORM, gl::BYTE),
+        _ => (gl::RGBA8, gl::UNSIGNED_BYTE)
+    }
+}
+
+/// The compressed formats that can be decompressed
+pub const FORMATS: [GLenum; 19] = [
+    ktx::COMPRESSED_RGB_S3TC_DXT1_EXT, ktx::COMPRESSED_RGBA_S3TC_DXT1_EXT,
+    ktx::COMPRESSED_RGBA_S3TC_DXT3_EXT, ktx::COMPRESSED_RGBA_S3TC_DXT5_EXT,
+    ktx::COMPRESSED_SRGB_S3TC_DXT1_EXT, ktx::COMPRESSED_SRGB_ALPHA_S3TC_DXT1_EXT,
+    ktx::COMPRESSED_SRGB_ALPHA_S3TC_DXT3_EXT, ktx::COMPRESSED_SRGB_ALPHA_S3TC_DXT5_EXT,
+    gl::COMPRESSED_RED_RGTC1, gl::COMPRESSED_SIGNED_RED_RGTC1,
+    gl::COMPRESSED_RG_RGTC2, gl::COMPRESSED_SIGNED_RG_RGTC2,
+    ktx::COMPRESSED_ETC1_RGB8_OES, gl::COMPRESSED_RGB8_ETC2, gl::COMPRESSED_SRGB8_ETC2,
+    gl::COMPRESSED_RGB8_PUNCHTHROUGH_ALPHA1_ETC2,
+    gl::COMPRESSED_SRGB8_PUNCHTHROUGH_ALPHA1_ETC2,
+    gl::COMPRESSED_RGBA8_ETC2_EAC, gl::COMPRESSED_SRGB8_ALPHA8_ETC2_EAC ];
+
+/// Returns true if the given compressed format can be decompressed
+pub fn is_supported(internal_format: GLenum) -> bool {
+    block_decoder(internal_format).is_some()
+}
+
+/// Decompresses `depth` slices of compressed data to tightly packed RGBA8
+/// pixels. Returns None if the format is not supported or the data is too
+/// short.
+pub fn decompress_image(internal_format: GLenum, width: usize, height: usize,
+                        depth: usize, data: &[u8]) -> Option<Vec<u8>> {
+    let (block_size, decode) = match block_decoder(internal_format) {
+        Some(d) => d,
+        None => return None
+    };
+    let blocks_x = (width + 3) / 4;
+    let blocks_y = (height + 3) / 4;
+    if data.len() < blocks_x * blocks_y * depth * block_size {
+        return None
+    }
+    let mut out = vec![0u8; width * height * depth * 4];
+    let mut block = [[0u8; 4]; 16];
+    let mut blocks = data.chunks(block_size);
+    for z in 0..depth {
+        for by in 0..blocks_y {
+            for bx in 0..blocks_x {
+                decode(blocks.next().unwrap(), &mut block);
+                // copy the pixels that fall inside the image
+                for y in 0..cmp::min(4, height - by * 4) {
+                    for x in 0..cmp::min(4, width - bx * 4) {
+                        let offset = (((z * height) + by * 4 + y) * width + bx * 4 + x) * 4;
+                        out[offset..offset + 4].clone_from_slice(&block[y * 4 + x]);
+                    }
+                }
+            }
+        }
+    }
+    Some(out)
+}
+
+/// Decompresses every array layer and cube face of the given mip level to
+/// RGBA8
+pub fn decompress_level(image: &KtxImage, level: usize) -> Option<Vec<u8>> {
+    let h = image.header();
+    let (width, height, depth) = image.dimensions(level);
+    let (width, height, depth) = (cmp::max(width, 1) as usize,
+                                  cmp::max(height, 1) as usize,
+                                  cmp::max(depth, 1) as usize);
+    let mut out = Vec::new();
+    for layer in 0..h.num_layers() {
+        for face in 0..h.num_faces() {
+            match decompress_image(h.gl_internal_format, width, height, depth,
+                                   image.image(level, layer, face)) {
+                Some(pixels) => out.extend(pixels.into_iter()),
+                None => return None
+            }
+        }
+    }
+    Some(out)
+}
+
+/// Decompresses a compressed image to an equivalent RGBA8 image. The key
+/// value data is kept.
+pub fn decompress(image: &KtxImage) -> Option<KtxImage> {
+    let h = image.header();
+    if !h.is_compressed() {
+        return None
+    }
+    let (internal_format, ty) = decompressed_format(h.gl_internal_format);
+    let header = Header {
+        gl_type: ty,
+        gl_type_size: 1,
+        gl_format: gl::RGBA,
+        gl_internal_format: internal_format,
+        gl_base_internal_format: gl::RGBA,
+        key_pair_bytes: 0,
+        .. *h
+    };
+    let mut levels = Vec::with_capacity(image.num_levels());
+    for level in 0..image.num_levels() {
+        match decompress_level(image, level) {
+            Some(pixels) => levels.push(pixels),
+            None => return None
+        }
+    }
+    KtxImage::new(header, image.key_values().clone(), levels).ok()
+}
@@ -0,0 +1,408 @@
+/*
+ * Copyright (c) 2014 Cameron Hart
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a
+ * copy of this software and associated documentation files (the "Software"),
+ * to deal in the Software without restriction, including without limitation
+ * the rights to use, copy, modify, merge, publish, distribute, sublicense,
+ * and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+ * THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+ * FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+ * DEALINGS IN THE SOFTWARE.
+ */
+
+use convert;
+use convert::{ConvertError, Format};
+use image::Image;
+use ktx;
+use ktx::{KeyValueData, KtxImage};
+use std::cmp;
+use std::f32::consts::PI;
+
+/// The ways an environment can be stored in a single 2D image
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Projection {
+    /// Longitude along the horizontal axis and latitude along the vertical,
+    /// with +Z in the centre and +X three quarters of the way across
+    Equirectangular,
+    /// The environment reflected in a mirrored sphere seen from +Z, as used
+    /// by the `envmapsphere` sample
+    SphereMap
+}
+
+fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let len = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    [v[0] / len, v[1] / len, v[2] / len]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+/// Returns the image coordinates in [0, 1] of a direction, with v = 0 at
+/// the top of the image
+pub fn direction_to_uv(projection: Projection, dir: [f32; 3]) -> (f32, f32) {
+    let d = normalize(dir);
+    match projection {
+        Projection::Equirectangular => {
+            (0.5 + d[0].atan2(d[2]) / (2.0 * PI),
+             0.5 - d[1].max(-1.0).min(1.0).asin() / PI)
+        },
+        Projection::SphereMap => {
+            let m = 0.5 / (d[0] * d[0] + d[1] * d[1] + (d[2] + 1.0) * (d[2] + 1.0))
+                .sqrt().max(1.0e-6);
+            (0.5 + d[0] * m, 0.5 - d[1] * m)
+        }
+    }
+}
+
+/// Returns the direction of a point on the image, or None for sphere map
+/// points outside the sphere
+pub fn uv_to_direction(projection: Projection, u: f32, v: f32) -> Option<[f32; 3]> {
+    match projection {
+        Projection::Equirectangular => {
+            let phi = (u - 0.5) * 2.0 * PI;
+            let theta = (0.5 - v) * PI;
+            Some([theta.cos() * phi.sin(), theta.sin(), theta.cos() * phi.cos()])
+        },
+        Projection::SphereMap => {
+            // reflect the view direction about the sphere's normal
+            let x = u * 2.0 - 1.0;
+            let y = 1.0 - v * 2.0;
+            let r2 = x * x + y * y;
+            if r2 > 1.0 {
+                return None
+            }
+            let z = (1.0 - r2).sqrt();
+            Some([2.0 * z * x, 2.0 * z * y, 2.0 * z * z - 1.0])
+        }
+    }
+}
+
+/// Returns the direction through the centre of a cube map face texel, with
+/// faces in the order +X, -X, +Y, -Y, +Z, -Z
+pub fn cube_direction(face: usize, s: f32, t: f32) -> [f32; 3] {
+    let sc = s * 2.0 - 1.0;
+    let tc = t * 2.0 - 1.0;
+    normalize(match face {
+        0 => [1.0, -tc, -sc],
+        1 => [-1.0, -tc, sc],
+        2 => [sc, 1.0, tc],
+        3 => [sc, -1.0, -tc],
+        4 => [sc, -tc, 1.0],
+        _ => [-sc, -tc, -1.0]
+    })
+}
+
+/// Returns the cube map face and face coordinates a direction points at
+pub fn cube_face(dir: [f32; 3]) -> (usize, f32, f32) {
+    let (x, y, z) = (dir[0], dir[1], dir[2]);
+    let (ax, ay, az) = (x.abs(), y.abs(), z.abs());
+    let (face, sc, tc, ma) = if ax >= ay && ax >= az {
+        if x > 0.0 { (0, -z, -y, ax) } else { (1, z, -y, ax) }
+    }
+    else if ay >= az {
+        if y > 0.0 { (2, x, z, ay) } else { (3, x, -z, ay) }
+    }
+    else {
+        if z > 0.0 { (4, x, -y, az) } else { (5, -x, -y, az) }
+    };
+    (face, (sc / ma + 1.0) * 0.5, (tc / ma + 1.0) * 0.5)
+}
+
+/// Bilinearly samples an image at coordinates in [0, 1], wrapping
+/// horizontally if `wrap` is set and clamping otherwise
+pub fn sample(image: &Image, u: f32, v: f32, wrap: bool) -> [f32; 4] {
+    let x = u * image.width as f32 - 0.5;
+    let y = v * image.height as f32 - 0.5;
+    let x0 = x.floor();
+    let y0 = y.floor();
+    let fx = x - x0;
+    let fy = y - y0;
+    let w = image.width as isize;
+    let h = image.height as isize;
+    let column = |x: isize| if wrap {
+        ((x % w + w) % w) as usize
+    }
+    else {
+        cmp::max(0, cmp::min(x, w - 1)) as usize
+    };
+    let row = |y: isize| cmp::max(0, cmp::min(y, h - 1)) as usize;
+    let (x0, y0) = (x0 as isize, y0 as isize);
+    let p00 = image.get(column(x0), row(y0));
+    let p10 = image.get(column(x0 + 1), row(y0));
+    let p01 = image.get(column(x0), row(y0 + 1));
+    let p11 = image.get(column(x0 + 1), row(y0 + 1));
+    let mut out = [0.0; 4];
+    for c in 0..4 {
+        let top = p00[c] + (p10[c] - p00[c]) * fx;
+        let bottom = p01[c] + (p11[c] - p01[c]) * fx;
+        out[c] = top + (bottom - top) * fy;
+    }
+    out
+}
+
+/// Bilinearly samples a cube map in the given direction
+pub fn sample_cube(faces: &[Image], dir: [f32; 3]) -> [f32; 4] {
+    let (face, s, t) = cube_face(dir);
+    sample(&faces[face], s, t, false)
+}
+
+/// Resamples a 2D environment map to six cube map faces of the given size
+pub fn to_cube(src: &Image, projection: Projection, size: usize) -> Vec<Image> {
+    let wrap = projection == Projection::Equirectangular;
+    (0..6).map(|face| {
+        let mut out = Image::new(size, size, src.linear);
+        for y in 0..size {
+            for x in 0..size {
+                let dir = cube_direction(face, (x as f32 + 0.5) / size as f32,
+                                         (y as f32 + 0.5) / size as f32);
+                let (u, v) = direction_to_uv(projection, dir);
+                out.set(x, y, sample(src, u, v, wrap));
+            }
+        }
+        out
+    }).collect()
+}
+
+/// Resamples six cube map faces to a 2D environment map. Sphere map texels
+/// outside the sphere are left transparent black.
+pub fn from_cube(faces: &[Image], projection: Projection, width: usize, height: usize)
+                 -> Image {
+    let mut out = Image::new(width, height, faces[0].linear);
+    for y in 0..height {
+        for x in 0..width {
+            let u = (x as f32 + 0.5) / width as f32;
+            let v = (y as f32 + 0.5) / height as f32;
+            if let Some(dir) = uv_to_direction(projection, u, v) {
+                out.set(x, y, sample_cube(faces, dir));
+            }
+        }
+    }
+    out
+}
+
+/// Halves each face with a box filter
+fn downsample_cube(faces: &[Image]) -> Vec<Image> {
+    faces.iter().map(|src| {
+        let size = cmp::max(1, src.width / 2);
+        let mut out = Image::new(size, size, src.linear);
+        for y in 0..size {
+            for x in 0..size {
+                let mut sum = [0.0; 4];
+                for &(dx, dy) in &[(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let p = src.get(cmp::min(x * 2 + dx, src.width - 1),
+                                    cmp::min(y * 2 + dy, src.height - 1));
+                    for c in 0..4 {
+                        sum[c] += p[c] * 0.25;
+                    }
+                }
+                out.set(x, y, sum);
+            }
+        }
+        out
+    }).collect()
+}
+
+/// Converts the colour channels of an image between sRGB and linear
+fn with_linear(src: &Image, linear: bool) -> Image {
+    let mut out = src.clone();
+    if src.linear == linear {
+        return out
+    }
+    let f = if linear { convert::srgb_to_linear } else { convert::linear_to_srgb };
+    for p in out.pixels.chunks_mut(4) {
+        for c in 0..3 {
+            p[c] = f(p[c]);
+        }
+    }
+    out.linear = linear;
+    out
+}
+
+/// Samples a cube map mip chain with trilinear filtering
+fn sample_cube_lod(chain: &[Vec<Image>], dir: [f32; 3], lod: f32) -> [f32; 4] {
+    let lod = lod.max(0.0).min((chain.len() - 1) as f32);
+    let level = lod.floor() as usize;
+    let a = sample_cube(&chain[level], dir);
+    if level + 1 >= chain.len() {
+        return a
+    }
+    let b = sample_cube(&chain[level + 1], dir);
+    let f = lod - level as f32;
+    [a[0] + (b[0] - a[0]) * f, a[1] + (b[1] - a[1]) * f,
+     a[2] + (b[2] - a[2]) * f, a[3] + (b[3] - a[3]) * f]
+}
+
+/// Returns the ith point of a Hammersley sequence of n points
+fn hammersley(i: u32, n: u32) -> (f32, f32) {
+    (i as f32 / n as f32, i.reverse_bits() as f32 / 4294967296.0)
+}
+
+/// Convolves a cube map with the GGX distribution for the given roughness,
+/// for use as a specular image based lighting level. Uses filtered
+/// importance sampling, `chain` is the source cube's box filtered mip chain.
+fn prefilter(chain: &[Vec<Image>], roughness: f32, size: usize, samples: u32) -> Vec<Image> {
+    let a = roughness * roughness;
+    let source_size = chain[0][0].width as f32;
+    let texel_solid_angle = 4.0 * PI / (6.0 * source_size * source_size);
+    (0..6).map(|face| {
+        let mut out = Image::new(size, size, chain[0][0].linear);
+        for y in 0..size {
+            for x in 0..size {
+                let n = cube_direction(face, (x as f32 + 0.5) / size as f32,
+                                       (y as f32 + 0.5) / size as f32);
+                if roughness == 0.0 {
+                    out.set(x, y, sample_cube(&chain[0], n));
+                    continue
+                }
+                // build a basis around the normal, which is also the view
+                // direction
+                let up = if n[1].abs() < 0.999 { [0.0, 1.0, 0.0] } else { [1.0, 0.0, 0.0] };
+                let tangent = normalize(cross(up, n));
+                let bitangent = cross(n, tangent);
+                let mut sum = [0.0; 4];
+                let mut total = 0.0;
+                for i in 0..samples {
+                    let (e1, e2) = hammersley(i, samples);
+                    let phi = 2.0 * PI * e1;
+                    let cos_theta = ((1.0 - e2) / (1.0 + (a * a - 1.0) * e2)).sqrt();
+                    let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+                    let hl = [sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta];
+                    let h = [tangent[0] * hl[0] + bitangent[0] * hl[1] + n[0] * hl[2],
+                             tangent[1] * hl[0] + bitangent[1] * hl[1] + n[1] * hl[2],
+                             tangent[2] * hl[0] + bitangent[2] * hl[1] + n[2] * hl[2]];
+                    let v_dot_h = dot(n, h);
+                    let l = [2.0 * v_dot_h * h[0] - n[0], 2.0 * v_dot_h * h[1] - n[1],
+                             2.0 * v_dot_h * h[2] - n[2]];
+                    let n_dot_l = dot(n, l);
+                    if n_dot_l <= 0.0 {
+                        continue
+                    }
+                    // pick the source level whose texels cover the sample's
+                    // solid angle
+                    let d = a * a / (PI * (cos_theta * cos_theta * (a * a - 1.0) + 1.0).powi(2));
+                    let pdf = d / 4.0;
+                    let sample_solid_angle = 1.0 / (samples as f32 * pdf + 1.0e-4);
+                    let lod = 0.5 * (sample_solid_angle / texel_solid_angle).log2() + 1.0;
+                    let p = sample_cube_lod(chain, l, lod);
+                    for c in 0..4 {
+                        sum[c] += p[c] * n_dot_l;
+                    }
+                    total += n_dot_l;
+                }
+                // every sample can miss the hemisphere when there are few
+                if total <= 0.0 {
+                    out.set(x, y, sample_cube(&chain[0], n));
+                    continue
+                }
+                out.set(x, y, [sum[0] / total, sum[1] / total, sum[2] / total,
+                               sum[3] / total]);
+            }
+        }
+        out
+    }).collect()
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct CubeOptions {
+    pub format: Format,
+    /// Generate a full mip chain
+    pub mipmaps: bool,
+    /// Convolve each mip level with the GGX distribution, with roughness
+    /// increasing linearly from 0 at the base level to 1 at the smallest
+    pub prefilter: bool,
+    /// The number of samples per texel when prefiltering
+    pub samples: u32
+}
+
+impl Default for CubeOptions {
+    fn default() -> CubeOptions {
+        CubeOptions {
+            format: Format::Rgba16f,
+            mipmaps: true,
+            prefilter: false,
+            samples: 256
+        }
+    }
+}
+
+/// Builds a cube map texture from six faces, generating mip levels by box
+/// filtering or by prefiltering for specular image based lighting
+pub fn cube_texture(faces: &[Image], options: &CubeOptions) -> Result<KtxImage, ConvertError> {
+    if faces.len() != 6 || faces.iter().any(|f| f.width != faces[0].width ||
+                                            f.height != faces[0].width) {
+        return Err(ConvertError::SizeError(String::from(
+            "a cube map needs 6 square faces of the same size")))
+    }
+    let size = faces[0].width;
+    // filter in linear space, then return LDR levels to sRGB
+    let srgb = !faces[0].linear;
+    let mut chain = vec![faces.iter().map(|f| with_linear(f, true)).collect::<Vec<_>>()];
+    if options.mipmaps {
+        while chain.last().unwrap()[0].width > 1 {
+            let next = downsample_cube(chain.last().unwrap());
+            chain.push(next);
+        }
+    }
+    let num_levels = chain.len();
+
+    let mut levels = Vec::with_capacity(num_levels);
+    for level in 0..num_levels {
+        let filtered;
+        let level_faces = if options.prefilter && level > 0 {
+            let roughness = level as f32 / (num_levels - 1) as f32;
+            filtered = prefilter(&chain, roughness, chain[level][0].width, options.samples);
+            &filtered
+        }
+        else {
+            &chain[level]
+        };
+        let mut data = Vec::new();
+        for face in level_faces {
+            if srgb {
+                data.extend(options.format.encode(&with_linear(face, false)));
+            }
+            else {
+                data.extend(options.format.encode(face));
+            }
+        }
+        levels.push(data);
+    }
+
+    let mut header = options.format.header(size, size);
+    header.faces = 6;
+    header.mip_levels = num_levels as i32;
+    Ok(try!(KtxImage::new(header, KeyValueData::new(), levels)))
+}
+
+/// Converts a 2D environment map image to a cube map texture
+pub fn to_cube_texture(src: &Image, projection: Projection, size: usize,
+                       options: &CubeOptions) -> Result<KtxImage, ConvertError> {
+    cube_texture(&to_cube(src, projection, size), options)
+}
+
+/// Converts the base level of a cube map texture to a 2D environment map
+pub fn from_cube_texture(cube: &KtxImage, projection: Projection, width: usize,
+                         height: usize) -> Result<Image, ConvertError> {
+    if cube.header().num_faces() != 6 {
+        return Err(ConvertError::KtxError(ktx::LoadError::UnsupportedError(
+            String::from("conversion from a texture that isn't a cube map"))))
+    }
+    let faces = try!(convert::ktx_images(cube, 0));
+    Ok(from_cube(&faces[..6], projection, width, height))
+}
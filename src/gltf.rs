@@ -0,0 +1,1036 @@
+/*
+ * Copyright (c) 2014 Cameron Hart
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a
+ * copy of this software and associated documentation files (the "Software"),
+ * to deal in the Software without restriction, including without limitation
+ * the rights to use, copy, modify, merge, publish, distribute, sublicense,
+ * and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+ * THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+ * FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+ * DEALINGS IN THE SOFTWARE.
+ */
+
+extern crate gl;
+
+use gl::types::*;
+use image;
+use image::ImageError;
+use ktx::TextureOptions;
+use mesh::{cross, face_normal, normalize};
+use object::{LoadError, Object, SbmMesh, SubObject, VertexAttrib};
+use serde_json;
+use serde_json::Value;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str;
+
+const GLB_MAGIC: &'static [u8] = b"glTF";
+const GLB_JSON: u32 = 0x4E4F534A;
+const GLB_BIN: u32 = 0x004E4942;
+
+// primitive modes
+const TRIANGLES: u64 = 4;
+const TRIANGLE_STRIP: u64 = 5;
+const TRIANGLE_FAN: u64 = 6;
+
+/// The glTF attributes that are imported with the names they're given in
+/// the mesh, in attribute location order
+const SEMANTICS: [(&'static str, &'static str); 6] = [
+    ("POSITION", "position"),
+    ("NORMAL", "normal"),
+    ("TEXCOORD_0", "texcoord"),
+    ("TANGENT", "tangent"),
+    ("TEXCOORD_1", "texcoord1"),
+    ("COLOR_0", "color")
+];
+
+#[derive(Debug)]
+pub enum GltfError {
+    JsonError(serde_json::Error),
+    /// The file breaks the glTF spec
+    FormatError(String),
+    /// The file uses a feature that isn't supported
+    UnsupportedError(String),
+    MeshError(LoadError),
+    ImageError(ImageError),
+    IoError(io::Error)
+}
+
+impl From<serde_json::Error> for GltfError {
+    fn from(e: serde_json::Error) -> GltfError {
+        GltfError::JsonError(e)
+    }
+}
+
+impl From<LoadError> for GltfError {
+    fn from(e: LoadError) -> GltfError {
+        GltfError::MeshError(e)
+    }
+}
+
+impl From<ImageError> for GltfError {
+    fn from(e: ImageError) -> GltfError {
+        GltfError::ImageError(e)
+    }
+}
+
+impl From<io::Error> for GltfError {
+    fn from(e: io::Error) -> GltfError {
+        GltfError::IoError(e)
+    }
+}
+
+impl fmt::Display for GltfError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &GltfError::JsonError(ref e) => write!(fmt, "Invalid glTF JSON: {}", e),
+            &GltfError::FormatError(ref s) => write!(fmt, "Invalid glTF file: {}", s),
+            &GltfError::UnsupportedError(ref s) => write!(fmt, "Unsupported glTF feature: {}", s),
+            &GltfError::MeshError(ref e) => e.fmt(fmt),
+            &GltfError::ImageError(ref e) => e.fmt(fmt),
+            &GltfError::IoError(ref e) => e.fmt(fmt)
+        }
+    }
+}
+
+fn format_error<T>(s: String) -> Result<T, GltfError> {
+    Err(GltfError::FormatError(s))
+}
+
+/// An image used by a texture, either stored in the file or referring to
+/// one next to it
+#[derive(Clone, Debug)]
+pub struct Image {
+    pub name: Option<String>,
+    /// The path of an external image, relative to the glTF file
+    pub uri: Option<String>,
+    pub mime_type: Option<String>,
+    /// The encoded contents of an embedded image
+    pub data: Option<Vec<u8>>
+}
+
+/// A material's use of a texture
+#[derive(Clone, Debug)]
+pub struct TextureRef {
+    /// An index into `GltfModel::images`, None if the texture's image comes
+    /// from an unsupported extension
+    pub image: Option<usize>,
+    /// Which texcoord set the texture is sampled with
+    pub texcoord: u32,
+    /// The texture's sampler state, and whether its data is sRGB encoded
+    pub options: TextureOptions
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AlphaMode {
+    Opaque,
+    /// Alpha values below the cutoff are fully transparent
+    Mask(f32),
+    Blend
+}
+
+/// A metallic-roughness material
+#[derive(Clone, Debug)]
+pub struct Material {
+    pub name: Option<String>,
+    pub base_color_factor: [f32; 4],
+    pub base_color_texture: Option<TextureRef>,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    /// Roughness in green and metalness in blue
+    pub metallic_roughness_texture: Option<TextureRef>,
+    pub normal_texture: Option<TextureRef>,
+    pub normal_scale: f32,
+    pub occlusion_texture: Option<TextureRef>,
+    pub occlusion_strength: f32,
+    pub emissive_texture: Option<TextureRef>,
+    pub emissive_factor: [f32; 3],
+    pub alpha_mode: AlphaMode,
+    pub double_sided: bool
+}
+
+/// Where a sub-object of the imported mesh came from
+#[derive(Clone, Debug, PartialEq)]
+pub struct Primitive {
+    /// The index of the glTF mesh
+    pub mesh: usize,
+    pub mesh_name: Option<String>,
+    /// An index into `GltfModel::materials`
+    pub material: Option<usize>
+}
+
+/// A glTF scene flattened into one mesh
+pub struct GltfModel {
+    /// The triangles of every primitive in the default scene, moved into
+    /// place by their nodes' transforms
+    pub mesh: SbmMesh,
+    /// One per sub-object of the mesh
+    pub primitives: Vec<Primitive>,
+    pub materials: Vec<Material>,
+    pub images: Vec<Image>,
+    base: PathBuf
+}
+
+impl GltfModel {
+    /// Decodes one of the model's images, reading it from disk if it isn't
+    /// embedded
+    pub fn load_image(&self, index: usize) -> Result<image::Image, GltfError> {
+        let image = match self.images.get(index) {
+            Some(image) => image,
+            None => return format_error(format!("no image {}", index))
+        };
+        match (&image.data, &image.uri) {
+            (&Some(ref data), _) => Ok(try!(image::decode(data))),
+            (_, &Some(ref uri)) => {
+                let path = self.base.join(uri);
+                Ok(try!(image::decode(&try!(fs::read(path)))))
+            },
+            _ => format_error(format!("image {} has no data", index))
+        }
+    }
+
+    /// Returns the material of a sub-object
+    pub fn material(&self, sub_object: usize) -> Option<&Material> {
+        self.primitives.get(sub_object)
+            .and_then(|p| p.material)
+            .and_then(|m| self.materials.get(m))
+    }
+}
+
+fn get_usize(value: &Value, key: &str) -> Option<usize> {
+    value.get(key).and_then(Value::as_u64).map(|n| n as usize)
+}
+
+fn get_f32(value: &Value, key: &str, default: f32) -> f32 {
+    value.get(key).and_then(Value::as_f64).map_or(default, |n| n as f32)
+}
+
+fn get_str(value: &Value, key: &str) -> Option<String> {
+    value.get(key).and_then(Value::as_str).map(String::from)
+}
+
+/// Returns the array held by `key`, empty if there isn't one
+fn get_array<'a>(value: &'a Value, key: &str) -> &'a [Value] {
+    value.get(key).and_then(Value::as_array).map_or(&[], |a| &a[..])
+}
+
+/// Fills `out` from an array of numbers, leaving it alone if the array is
+/// missing or the wrong length
+fn get_floats(value: &Value, key: &str, out: &mut [f32]) {
+    let array = get_array(value, key);
+    if array.len() == out.len() {
+        for (o, v) in out.iter_mut().zip(array) {
+            *o = v.as_f64().unwrap_or(0.0) as f32;
+        }
+    }
+}
+
+/// Looks up `root[key][index]`
+fn element<'a>(root: &'a Value, key: &str, index: usize) -> Result<&'a Value, GltfError> {
+    match get_array(root, key).get(index) {
+        Some(value) => Ok(value),
+        None => format_error(format!("{} {} doesn't exist", key, index))
+    }
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() / 4 * 3);
+    let mut bits = 0u32;
+    let mut count = 0;
+    for c in text.bytes() {
+        let v = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' => break,
+            _ => return None
+        };
+        bits = (bits << 6) | v as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            out.push((bits >> count) as u8);
+            bits &= (1 << count) - 1;
+        }
+    }
+    Some(out)
+}
+
+/// Undoes the percent encoding of a relative URI
+fn decode_uri(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = if bytes[i] == b'%' && i + 2 < bytes.len() {
+            str::from_utf8(&bytes[i + 1..i + 3]).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        } else {
+            None
+        };
+        match escape {
+            Some(b) => {
+                out.push(b);
+                i += 3;
+            },
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Returns the contents of a data URI
+fn data_uri(uri: &str) -> Result<Option<Vec<u8>>, GltfError> {
+    if !uri.starts_with("data:") {
+        return Ok(None)
+    }
+    let comma = match uri.find(',') {
+        Some(comma) => comma,
+        None => return format_error(String::from("malformed data URI"))
+    };
+    if !uri[..comma].ends_with(";base64") {
+        return Err(GltfError::UnsupportedError(String::from("data URIs that aren't base64")))
+    }
+    match decode_base64(&uri[comma + 1..]) {
+        Some(data) => Ok(Some(data)),
+        None => format_error(String::from("invalid base64 in data URI"))
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    bytes.get(offset..offset + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// Splits a binary glTF file into its JSON and binary chunks
+fn split_glb(bytes: &[u8]) -> Result<(&[u8], Option<&[u8]>), GltfError> {
+    let version = read_u32(bytes, 4).unwrap_or(0);
+    if version != 2 {
+        return Err(GltfError::UnsupportedError(format!("GLB version {}", version)))
+    }
+    let length = ::std::cmp::min(read_u32(bytes, 8).unwrap_or(0) as usize, bytes.len());
+    let mut json = None;
+    let mut bin = None;
+    let mut offset = 12;
+    while offset + 8 <= length {
+        let chunk_length = read_u32(bytes, offset).unwrap() as usize;
+        let chunk_type = read_u32(bytes, offset + 4).unwrap();
+        let start = offset + 8;
+        if chunk_length > length - start {
+            return format_error(String::from("GLB chunk overruns the file"))
+        }
+        let chunk = &bytes[start..start + chunk_length];
+        match chunk_type {
+            GLB_JSON if json.is_none() => json = Some(chunk),
+            GLB_BIN if bin.is_none() => bin = Some(chunk),
+            _ => {}
+        }
+        offset = start + chunk_length;
+    }
+    match json {
+        Some(json) => Ok((json, bin)),
+        None => format_error(String::from("GLB file has no JSON chunk"))
+    }
+}
+
+fn load_buffers(root: &Value, bin: Option<&[u8]>, base: &Path) -> Result<Vec<Vec<u8>>, GltfError> {
+    let mut buffers = Vec::new();
+    for (i, buffer) in get_array(root, "buffers").iter().enumerate() {
+        let length = get_usize(buffer, "byteLength").unwrap_or(0);
+        let data = match buffer.get("uri").and_then(Value::as_str) {
+            Some(uri) => match try!(data_uri(uri)) {
+                Some(data) => data,
+                None => try!(fs::read(base.join(decode_uri(uri))))
+            },
+            None => match bin {
+                Some(bin) if i == 0 => bin.to_vec(),
+                _ => return format_error(format!("buffer {} has no data", i))
+            }
+        };
+        if data.len() < length {
+            return format_error(format!("buffer {} is shorter than its byteLength", i))
+        }
+        buffers.push(data);
+    }
+    Ok(buffers)
+}
+
+/// Returns the bytes of a buffer view and its stride
+fn buffer_view<'a>(root: &Value, buffers: &'a [Vec<u8>], index: usize)
+                   -> Result<(&'a [u8], Option<usize>), GltfError> {
+    let view = try!(element(root, "bufferViews", index));
+    let buffer = match get_usize(view, "buffer").and_then(|b| buffers.get(b)) {
+        Some(buffer) => buffer,
+        None => return format_error(format!("bufferView {} has no buffer", index))
+    };
+    let offset = get_usize(view, "byteOffset").unwrap_or(0);
+    let length = get_usize(view, "byteLength").unwrap_or(0);
+    match offset.checked_add(length) {
+        Some(end) if end <= buffer.len() => Ok((&buffer[offset..end], get_usize(view, "byteStride"))),
+        _ => format_error(format!("bufferView {} overruns its buffer", index))
+    }
+}
+
+fn component_size(ty: GLenum) -> Option<usize> {
+    match ty {
+        gl::BYTE | gl::UNSIGNED_BYTE => Some(1),
+        gl::SHORT | gl::UNSIGNED_SHORT => Some(2),
+        gl::UNSIGNED_INT | gl::FLOAT => Some(4),
+        _ => None
+    }
+}
+
+// an element of a MAT4 accessor is the largest
+static ZEROS: [u8; 64] = [0; 64];
+
+/// A typed view of a buffer
+struct Accessor<'a> {
+    data: &'a [u8],
+    stride: usize,
+    count: usize,
+    ty: GLenum,
+    components: usize,
+    normalized: bool
+}
+
+impl<'a> Accessor<'a> {
+    fn new(root: &Value, buffers: &'a [Vec<u8>], index: usize) -> Result<Accessor<'a>, GltfError> {
+        let accessor = try!(element(root, "accessors", index));
+        if accessor.get("sparse").is_some() {
+            return Err(GltfError::UnsupportedError(String::from("sparse accessors")))
+        }
+        let ty = get_usize(accessor, "componentType").unwrap_or(0) as GLenum;
+        let size = match component_size(ty) {
+            Some(size) => size,
+            None => return format_error(format!("accessor {} has component type {}", index, ty))
+        };
+        let components = match accessor.get("type").and_then(Value::as_str) {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") | Some("MAT2") => 4,
+            Some("MAT3") => 9,
+            Some("MAT4") => 16,
+            _ => return format_error(format!("accessor {} has no valid type", index))
+        };
+        let count = get_usize(accessor, "count").unwrap_or(0);
+        let element_size = size * components;
+        // accessors without a buffer view are all zeros
+        let (data, stride) = match get_usize(accessor, "bufferView") {
+            Some(view) => {
+                let (data, stride) = try!(buffer_view(root, buffers, view));
+                let offset = get_usize(accessor, "byteOffset").unwrap_or(0);
+                let stride = stride.unwrap_or(element_size);
+                let end = match offset.checked_add(element_size) {
+                    Some(first_end) => count.checked_sub(1)
+                        .and_then(|last| last.checked_mul(stride))
+                        .and_then(|last| last.checked_add(first_end)),
+                    None => return format_error(format!("accessor {} overruns its bufferView", index))
+                };
+                match end {
+                    Some(end) if end <= data.len() => (&data[offset..end], stride),
+                    None if count == 0 && offset <= data.len() => (&data[offset..offset], stride),
+                    _ => return format_error(format!("accessor {} overruns its bufferView", index))
+                }
+            },
+            None => (&ZEROS[..element_size], 0)
+        };
+        Ok(Accessor {
+            data: data,
+            stride: stride,
+            count: count,
+            ty: ty,
+            components: components,
+            normalized: accessor.get("normalized").and_then(Value::as_bool).unwrap_or(false)
+        })
+    }
+
+    fn element_size(&self) -> usize {
+        component_size(self.ty).unwrap() * self.components
+    }
+
+    fn element(&self, i: usize) -> &'a [u8] {
+        let start = i * self.stride;
+        &self.data[start..start + self.element_size()]
+    }
+
+    /// Returns a component as an integer, without normalizing it
+    fn int(&self, i: usize, c: usize) -> i64 {
+        let e = self.element(i);
+        match self.ty {
+            gl::BYTE => e[c] as i8 as i64,
+            gl::UNSIGNED_BYTE => e[c] as i64,
+            gl::SHORT => i16::from_le_bytes([e[c * 2], e[c * 2 + 1]]) as i64,
+            gl::UNSIGNED_SHORT => u16::from_le_bytes([e[c * 2], e[c * 2 + 1]]) as i64,
+            _ => u32::from_le_bytes([e[c * 4], e[c * 4 + 1], e[c * 4 + 2], e[c * 4 + 3]]) as i64
+        }
+    }
+
+    fn float(&self, i: usize, c: usize) -> f32 {
+        if self.ty == gl::FLOAT {
+            return f32::from_bits(self.int(i, c) as u32)
+        }
+        let v = self.int(i, c) as f32;
+        if !self.normalized {
+            return v
+        }
+        match self.ty {
+            gl::BYTE => (v / 127.0).max(-1.0),
+            gl::UNSIGNED_BYTE => v / 255.0,
+            gl::SHORT => (v / 32767.0).max(-1.0),
+            gl::UNSIGNED_SHORT => v / 65535.0,
+            _ => v / 4294967295.0
+        }
+    }
+
+    fn vec3(&self, i: usize) -> [f32; 3] {
+        [self.float(i, 0), self.float(i, 1), self.float(i, 2)]
+    }
+}
+
+/// Appends a float as a component of the given type
+fn encode(value: f32, ty: GLenum, normalized: bool, out: &mut Vec<u8>) {
+    let scale = |max: f32| if normalized { (value * max).round() } else { value };
+    match ty {
+        gl::BYTE => out.push(scale(127.0) as i8 as u8),
+        gl::UNSIGNED_BYTE => out.push(scale(255.0) as u8),
+        gl::SHORT => out.extend_from_slice(&(scale(32767.0) as i16).to_le_bytes()),
+        gl::UNSIGNED_SHORT => out.extend_from_slice(&(scale(65535.0) as u16).to_le_bytes()),
+        gl::UNSIGNED_INT => out.extend_from_slice(&(scale(4294967295.0) as u32).to_le_bytes()),
+        _ => out.extend_from_slice(&value.to_bits().to_le_bytes())
+    }
+}
+
+/// A column-major 4x4 matrix
+type Matrix = [f32; 16];
+
+const IDENTITY: Matrix = [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0,
+                          0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0];
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut out = [0.0; 16];
+    for col in 0..4 {
+        for row in 0..4 {
+            out[col * 4 + row] = (0..4).map(|k| a[k * 4 + row] * b[col * 4 + k]).sum();
+        }
+    }
+    out
+}
+
+fn node_matrix(node: &Value) -> Matrix {
+    let mut m = IDENTITY;
+    if node.get("matrix").is_some() {
+        get_floats(node, "matrix", &mut m);
+        return m
+    }
+    let mut t = [0.0; 3];
+    let mut r = [0.0, 0.0, 0.0, 1.0];
+    let mut s = [1.0; 3];
+    get_floats(node, "translation", &mut t);
+    get_floats(node, "rotation", &mut r);
+    get_floats(node, "scale", &mut s);
+    let (x, y, z, w) = (r[0], r[1], r[2], r[3]);
+    let rotation = [
+        1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y + z * w), 2.0 * (x * z - y * w),
+        2.0 * (x * y - z * w), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z + x * w),
+        2.0 * (x * z + y * w), 2.0 * (y * z - x * w), 1.0 - 2.0 * (x * x + y * y)
+    ];
+    for col in 0..3 {
+        for row in 0..3 {
+            m[col * 4 + row] = rotation[col * 3 + row] * s[col];
+        }
+        m[12 + col] = t[col];
+    }
+    m
+}
+
+fn column(m: &Matrix, col: usize) -> [f32; 3] {
+    [m[col * 4], m[col * 4 + 1], m[col * 4 + 2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn combine(columns: &[[f32; 3]; 3], v: [f32; 3]) -> [f32; 3] {
+    let mut out = [0.0; 3];
+    for c in 0..3 {
+        for row in 0..3 {
+            out[row] += columns[c][row] * v[c];
+        }
+    }
+    out
+}
+
+/// Moves positions, normals and tangents by a node's transform
+struct Transform {
+    matrix: Matrix,
+    axes: [[f32; 3]; 3],
+    /// The inverse transpose of `axes`, scaled by its determinant's magnitude
+    normals: [[f32; 3]; 3],
+    /// Set if the transform mirrors, which swaps triangles' winding
+    mirrored: bool
+}
+
+impl Transform {
+    fn new(matrix: Matrix) -> Transform {
+        let axes = [column(&matrix, 0), column(&matrix, 1), column(&matrix, 2)];
+        let det = dot(axes[0], cross(axes[1], axes[2]));
+        let sign = if det < 0.0 { -1.0 } else { 1.0 };
+        let cofactor = |a: [f32; 3], b: [f32; 3]| {
+            let c = cross(a, b);
+            [c[0] * sign, c[1] * sign, c[2] * sign]
+        };
+        Transform {
+            matrix: matrix,
+            axes: axes,
+            normals: [cofactor(axes[1], axes[2]), cofactor(axes[2], axes[0]),
+                      cofactor(axes[0], axes[1])],
+            mirrored: det < 0.0
+        }
+    }
+
+    fn point(&self, p: [f32; 3]) -> [f32; 3] {
+        let v = combine(&self.axes, p);
+        [v[0] + self.matrix[12], v[1] + self.matrix[13], v[2] + self.matrix[14]]
+    }
+
+    fn normal(&self, n: [f32; 3]) -> [f32; 3] {
+        normalize(combine(&self.normals, n))
+    }
+
+    fn direction(&self, d: [f32; 3]) -> [f32; 3] {
+        normalize(combine(&self.axes, d))
+    }
+}
+
+/// Collects every primitive drawn by a node and its children
+fn visit(root: &Value, index: usize, parent: &Matrix, depth: usize,
+         draws: &mut Vec<(usize, Matrix)>) -> Result<(), GltfError> {
+    if depth > get_array(root, "nodes").len() {
+        return format_error(format!("node {} is its own ancestor", index))
+    }
+    let node = try!(element(root, "nodes", index));
+    let matrix = multiply(parent, &node_matrix(node));
+    if let Some(mesh) = get_usize(node, "mesh") {
+        draws.push((mesh, matrix));
+    }
+    for child in get_array(node, "children") {
+        match child.as_u64() {
+            Some(child) => try!(visit(root, child as usize, &matrix, depth + 1, draws)),
+            None => return format_error(format!("node {} has an invalid child", index))
+        }
+    }
+    Ok(())
+}
+
+/// Returns the meshes to draw and their transforms, from the default scene
+/// or, if there are no scenes, every mesh untransformed
+fn scene_meshes(root: &Value) -> Result<Vec<(usize, Matrix)>, GltfError> {
+    let mut draws = Vec::new();
+    if get_array(root, "scenes").is_empty() {
+        for mesh in 0..get_array(root, "meshes").len() {
+            draws.push((mesh, IDENTITY));
+        }
+        return Ok(draws)
+    }
+    let scene = try!(element(root, "scenes", get_usize(root, "scene").unwrap_or(0)));
+    for node in get_array(scene, "nodes") {
+        match node.as_u64() {
+            Some(node) => try!(visit(root, node as usize, &IDENTITY, 0, &mut draws)),
+            None => return format_error(String::from("scene has an invalid node"))
+        }
+    }
+    Ok(draws)
+}
+
+/// An attribute of the imported mesh, packed into its own array
+struct Column {
+    name: &'static str,
+    ty: GLenum,
+    components: usize,
+    normalized: bool,
+    /// The value used by primitives that don't have the attribute
+    default: [f32; 4],
+    data: Vec<u8>
+}
+
+impl Column {
+    fn push(&mut self, accessor: Option<&Accessor>, i: usize) {
+        match accessor {
+            Some(a) if a.ty == self.ty && a.components == self.components &&
+                    a.normalized == self.normalized => {
+                self.data.extend_from_slice(a.element(i));
+            },
+            _ => for c in 0..self.components {
+                let value = match accessor {
+                    Some(a) if c < a.components => a.float(i, c),
+                    _ => self.default[c]
+                };
+                encode(value, self.ty, self.normalized, &mut self.data);
+            }
+        }
+    }
+
+    fn push_floats(&mut self, values: &[f32]) {
+        for &v in values {
+            self.data.extend_from_slice(&v.to_bits().to_le_bytes());
+        }
+    }
+}
+
+fn texture_ref(root: &Value, info: Option<&Value>, srgb: bool) -> Result<Option<TextureRef>, GltfError> {
+    let info = match info {
+        Some(info) => info,
+        None => return Ok(None)
+    };
+    let index = match get_usize(info, "index") {
+        Some(index) => index,
+        None => return format_error(String::from("texture reference without an index"))
+    };
+    let texture = try!(element(root, "textures", index));
+    let mut options = TextureOptions {
+        min_filter: gl::LINEAR_MIPMAP_LINEAR,
+        srgb: Some(srgb),
+        .. TextureOptions::default()
+    };
+    if let Some(sampler) = get_usize(texture, "sampler") {
+        let sampler = try!(element(root, "samplers", sampler));
+        let get = |key, default| get_usize(sampler, key).map_or(default, |v| v as GLenum);
+        options.mag_filter = get("magFilter", options.mag_filter);
+        options.min_filter = get("minFilter", options.min_filter);
+        options.wrap_s = get("wrapS", options.wrap_s);
+        options.wrap_t = get("wrapT", options.wrap_t);
+    }
+    Ok(Some(TextureRef {
+        image: get_usize(texture, "source"),
+        texcoord: get_usize(info, "texCoord").unwrap_or(0) as u32,
+        options: options
+    }))
+}
+
+fn parse_material(root: &Value, material: &Value) -> Result<Material, GltfError> {
+    let pbr = material.get("pbrMetallicRoughness").unwrap_or(&Value::Null);
+    let mut base_color_factor = [1.0; 4];
+    get_floats(pbr, "baseColorFactor", &mut base_color_factor);
+    let mut emissive_factor = [0.0; 3];
+    get_floats(material, "emissiveFactor", &mut emissive_factor);
+    let normal = material.get("normalTexture");
+    let occlusion = material.get("occlusionTexture");
+    Ok(Material {
+        name: get_str(material, "name"),
+        base_color_factor: base_color_factor,
+        base_color_texture: try!(texture_ref(root, pbr.get("baseColorTexture"), true)),
+        metallic_factor: get_f32(pbr, "metallicFactor", 1.0),
+        roughness_factor: get_f32(pbr, "roughnessFactor", 1.0),
+        metallic_roughness_texture: try!(texture_ref(root, pbr.get("metallicRoughnessTexture"), false)),
+        normal_texture: try!(texture_ref(root, normal, false)),
+        normal_scale: normal.map_or(1.0, |n| get_f32(n, "scale", 1.0)),
+        occlusion_texture: try!(texture_ref(root, occlusion, false)),
+        occlusion_strength: occlusion.map_or(1.0, |o| get_f32(o, "strength", 1.0)),
+        emissive_texture: try!(texture_ref(root, material.get("emissiveTexture"), true)),
+        emissive_factor: emissive_factor,
+        alpha_mode: match material.get("alphaMode").and_then(Value::as_str) {
+            Some("MASK") => AlphaMode::Mask(get_f32(material, "alphaCutoff", 0.5)),
+            Some("BLEND") => AlphaMode::Blend,
+            _ => AlphaMode::Opaque
+        },
+        double_sided: material.get("doubleSided").and_then(Value::as_bool).unwrap_or(false)
+    })
+}
+
+fn parse_image(root: &Value, buffers: &[Vec<u8>], image: &Value) -> Result<Image, GltfError> {
+    let mut out = Image {
+        name: get_str(image, "name"),
+        uri: None,
+        mime_type: get_str(image, "mimeType"),
+        data: None
+    };
+    if let Some(uri) = image.get("uri").and_then(Value::as_str) {
+        match try!(data_uri(uri)) {
+            Some(data) => out.data = Some(data),
+            None => out.uri = Some(decode_uri(uri))
+        }
+    }
+    else if let Some(view) = get_usize(image, "bufferView") {
+        out.data = Some(try!(buffer_view(root, buffers, view)).0.to_vec());
+    }
+    Ok(out)
+}
+
+/// Converts a primitive's indices into a triangle list
+fn triangles(mode: u64, indices: Vec<u32>) -> Vec<u32> {
+    match mode {
+        TRIANGLE_STRIP => (2..indices.len()).flat_map(|i| if i % 2 == 0 {
+            vec![indices[i - 2], indices[i - 1], indices[i]]
+        } else {
+            vec![indices[i - 1], indices[i - 2], indices[i]]
+        }).collect(),
+        TRIANGLE_FAN => (2..indices.len()).flat_map(|i| {
+            vec![indices[0], indices[i - 1], indices[i]]
+        }).collect(),
+        _ => {
+            let whole = indices.len() / 3 * 3;
+            let mut indices = indices;
+            indices.truncate(whole);
+            indices
+        }
+    }
+}
+
+/// Parses a glTF file, either JSON or binary, held in memory. External
+/// buffers are read relative to `base`, and images are left to
+/// `GltfModel::load_image`.
+///
+/// The primitives of the default scene become sub-objects of one mesh, with
+/// node transforms applied. Attributes keep their accessor's type unless
+/// primitives disagree, when they're converted to floats. Primitives without
+/// normals get smooth normals, which are flat if their vertices aren't
+/// shared. Points and lines are skipped, as are skins and morph targets.
+pub fn parse(bytes: &[u8], base: &Path) -> Result<GltfModel, GltfError> {
+    let (json, bin) = if bytes.starts_with(GLB_MAGIC) {
+        try!(split_glb(bytes))
+    } else {
+        (bytes, None)
+    };
+    let root: Value = try!(serde_json::from_slice(json));
+    let version = root.get("asset").and_then(|a| get_str(a, "version")).unwrap_or_default();
+    if !version.starts_with("2.") {
+        return Err(GltfError::UnsupportedError(format!("glTF version '{}'", version)))
+    }
+    if let Some(extension) = get_array(&root, "extensionsRequired").first() {
+        return Err(GltfError::UnsupportedError(format!("extension {}", extension)))
+    }
+
+    let buffers = try!(load_buffers(&root, bin, base));
+    let mut materials = Vec::new();
+    for material in get_array(&root, "materials") {
+        materials.push(try!(parse_material(&root, material)));
+    }
+    let mut images = Vec::new();
+    for image in get_array(&root, "images") {
+        images.push(try!(parse_image(&root, &buffers, image)));
+    }
+
+    // gather the triangle primitives and their attributes
+    let mut draws = Vec::new();
+    for (mesh_index, matrix) in try!(scene_meshes(&root)) {
+        let mesh = try!(element(&root, "meshes", mesh_index));
+        for primitive in get_array(mesh, "primitives") {
+            let mode = primitive.get("mode").and_then(Value::as_u64).unwrap_or(TRIANGLES);
+            if mode < TRIANGLES || mode > TRIANGLE_FAN {
+                warn!("skipping a mesh {} primitive that isn't made of triangles", mesh_index);
+                continue
+            }
+            let attributes = primitive.get("attributes").unwrap_or(&Value::Null);
+            let mut accessors = Vec::new();
+            for &(semantic, _) in SEMANTICS.iter() {
+                accessors.push(match get_usize(attributes, semantic) {
+                    Some(index) => Some(try!(Accessor::new(&root, &buffers, index))),
+                    None => None
+                });
+            }
+            let count = match accessors[0] {
+                Some(ref position) if position.components == 3 => position.count,
+                _ => return format_error(format!("mesh {} has a primitive without positions", mesh_index))
+            };
+            if accessors[1].as_ref().map_or(false, |a| a.components != 3) ||
+               accessors[3].as_ref().map_or(false, |a| a.components != 4) {
+                return format_error(format!("mesh {} has normals or tangents of the wrong size", mesh_index))
+            }
+            if accessors.iter().any(|a| a.as_ref().map_or(false, |a| a.components > 4)) {
+                return format_error(format!("mesh {} has a matrix attribute", mesh_index))
+            }
+            if accessors.iter().any(|a| a.as_ref().map_or(false, |a| a.count != count)) {
+                return format_error(format!("mesh {} has attributes of different lengths", mesh_index))
+            }
+            let indices = match get_usize(primitive, "indices") {
+                Some(index) => {
+                    let a = try!(Accessor::new(&root, &buffers, index));
+                    if a.ty == gl::FLOAT || a.components != 1 {
+                        return format_error(format!("accessor {} can't hold indices", index))
+                    }
+                    let indices: Vec<u32> = (0..a.count).map(|i| a.int(i, 0) as u32).collect();
+                    if indices.iter().any(|&i| i as usize >= count) {
+                        return format_error(format!("mesh {} has an index out of range", mesh_index))
+                    }
+                    indices
+                },
+                None => (0..count as u32).collect()
+            };
+            draws.push((mesh_index, primitive, Transform::new(matrix), accessors, count,
+                        triangles(mode, indices)));
+        }
+    }
+    if draws.is_empty() {
+        return format_error(String::from("the scene has no triangles"))
+    }
+
+    // each attribute keeps its type if all the primitives that have it agree
+    let mut columns = Vec::new();
+    for (s, &(_, name)) in SEMANTICS.iter().enumerate() {
+        let mut column = match s {
+            0 | 1 => Column { name: name, ty: gl::FLOAT, components: 3, normalized: false,
+                              default: [0.0; 4], data: Vec::new() },
+            3 => Column { name: name, ty: gl::FLOAT, components: 4, normalized: false,
+                          default: [1.0, 0.0, 0.0, 1.0], data: Vec::new() },
+            _ => {
+                let mut found = draws.iter().filter_map(|d| d.3[s].as_ref());
+                let first = match found.next() {
+                    Some(first) => first,
+                    None => continue
+                };
+                let mut column = Column { name: name, ty: first.ty, components: first.components,
+                                          normalized: first.normalized, default: [0.0, 0.0, 0.0, 1.0],
+                                          data: Vec::new() };
+                for a in found {
+                    if a.ty != column.ty || a.normalized != column.normalized {
+                        column.ty = gl::FLOAT;
+                        column.normalized = false;
+                    }
+                    column.components = ::std::cmp::max(column.components, a.components);
+                }
+                if name == "color" {
+                    column.default = [1.0; 4];
+                }
+                column
+            }
+        };
+        // tangents are only imported when every primitive has them
+        if s == 3 && draws.iter().any(|d| d.3[s].is_none()) {
+            continue
+        }
+        column.data.reserve(draws.iter().map(|d| d.4).sum::<usize>() * column.components * 4);
+        columns.push((s, column));
+    }
+
+    let mut indices = Vec::new();
+    let mut sub_objects = Vec::new();
+    let mut primitives = Vec::new();
+    let mut total_vertices = 0;
+    for &(mesh_index, primitive, ref transform, ref accessors, count, ref triangles) in &draws {
+        let positions: Vec<[f32; 3]> = {
+            let a = accessors[0].as_ref().unwrap();
+            (0..count).map(|i| transform.point(a.vec3(i))).collect()
+        };
+        let normals: Vec<[f32; 3]> = match accessors[1] {
+            Some(ref a) => (0..count).map(|i| transform.normal(a.vec3(i))).collect(),
+            None => {
+                let mut normals = vec![[0.0f32; 3]; count];
+                for t in triangles.chunks(3) {
+                    let n = face_normal(positions[t[0] as usize], positions[t[1] as usize],
+                                        positions[t[2] as usize]);
+                    for &i in t {
+                        for c in 0..3 {
+                            normals[i as usize][c] += n[c];
+                        }
+                    }
+                }
+                // mirroring turns the triangles inside out until their
+                // winding is swapped below
+                normals.into_iter().map(normalize)
+                    .map(|n| if transform.mirrored { [-n[0], -n[1], -n[2]] } else { n }).collect()
+            }
+        };
+        for &mut (s, ref mut column) in columns.iter_mut() {
+            match s {
+                0 => for p in &positions { column.push_floats(p) },
+                1 => for n in &normals { column.push_floats(n) },
+                3 => {
+                    let a = accessors[3].as_ref().unwrap();
+                    for i in 0..count {
+                        let t = transform.direction(a.vec3(i));
+                        let w = if a.components > 3 { a.float(i, 3) } else { 1.0 };
+                        column.push_floats(&[t[0], t[1], t[2], w]);
+                    }
+                },
+                _ => for i in 0..count {
+                    column.push(accessors[s].as_ref(), i);
+                }
+            }
+        }
+
+        let first = indices.len();
+        for t in triangles.chunks(3) {
+            let base = total_vertices as u32;
+            if transform.mirrored {
+                indices.extend_from_slice(&[t[0] + base, t[2] + base, t[1] + base]);
+            } else {
+                indices.extend_from_slice(&[t[0] + base, t[1] + base, t[2] + base]);
+            }
+        }
+        sub_objects.push(SubObject { first: first as u32, count: (indices.len() - first) as u32 });
+        primitives.push(Primitive {
+            mesh: mesh_index,
+            mesh_name: try!(element(&root, "meshes", mesh_index)).get("name")
+                .and_then(Value::as_str).map(String::from),
+            material: get_usize(primitive, "material")
+        });
+        total_vertices += count;
+    }
+
+    let mut attribs = Vec::new();
+    let mut vertex_data = Vec::new();
+    for (_, column) in columns {
+        attribs.push(VertexAttrib {
+            name: String::from(column.name),
+            size: column.components as u32,
+            ty: column.ty,
+            stride: 0,
+            normalized: column.normalized,
+            data_offset: vertex_data.len() as u32
+        });
+        vertex_data.extend_from_slice(&column.data);
+        while vertex_data.len() % 4 != 0 {
+            vertex_data.push(0);
+        }
+    }
+    let comments = primitives.iter().map(|p| {
+        let mesh = p.mesh_name.clone().unwrap_or_else(|| format!("{}", p.mesh));
+        match p.material.and_then(|m| materials.get(m)).and_then(|m| m.name.as_ref()) {
+            Some(material) => format!("mesh {}\nmaterial {}", mesh, material),
+            None => format!("mesh {}", mesh)
+        }
+    }).collect();
+    let mesh = try!(SbmMesh::new(attribs, vertex_data, total_vertices as u32, Some(&indices),
+                                 sub_objects, comments));
+
+    Ok(GltfModel {
+        mesh: mesh,
+        primitives: primitives,
+        materials: materials,
+        images: images,
+        base: base.to_path_buf()
+    })
+}
+
+/// Reads and parses a .gltf or .glb file
+pub fn open(filename: &str) -> Result<GltfModel, GltfError> {
+    let path = Path::new(filename);
+    let bytes = try!(fs::read(path));
+    parse(&bytes, path.parent().unwrap_or(Path::new("")))
+}
+
+/// Imports a glTF file and creates an `Object` from it
+pub fn load_object(filename: &str) -> Result<Object, GltfError> {
+    let model = try!(open(filename));
+    let mut object = Object::new();
+    object.load_mesh(&model.mesh);
+    Ok(object)
+}
@@ -0,0 +1,734 @@
+/*
+ * Copyright (c) 2014 Cameron Hart
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a
+ * copy of this software and associated documentation files (the "Software"),
+ * to deal in the Software without restriction, including without limitation
+ * the rights to use, copy, modify, merge, publish, distribute, sublicense,
+ * and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+ * THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+ * FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+ * DEALINGS IN THE SOFTWARE.
+ */
+
+use convert;
+use flate2::Compression;
+use flate2::Crc;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::str;
+
+/// An RGBA image with floating point channels, as decoded from a PNG, TGA or
+/// Radiance HDR file. The first row is the top of the image.
+#[derive(Clone, Debug)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    /// Four channels per pixel, one row after another
+    pub pixels: Vec<f32>,
+    /// True if the colour channels hold linear values, as read from HDR
+    /// files. PNG and TGA images hold sRGB encoded values in [0, 1].
+    pub linear: bool
+}
+
+impl Image {
+    /// Creates a transparent black image
+    pub fn new(width: usize, height: usize, linear: bool) -> Image {
+        Image {
+            width: width,
+            height: height,
+            pixels: vec![0.0; width * height * 4],
+            linear: linear
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> [f32; 4] {
+        let i = (y * self.width + x) * 4;
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2], self.pixels[i + 3]]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, pixel: [f32; 4]) {
+        let i = (y * self.width + x) * 4;
+        self.pixels[i..i + 4].copy_from_slice(&pixel);
+    }
+
+    /// Mirrors the image vertically
+    pub fn flip_vertical(&mut self) {
+        let stride = self.width * 4;
+        for y in 0..self.height / 2 {
+            let (top, bottom) = self.pixels.split_at_mut((self.height - y - 1) * stride);
+            top[y * stride..(y + 1) * stride].swap_with_slice(&mut bottom[..stride]);
+        }
+    }
+
+    /// Mirrors the image horizontally
+    pub fn flip_horizontal(&mut self) {
+        for row in self.pixels.chunks_mut(self.width * 4) {
+            for x in 0..self.width / 2 {
+                for c in 0..4 {
+                    row.swap(x * 4 + c, (self.width - x - 1) * 4 + c);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ImageError {
+    /// The file is malformed, holds a description of the problem
+    FormatError(&'static str),
+    UnsupportedError(String),
+    IoError(io::Error)
+}
+
+impl From<io::Error> for ImageError {
+    fn from(e: io::Error) -> ImageError {
+        ImageError::IoError(e)
+    }
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &ImageError::FormatError(s) => write!(fmt, "Invalid image file: {}", s),
+            &ImageError::UnsupportedError(ref s) => write!(fmt, "Unsupported image feature: {}", s),
+            &ImageError::IoError(ref e) => e.fmt(fmt)
+        }
+    }
+}
+
+const PNG_SIGNATURE: [u8; 8] = [ 137, 80, 78, 71, 13, 10, 26, 10 ];
+
+/// Images larger than this in either dimension are rejected
+const MAX_DIMENSION: usize = 16384;
+
+/// Reads and decodes the given image file
+pub fn open(filename: &str) -> Result<Image, ImageError> {
+    let mut file = try!(fs::File::open(&Path::new(filename)));
+    let mut bytes = Vec::new();
+    try!(file.read_to_end(&mut bytes));
+    decode(&bytes)
+}
+
+/// Decodes a PNG, TGA or Radiance HDR file held in memory. TGA files have no
+/// signature so anything that isn't PNG or HDR is treated as TGA.
+pub fn decode(data: &[u8]) -> Result<Image, ImageError> {
+    if data.starts_with(&PNG_SIGNATURE) {
+        decode_png(data)
+    }
+    else if data.starts_with(b"#?") {
+        decode_hdr(data)
+    }
+    else {
+        decode_tga(data)
+    }
+}
+
+/// Writes the image to a PNG or PFM file, chosen by the file extension
+pub fn save(filename: &str, image: &Image) -> Result<(), ImageError> {
+    let extension = Path::new(filename).extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+    let mut file = io::BufWriter::new(try!(fs::File::create(&Path::new(filename))));
+    match extension.as_ref().map(|e| &e[..]) {
+        Some("png") => try!(write_png(&mut file, image)),
+        Some("pfm") => try!(write_pfm(&mut file, image)),
+        _ => return Err(ImageError::UnsupportedError(format!(
+            "saving to {}, expected a .png or .pfm file", filename)))
+    }
+    Ok(try!(file.flush()))
+}
+
+fn write_png_chunk<W: Write>(writer: &mut W, name: &[u8], data: &[u8]) -> io::Result<()> {
+    let mut crc = Crc::new();
+    crc.update(name);
+    crc.update(data);
+    try!(writer.write_all(&(data.len() as u32).to_be_bytes()));
+    try!(writer.write_all(name));
+    try!(writer.write_all(data));
+    writer.write_all(&crc.sum().to_be_bytes())
+}
+
+/// Writes the image as an 8 bit RGBA PNG. Linear images are sRGB encoded
+/// and every channel is clamped to [0, 1].
+pub fn write_png<W: Write>(writer: &mut W, image: &Image) -> io::Result<()> {
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(image.width as u32).to_be_bytes());
+    header.extend_from_slice(&(image.height as u32).to_be_bytes());
+    // 8 bits per channel, RGBA, no interlacing
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    let mut row = Vec::with_capacity(image.width * 4 + 1);
+    for pixels in image.pixels.chunks(image.width * 4) {
+        row.clear();
+        // no filtering
+        row.push(0);
+        for p in pixels.chunks(4) {
+            for c in 0..4 {
+                let v = if image.linear && c < 3 { convert::linear_to_srgb(p[c]) } else { p[c] };
+                row.push((v.max(0.0).min(1.0) * 255.0 + 0.5) as u8);
+            }
+        }
+        try!(encoder.write_all(&row));
+    }
+    let data = try!(encoder.finish());
+
+    try!(writer.write_all(&PNG_SIGNATURE));
+    try!(write_png_chunk(writer, b"IHDR", &header));
+    try!(write_png_chunk(writer, b"IDAT", &data));
+    write_png_chunk(writer, b"IEND", &[])
+}
+
+/// Writes the colour channels of the image as a little endian PFM file,
+/// which stores linear values from the bottom row up. sRGB encoded images
+/// are converted to linear.
+pub fn write_pfm<W: Write>(writer: &mut W, image: &Image) -> io::Result<()> {
+    try!(write!(writer, "PF\n{} {}\n-1.0\n", image.width, image.height));
+    let mut row = Vec::with_capacity(image.width * 12);
+    for pixels in image.pixels.chunks(image.width * 4).rev() {
+        row.clear();
+        for p in pixels.chunks(4) {
+            for c in 0..3 {
+                let v = if image.linear { p[c] } else { convert::srgb_to_linear(p[c]) };
+                row.extend_from_slice(&v.to_bits().to_le_bytes());
+            }
+        }
+        try!(writer.write_all(&row));
+    }
+    Ok(())
+}
+
+fn read_u16_be(data: &[u8]) -> u16 {
+    (data[0] as u16) << 8 | data[1] as u16
+}
+
+fn read_u16_le(data: &[u8]) -> u16 {
+    (data[1] as u16) << 8 | data[0] as u16
+}
+
+fn read_u32_be(data: &[u8]) -> u32 {
+    (data[0] as u32) << 24 | (data[1] as u32) << 16 | (data[2] as u32) << 8 | data[3] as u32
+}
+
+fn check_dimensions(width: usize, height: usize) -> Result<(), ImageError> {
+    if width == 0 || height == 0 {
+        return Err(ImageError::FormatError("zero image dimension"))
+    }
+    if width > MAX_DIMENSION || height > MAX_DIMENSION {
+        return Err(ImageError::UnsupportedError(format!("{}x{} image", width, height)))
+    }
+    Ok(())
+}
+
+struct PngHeader {
+    width: usize,
+    height: usize,
+    depth: u8,
+    color_type: u8,
+    interlaced: bool
+}
+
+impl PngHeader {
+    fn channels(&self) -> usize {
+        match self.color_type {
+            2 => 3,
+            4 => 2,
+            6 => 4,
+            _ => 1
+        }
+    }
+
+    /// Returns the size in bytes of a row of the given width, excluding the
+    /// filter type byte
+    fn row_size(&self, width: usize) -> usize {
+        (width * self.channels() * self.depth as usize + 7) / 8
+    }
+
+    /// Returns the distance in bytes between a byte and the matching byte of
+    /// the previous pixel, used by the row filters
+    fn filter_stride(&self) -> usize {
+        ::std::cmp::max(1, self.channels() * self.depth as usize / 8)
+    }
+}
+
+/// Reads the `index`th sample of a row with the given bit depth
+fn png_sample(row: &[u8], index: usize, depth: u8) -> u32 {
+    match depth {
+        8 => row[index] as u32,
+        16 => read_u16_be(&row[index * 2..]) as u32,
+        _ => {
+            let bit = index * depth as usize;
+            let shift = 8 - depth as usize - bit % 8;
+            (row[bit / 8] as u32 >> shift) & ((1 << depth) - 1)
+        }
+    }
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i32 + b as i32 - c as i32;
+    let pa = (p - a as i32).abs();
+    let pb = (p - b as i32).abs();
+    let pc = (p - c as i32).abs();
+    if pa <= pb && pa <= pc {
+        a
+    }
+    else if pb <= pc {
+        b
+    }
+    else {
+        c
+    }
+}
+
+/// Reverses the filter applied to `row` in place, `prev` is the previous
+/// unfiltered row or all zeroes for the first row
+fn unfilter_row(filter: u8, row: &mut [u8], prev: &[u8], stride: usize)
+                -> Result<(), ImageError> {
+    match filter {
+        0 => (),
+        1 => for i in stride..row.len() {
+            row[i] = row[i].wrapping_add(row[i - stride]);
+        },
+        2 => for i in 0..row.len() {
+            row[i] = row[i].wrapping_add(prev[i]);
+        },
+        3 => for i in 0..row.len() {
+            let left = if i >= stride { row[i - stride] as u32 } else { 0 };
+            row[i] = row[i].wrapping_add(((left + prev[i] as u32) / 2) as u8);
+        },
+        4 => for i in 0..row.len() {
+            let (left, up_left) = if i >= stride {
+                (row[i - stride], prev[i - stride])
+            }
+            else {
+                (0, 0)
+            };
+            row[i] = row[i].wrapping_add(paeth(left, prev[i], up_left));
+        },
+        _ => return Err(ImageError::FormatError("unknown png filter type"))
+    }
+    Ok(())
+}
+
+fn decode_png(data: &[u8]) -> Result<Image, ImageError> {
+    let mut header = None;
+    let mut palette: &[u8] = &[];
+    let mut transparency: &[u8] = &[];
+    let mut compressed = Vec::new();
+
+    // gather the chunks we need, each is a length, type, data and crc
+    let mut pos = PNG_SIGNATURE.len();
+    loop {
+        if data.len() - pos < 12 {
+            return Err(ImageError::FormatError("truncated png chunk"))
+        }
+        let length = read_u32_be(&data[pos..]) as usize;
+        let chunk_type = &data[pos + 4..pos + 8];
+        let start = pos + 8;
+        if length > data.len() - start - 4 {
+            return Err(ImageError::FormatError("truncated png chunk"))
+        }
+        let chunk = &data[start..start + length];
+        match chunk_type {
+            b"IHDR" => {
+                if length != 13 {
+                    return Err(ImageError::FormatError("invalid png header size"))
+                }
+                let h = PngHeader {
+                    width: read_u32_be(chunk) as usize,
+                    height: read_u32_be(&chunk[4..]) as usize,
+                    depth: chunk[8],
+                    color_type: chunk[9],
+                    interlaced: chunk[12] == 1
+                };
+                try!(check_dimensions(h.width, h.height));
+                let valid_depth = match h.color_type {
+                    0 => [1, 2, 4, 8, 16].contains(&h.depth),
+                    3 => [1, 2, 4, 8].contains(&h.depth),
+                    2 | 4 | 6 => h.depth == 8 || h.depth == 16,
+                    _ => false
+                };
+                if !valid_depth || chunk[10] != 0 || chunk[11] != 0 || chunk[12] > 1 {
+                    return Err(ImageError::FormatError("invalid png header"))
+                }
+                header = Some(h);
+            },
+            b"PLTE" => palette = chunk,
+            b"tRNS" => transparency = chunk,
+            b"IDAT" => compressed.extend_from_slice(chunk),
+            b"IEND" => break,
+            _ => {
+                // critical chunks have an upper case first letter
+                if chunk_type[0] & 0x20 == 0 {
+                    return Err(ImageError::UnsupportedError(format!("png chunk {}",
+                        String::from_utf8_lossy(chunk_type))))
+                }
+            }
+        }
+        pos = start + length + 4;
+    }
+
+    let h = match header {
+        Some(h) => h,
+        None => return Err(ImageError::FormatError("missing png header"))
+    };
+    if h.color_type == 3 && (palette.is_empty() || palette.len() % 3 != 0) {
+        return Err(ImageError::FormatError("invalid png palette"))
+    }
+
+    // non-interlaced images are a single pass covering every pixel
+    const ADAM7: [(usize, usize, usize, usize); 7] = [
+        (0, 0, 8, 8), (4, 0, 8, 8), (0, 4, 4, 8), (2, 0, 4, 4),
+        (0, 2, 2, 4), (1, 0, 2, 2), (0, 1, 1, 2) ];
+    let pattern: &[(usize, usize, usize, usize)] = if h.interlaced {
+        &ADAM7
+    }
+    else {
+        &[(0, 0, 1, 1)]
+    };
+    // each pass is its offset, step and size, empty passes have no data
+    let passes: Vec<(usize, usize, usize, usize, usize, usize)> = pattern.iter()
+        .filter(|p| p.0 < h.width && p.1 < h.height)
+        .map(|&(x0, y0, dx, dy)| (x0, y0, dx, dy, (h.width - x0 + dx - 1) / dx,
+                                  (h.height - y0 + dy - 1) / dy))
+        .collect();
+
+    // decompress no more than the image needs, so a small stream can't
+    // expand without bound, and check it all came before allocating the image
+    let expected = passes.iter().fold(0, |total, p| total + (h.row_size(p.4) + 1) * p.5);
+    let mut raw = Vec::new();
+    if ZlibDecoder::new(&compressed[..]).take(expected as u64 + 1).read_to_end(&mut raw).is_err() {
+        return Err(ImageError::FormatError("invalid png image data"))
+    }
+    if raw.len() < expected {
+        return Err(ImageError::FormatError("truncated png image data"))
+    }
+    if raw.len() > expected {
+        return Err(ImageError::FormatError("png image data overruns the image"))
+    }
+
+    let max = ((1u32 << h.depth) - 1) as f32;
+    let channels = h.channels();
+    let mut image = Image::new(h.width, h.height, false);
+    let mut pos = 0;
+    for &(x0, y0, dx, dy, pass_width, pass_height) in &passes {
+        let row_size = h.row_size(pass_width);
+        let mut prev = vec![0u8; row_size];
+        for y in 0..pass_height {
+            let filter = raw[pos];
+            let mut row = raw[pos + 1..pos + 1 + row_size].to_vec();
+            pos += row_size + 1;
+            try!(unfilter_row(filter, &mut row, &prev, h.filter_stride()));
+
+            for x in 0..pass_width {
+                let s = |c: usize| png_sample(&row, x * channels + c, h.depth);
+                let pixel = match h.color_type {
+                    0 => {
+                        let g = s(0);
+                        let opaque = transparency.len() < 2 ||
+                            read_u16_be(transparency) as u32 != g;
+                        let g = g as f32 / max;
+                        [g, g, g, if opaque { 1.0 } else { 0.0 }]
+                    },
+                    2 => {
+                        let rgb = [s(0), s(1), s(2)];
+                        let opaque = transparency.len() < 6 ||
+                            (0..3).any(|c| read_u16_be(&transparency[c * 2..]) as u32 != rgb[c]);
+                        [rgb[0] as f32 / max, rgb[1] as f32 / max, rgb[2] as f32 / max,
+                         if opaque { 1.0 } else { 0.0 }]
+                    },
+                    3 => {
+                        let index = s(0) as usize;
+                        if index * 3 >= palette.len() {
+                            return Err(ImageError::FormatError("png palette index out of range"))
+                        }
+                        let alpha = transparency.get(index).map_or(255, |&a| a);
+                        [palette[index * 3] as f32 / 255.0,
+                         palette[index * 3 + 1] as f32 / 255.0,
+                         palette[index * 3 + 2] as f32 / 255.0,
+                         alpha as f32 / 255.0]
+                    },
+                    4 => {
+                        let g = s(0) as f32 / max;
+                        [g, g, g, s(1) as f32 / max]
+                    },
+                    _ => [s(0) as f32 / max, s(1) as f32 / max, s(2) as f32 / max,
+                          s(3) as f32 / max]
+                };
+                image.set(x0 + x * dx, y0 + y * dy, pixel);
+            }
+            prev = row;
+        }
+    }
+    Ok(image)
+}
+
+/// Converts a TGA pixel or colour map entry with the given number of bits to
+/// RGBA. 15 and 16 bit values are A1R5G5B5, others are BGR(A) or grey.
+fn tga_color(data: &[u8], bits: u8, gray: bool) -> [f32; 4] {
+    match (bits, gray) {
+        (8, _) => {
+            let g = data[0] as f32 / 255.0;
+            [g, g, g, 1.0]
+        },
+        (16, true) => {
+            let g = data[0] as f32 / 255.0;
+            [g, g, g, data[1] as f32 / 255.0]
+        },
+        (15, _) | (16, _) => {
+            let v = read_u16_le(data);
+            let c = |shift: u16| ((v >> shift) & 31) as f32 / 31.0;
+            let a = if bits == 16 && v & 0x8000 == 0 { 0.0 } else { 1.0 };
+            [c(10), c(5), c(0), a]
+        },
+        (24, _) => [data[2] as f32 / 255.0, data[1] as f32 / 255.0, data[0] as f32 / 255.0, 1.0],
+        _ => [data[2] as f32 / 255.0, data[1] as f32 / 255.0, data[0] as f32 / 255.0,
+              data[3] as f32 / 255.0]
+    }
+}
+
+fn decode_tga(data: &[u8]) -> Result<Image, ImageError> {
+    if data.len() < 18 {
+        return Err(ImageError::FormatError("truncated tga header"))
+    }
+    let id_length = data[0] as usize;
+    let has_color_map = data[1] == 1;
+    let image_type = data[2];
+    let map_first = read_u16_le(&data[3..]) as usize;
+    let map_length = read_u16_le(&data[5..]) as usize;
+    let map_bits = data[7];
+    let width = read_u16_le(&data[12..]) as usize;
+    let height = read_u16_le(&data[14..]) as usize;
+    let bits = data[16];
+    let descriptor = data[17];
+    try!(check_dimensions(width, height));
+
+    let rle = image_type & 8 != 0;
+    let gray = match image_type & !8 {
+        1 if has_color_map && (bits == 8 || bits == 16) => false,
+        2 if [15, 16, 24, 32].contains(&bits) => false,
+        3 if bits == 8 || bits == 16 => true,
+        _ => return Err(ImageError::UnsupportedError(format!(
+            "tga image type {} with {} bits per pixel", image_type, bits)))
+    };
+
+    let mut pos = 18 + id_length;
+    let mut color_map = Vec::new();
+    if has_color_map {
+        if ![15, 16, 24, 32].contains(&map_bits) {
+            return Err(ImageError::UnsupportedError(format!(
+                "tga colour map with {} bits per entry", map_bits)))
+        }
+        let entry_size = (map_bits as usize + 7) / 8;
+        let map_size = map_length * entry_size;
+        if data.len() < pos || data.len() - pos < map_size {
+            return Err(ImageError::FormatError("truncated tga colour map"))
+        }
+        color_map = data[pos..pos + map_size].chunks(entry_size)
+            .map(|entry| tga_color(entry, map_bits, false)).collect();
+        pos += map_size;
+    }
+
+    // read the raw pixel values, expanding run length packets
+    let pixel_size = (bits as usize + 7) / 8;
+    let size = width * height * pixel_size;
+    let mut values = Vec::new();
+    if !rle {
+        if data.len() < pos || data.len() - pos < size {
+            return Err(ImageError::FormatError("truncated tga image data"))
+        }
+        values.extend_from_slice(&data[pos..pos + size]);
+    }
+    else {
+        while values.len() < size {
+            if pos >= data.len() {
+                return Err(ImageError::FormatError("truncated tga image data"))
+            }
+            let packet = data[pos];
+            let count = (packet & 0x7f) as usize + 1;
+            pos += 1;
+            let packet_size = if packet & 0x80 != 0 { pixel_size } else { count * pixel_size };
+            if data.len() - pos < packet_size {
+                return Err(ImageError::FormatError("truncated tga image data"))
+            }
+            if packet & 0x80 != 0 {
+                for _ in 0..count {
+                    values.extend_from_slice(&data[pos..pos + pixel_size]);
+                }
+            }
+            else {
+                values.extend_from_slice(&data[pos..pos + packet_size]);
+            }
+            pos += packet_size;
+        }
+        values.truncate(size);
+    }
+
+    let mut image = Image::new(width, height, false);
+    for (i, value) in values.chunks(pixel_size).enumerate() {
+        let pixel = if has_color_map && image_type & !8 == 1 {
+            let index = if pixel_size == 1 {
+                value[0] as usize
+            }
+            else {
+                read_u16_le(value) as usize
+            };
+            match index.checked_sub(map_first).and_then(|i| color_map.get(i)) {
+                Some(&color) => color,
+                None => return Err(ImageError::FormatError("tga colour map index out of range"))
+            }
+        }
+        else {
+            tga_color(value, bits, gray)
+        };
+        image.set(i % width, i / width, pixel);
+    }
+
+    // rows are stored bottom to top unless the descriptor says otherwise
+    if descriptor & 0x20 == 0 {
+        image.flip_vertical();
+    }
+    if descriptor & 0x10 != 0 {
+        image.flip_horizontal();
+    }
+    Ok(image)
+}
+
+/// Decodes a scanline of RGBE pixels into `out`, handling both flat and run
+/// length encoded scanlines
+fn read_hdr_scanline(data: &[u8], pos: &mut usize, out: &mut [u8])
+                     -> Result<(), ImageError> {
+    let width = out.len() / 4;
+    let truncated = ImageError::FormatError("truncated hdr image data");
+    if data.len() - *pos < 4 {
+        return Err(truncated)
+    }
+    let d = &data[*pos..];
+    if width < 8 || width >= 0x8000 || d[0] != 2 || d[1] != 2 || d[2] & 0x80 != 0 {
+        // a flat scanline
+        if data.len() - *pos < out.len() {
+            return Err(truncated)
+        }
+        out.copy_from_slice(&data[*pos..*pos + out.len()]);
+        *pos += out.len();
+        return Ok(())
+    }
+    if read_u16_be(&d[2..]) as usize != width {
+        return Err(ImageError::FormatError("hdr scanline width mismatch"))
+    }
+    *pos += 4;
+
+    // each channel is run length encoded separately
+    for c in 0..4 {
+        let mut x = 0;
+        while x < width {
+            if *pos >= data.len() {
+                return Err(truncated)
+            }
+            let mut count = data[*pos] as usize;
+            *pos += 1;
+            let run = count > 128;
+            if run {
+                count -= 128;
+            }
+            if count == 0 || count > width - x {
+                return Err(ImageError::FormatError("invalid hdr run length"))
+            }
+            let needed = if run { 1 } else { count };
+            if data.len() - *pos < needed {
+                return Err(truncated)
+            }
+            for i in 0..count {
+                out[(x + i) * 4 + c] = data[*pos + if run { 0 } else { i }];
+            }
+            *pos += needed;
+            x += count;
+        }
+    }
+    Ok(())
+}
+
+fn decode_hdr(data: &[u8]) -> Result<Image, ImageError> {
+    // the header is text lines ending with a blank line, then the resolution
+    let mut pos = 0;
+    let mut next_line = || -> Result<&str, ImageError> {
+        match data[pos..].iter().position(|&b| b == b'\n') {
+            Some(n) => {
+                let line = &data[pos..pos + n];
+                pos += n + 1;
+                str::from_utf8(line).map_err(|_| ImageError::FormatError("invalid hdr header"))
+            },
+            None => Err(ImageError::FormatError("truncated hdr header"))
+        }
+    };
+    loop {
+        let line = try!(next_line());
+        if line.is_empty() {
+            break
+        }
+        if line.starts_with("FORMAT=") && line != "FORMAT=32-bit_rle_rgbe" {
+            return Err(ImageError::UnsupportedError(String::from(&line[7..])))
+        }
+    }
+    let resolution: Vec<&str> = try!(next_line()).split_whitespace().collect();
+    let (flip, height, width) = match &resolution[..] {
+        [y, h, "+X", w] if *y == "-Y" || *y == "+Y" => (*y == "+Y", h.parse::<usize>(),
+            w.parse::<usize>()),
+        _ => return Err(ImageError::UnsupportedError(format!(
+            "hdr orientation {}", resolution.join(" "))))
+    };
+    let (width, height) = match (width, height) {
+        (Ok(w), Ok(h)) => (w, h),
+        _ => return Err(ImageError::FormatError("invalid hdr resolution"))
+    };
+    try!(check_dimensions(width, height));
+
+    // check there's enough data for the smallest possible scanlines before
+    // allocating the image
+    let min_scanline = if width >= 8 && width < 0x8000 {
+        4 + 4 * 2 * ((width + 126) / 127)
+    }
+    else {
+        width * 4
+    };
+    if (data.len() - pos) / height < min_scanline {
+        return Err(ImageError::FormatError("truncated hdr image data"))
+    }
+
+    let mut image = Image::new(width, height, true);
+    let mut scanline = vec![0u8; width * 4];
+    for y in 0..height {
+        try!(read_hdr_scanline(data, &mut pos, &mut scanline));
+        for x in 0..width {
+            let rgbe = &scanline[x * 4..x * 4 + 4];
+            let scale = if rgbe[3] == 0 {
+                0.0
+            }
+            else {
+                2.0f32.powi(rgbe[3] as i32 - 136)
+            };
+            image.set(x, y, [rgbe[0] as f32 * scale, rgbe[1] as f32 * scale,
+                             rgbe[2] as f32 * scale, 1.0]);
+        }
+    }
+    if flip {
+        image.flip_vertical();
+    }
+    Ok(image)
+}
@@ -24,36 +24,295 @@
 
 extern crate gl;
 
+use decompress;
 use gl::types::*;
+use ktx2;
+use mipmap;
 use reader::BufferReader;
+use std::borrow::Cow;
+use std::ffi::CStr;
 use std::fmt;
 use std::fs;
 use std::io;
 use std::io::Read;
+use std::io::Write;
 use std::mem;
 use std::path::Path;
 use std::str;
 
-#[derive(Debug)]
-struct Header {
-    gl_type: u32,
-    gl_type_size: u32,
-    gl_format: u32,
-    gl_internal_format: u32,
-    gl_base_internal_format: u32,
-    pixel_width: i32,
-    pixel_height: i32,
-    pixel_depth: i32,
-    array_elements: i32,
-    faces: i32,
-    mip_levels: i32,
-    key_pair_bytes: u32
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+pub struct Header {
+    pub gl_type: u32,
+    pub gl_type_size: u32,
+    pub gl_format: u32,
+    pub gl_internal_format: u32,
+    pub gl_base_internal_format: u32,
+    pub pixel_width: i32,
+    pub pixel_height: i32,
+    pub pixel_depth: i32,
+    pub array_elements: i32,
+    pub faces: i32,
+    pub mip_levels: i32,
+    pub key_pair_bytes: u32
+}
+
+impl Header {
+    fn swap_bytes(&mut self) {
+        self.gl_type = self.gl_type.swap_bytes();
+        self.gl_type_size = self.gl_type_size.swap_bytes();
+        self.gl_format = self.gl_format.swap_bytes();
+        self.gl_internal_format = self.gl_internal_format.swap_bytes();
+        self.gl_base_internal_format = self.gl_base_internal_format.swap_bytes();
+        self.pixel_width = self.pixel_width.swap_bytes();
+        self.pixel_height = self.pixel_height.swap_bytes();
+        self.pixel_depth = self.pixel_depth.swap_bytes();
+        self.array_elements = self.array_elements.swap_bytes();
+        self.faces = self.faces.swap_bytes();
+        self.mip_levels = self.mip_levels.swap_bytes();
+        self.key_pair_bytes = self.key_pair_bytes.swap_bytes();
+    }
+
+    /// Guess the texture target from the header dimensions
+    pub fn target(&self) -> GLenum {
+        if self.pixel_height == 0 {
+            if self.array_elements == 0 {
+                gl::TEXTURE_1D
+            }
+            else {
+                gl::TEXTURE_1D_ARRAY
+            }
+        }
+        else if self.pixel_depth == 0 {
+            if self.array_elements == 0 {
+                if self.faces != 6 {
+                    gl::TEXTURE_2D
+                }
+                else {
+                    gl::TEXTURE_CUBE_MAP
+                }
+            }
+            else {
+                if self.faces != 6 {
+                    gl::TEXTURE_2D_ARRAY
+                }
+                else {
+                    gl::TEXTURE_CUBE_MAP_ARRAY
+                }
+            }
+        }
+        else {
+            gl::TEXTURE_3D
+        }
+    }
+
+    /// Returns the number of mip levels stored in the file
+    pub fn num_levels(&self) -> usize {
+        match self.mip_levels {
+            0 => 1,
+            n => n as usize
+        }
+    }
+
+    /// Returns the number of array layers, non-array textures have one layer
+    pub fn num_layers(&self) -> usize {
+        match self.array_elements {
+            0 => 1,
+            n => n as usize
+        }
+    }
+
+    /// Returns the number of cube faces, non-cube textures have one face
+    pub fn num_faces(&self) -> usize {
+        match self.faces {
+            6 => 6,
+            _ => 1
+        }
+    }
+
+    /// Checks the header fields are consistent with each other
+    pub fn validate(&self) -> Result<(), LoadError> {
+        if self.pixel_width <= 0 {
+            return Err(LoadError::HeaderError("pixel_width", self.pixel_width as i64))
+        }
+        if self.pixel_height < 0 {
+            return Err(LoadError::HeaderError("pixel_height", self.pixel_height as i64))
+        }
+        // there are no 3D array or cube textures
+        if self.pixel_depth < 0 || (self.pixel_depth != 0 &&
+            (self.pixel_height == 0 || self.array_elements != 0 || self.faces == 6)) {
+            return Err(LoadError::HeaderError("pixel_depth", self.pixel_depth as i64))
+        }
+        if self.array_elements < 0 {
+            return Err(LoadError::HeaderError("array_elements", self.array_elements as i64))
+        }
+        match self.faces {
+            0 | 1 => (),
+            6 if self.pixel_width == self.pixel_height => (),
+            _ => return Err(LoadError::HeaderError("faces", self.faces as i64))
+        }
+        match self.gl_type_size {
+            1 | 2 | 4 | 8 => (),
+            _ => return Err(LoadError::HeaderError("gl_type_size", self.gl_type_size as i64))
+        }
+        // a full mip chain ends at 1x1x1
+        let max_size = ::std::cmp::max(self.pixel_width,
+            ::std::cmp::max(self.pixel_height, self.pixel_depth)) as u32;
+        let max_levels = 32 - max_size.leading_zeros() as i32;
+        if self.mip_levels < 0 || self.mip_levels > max_levels {
+            return Err(LoadError::HeaderError("mip_levels", self.mip_levels as i64))
+        }
+        Ok(())
+    }
+
+    /// Returns the number of images stored in each mip level
+    fn images_per_level(&self) -> Result<usize, LoadError> {
+        match self.num_layers().checked_mul(self.num_faces()) {
+            Some(n) => Ok(n),
+            None => Err(LoadError::OverflowError("array layer"))
+        }
+    }
+
+    /// Returns true if the image data is stored in a compressed format
+    pub fn is_compressed(&self) -> bool {
+        self.gl_type == 0
+    }
+
+    /// Returns the size in bytes of a single array layer or cube face of the
+    /// given mip level, including every depth slice. Uncompressed rows are
+    /// padded to four bytes. Returns None for unknown formats.
+    pub fn image_size(&self, level: usize) -> Option<usize> {
+        let width = ::std::cmp::max(1, level_dimension(self.pixel_width, level)) as usize;
+        let height = ::std::cmp::max(1, level_dimension(self.pixel_height, level)) as usize;
+        let depth = ::std::cmp::max(1, level_dimension(self.pixel_depth, level)) as usize;
+        if self.is_compressed() {
+            compressed_block_size(self.gl_internal_format).and_then(
+                |(block_width, block_height, block_size)| {
+                    let blocks_x = (width + block_width - 1) / block_width;
+                    let blocks_y = (height + block_height - 1) / block_height;
+                    blocks_x.checked_mul(blocks_y)
+                        .and_then(|n| n.checked_mul(depth))
+                        .and_then(|n| n.checked_mul(block_size))
+                })
+        }
+        else {
+            pixel_size(self.gl_format, self.gl_type, self.gl_type_size).and_then(
+                |pixel_size| {
+                    pixel_size.checked_mul(width)
+                        .and_then(|n| n.checked_add(padding(n)))
+                        .and_then(|stride| stride.checked_mul(height))
+                        .and_then(|n| n.checked_mul(depth))
+                })
+        }
+    }
+}
+
+/// Returns the size in bytes of a pixel of the given uncompressed format and
+/// type
+fn pixel_size(format: GLenum, ty: GLenum, type_size: u32) -> Option<usize> {
+    let components = match format {
+        gl::RED | gl::GREEN | gl::BLUE | gl::ALPHA | gl::RED_INTEGER |
+        gl::GREEN_INTEGER | gl::BLUE_INTEGER |
+        gl::DEPTH_COMPONENT | gl::STENCIL_INDEX => 1,
+        gl::RG | gl::RG_INTEGER | gl::DEPTH_STENCIL => 2,
+        gl::RGB | gl::BGR | gl::RGB_INTEGER | gl::BGR_INTEGER => 3,
+        gl::RGBA | gl::BGRA | gl::RGBA_INTEGER | gl::BGRA_INTEGER => 4,
+        _ => return None
+    };
+    match ty {
+        // packed types store a whole pixel in one element
+        gl::UNSIGNED_BYTE_3_3_2 | gl::UNSIGNED_BYTE_2_3_3_REV |
+        gl::UNSIGNED_SHORT_5_6_5 | gl::UNSIGNED_SHORT_5_6_5_REV |
+        gl::UNSIGNED_SHORT_4_4_4_4 | gl::UNSIGNED_SHORT_4_4_4_4_REV |
+        gl::UNSIGNED_SHORT_5_5_5_1 | gl::UNSIGNED_SHORT_1_5_5_5_REV |
+        gl::UNSIGNED_INT_8_8_8_8 | gl::UNSIGNED_INT_8_8_8_8_REV |
+        gl::UNSIGNED_INT_10_10_10_2 | gl::UNSIGNED_INT_2_10_10_10_REV |
+        gl::UNSIGNED_INT_24_8 | gl::UNSIGNED_INT_10F_11F_11F_REV |
+        gl::UNSIGNED_INT_5_9_9_9_REV => Some(type_size as usize),
+        gl::FLOAT_32_UNSIGNED_INT_24_8_REV => Some(8),
+        _ => Some(components * type_size as usize)
+    }
 }
 
+/// Returns the block width, height and size in bytes of a compressed
+/// internal format
+pub fn compressed_block_size(internal_format: GLenum) -> Option<(usize, usize, usize)> {
+    // ASTC block footprints in enum order
+    const ASTC_BLOCKS: [(usize, usize); 14] = [
+        (4, 4), (5, 4), (5, 5), (6, 5), (6, 6), (8, 5), (8, 6), (8, 8),
+        (10, 5), (10, 6), (10, 8), (10, 10), (12, 10), (12, 12) ];
+    match internal_format {
+        COMPRESSED_RGB_S3TC_DXT1_EXT | COMPRESSED_RGBA_S3TC_DXT1_EXT |
+        COMPRESSED_SRGB_S3TC_DXT1_EXT | COMPRESSED_SRGB_ALPHA_S3TC_DXT1_EXT |
+        gl::COMPRESSED_RED_RGTC1 | gl::COMPRESSED_SIGNED_RED_RGTC1 |
+        gl::COMPRESSED_RGB8_ETC2 | gl::COMPRESSED_SRGB8_ETC2 |
+        gl::COMPRESSED_RGB8_PUNCHTHROUGH_ALPHA1_ETC2 |
+        gl::COMPRESSED_SRGB8_PUNCHTHROUGH_ALPHA1_ETC2 |
+        gl::COMPRESSED_R11_EAC | gl::COMPRESSED_SIGNED_R11_EAC |
+        COMPRESSED_ETC1_RGB8_OES => Some((4, 4, 8)),
+        COMPRESSED_RGBA_S3TC_DXT3_EXT | COMPRESSED_RGBA_S3TC_DXT5_EXT |
+        COMPRESSED_SRGB_ALPHA_S3TC_DXT3_EXT | COMPRESSED_SRGB_ALPHA_S3TC_DXT5_EXT |
+        gl::COMPRESSED_RG_RGTC2 | gl::COMPRESSED_SIGNED_RG_RGTC2 |
+        gl::COMPRESSED_RGBA_BPTC_UNORM | gl::COMPRESSED_SRGB_ALPHA_BPTC_UNORM |
+        gl::COMPRESSED_RGB_BPTC_SIGNED_FLOAT | gl::COMPRESSED_RGB_BPTC_UNSIGNED_FLOAT |
+        gl::COMPRESSED_RGBA8_ETC2_EAC | gl::COMPRESSED_SRGB8_ALPHA8_ETC2_EAC |
+        gl::COMPRESSED_RG11_EAC | gl::COMPRESSED_SIGNED_RG11_EAC => Some((4, 4, 16)),
+        f if f >= COMPRESSED_RGBA_ASTC_4x4_KHR && f <= COMPRESSED_RGBA_ASTC_12x12_KHR => {
+            let (w, h) = ASTC_BLOCKS[(internal_format - COMPRESSED_RGBA_ASTC_4x4_KHR) as usize];
+            Some((w, h, 16))
+        },
+        f if f >= COMPRESSED_SRGB8_ALPHA8_ASTC_4x4_KHR &&
+            f <= COMPRESSED_SRGB8_ALPHA8_ASTC_12x12_KHR => {
+            let (w, h) = ASTC_BLOCKS[(internal_format - COMPRESSED_SRGB8_ALPHA8_ASTC_4x4_KHR) as usize];
+            Some((w, h, 16))
+        },
+        _ => None
+    }
+}
+
+// S3TC formats are not part of core OpenGL
+pub const COMPRESSED_RGB_S3TC_DXT1_EXT: GLenum = 0x83F0;
+pub const COMPRESSED_RGBA_S3TC_DXT1_EXT: GLenum = 0x83F1;
+pub const COMPRESSED_RGBA_S3TC_DXT3_EXT: GLenum = 0x83F2;
+pub const COMPRESSED_RGBA_S3TC_DXT5_EXT: GLenum = 0x83F3;
+pub const COMPRESSED_SRGB_S3TC_DXT1_EXT: GLenum = 0x8C4C;
+pub const COMPRESSED_SRGB_ALPHA_S3TC_DXT1_EXT: GLenum = 0x8C4D;
+pub const COMPRESSED_SRGB_ALPHA_S3TC_DXT3_EXT: GLenum = 0x8C4E;
+pub const COMPRESSED_SRGB_ALPHA_S3TC_DXT5_EXT: GLenum = 0x8C4F;
+
+// ETC1 and ASTC are also extensions on desktop OpenGL
+pub const COMPRESSED_ETC1_RGB8_OES: GLenum = 0x8D64;
+#[allow(non_upper_case_globals)]
+pub const COMPRESSED_RGBA_ASTC_4x4_KHR: GLenum = 0x93B0;
+#[allow(non_upper_case_globals)]
+pub const COMPRESSED_RGBA_ASTC_12x12_KHR: GLenum = 0x93BD;
+#[allow(non_upper_case_globals)]
+pub const COMPRESSED_SRGB8_ALPHA8_ASTC_4x4_KHR: GLenum = 0x93D0;
+#[allow(non_upper_case_globals)]
+pub const COMPRESSED_SRGB8_ALPHA8_ASTC_12x12_KHR: GLenum = 0x93DD;
+
 #[derive(Debug)]
 pub enum LoadError {
+    /// The file doesn't start with a KTX identifier
     MagicError,
-    HeaderError,
+    /// The endianness field holds neither of the expected values
+    EndiannessError(u32),
+    /// A header field has an invalid value, holds the field name and value
+    HeaderError(&'static str, i64),
+    /// The file ended early, holds what was being read, its byte offset and
+    /// the number of bytes it needed
+    TruncatedError(&'static str, usize, usize),
+    /// A key value pair is malformed, holds the pair's byte offset
+    KeyValueError(usize),
+    /// A mip level is smaller than its dimensions require, holds the level,
+    /// its byte offset, the image size and the expected image size
+    LevelSizeError(usize, usize, usize, usize),
+    /// A field doesn't start on a four byte boundary, holds what was being
+    /// read and its byte offset
+    MisalignedError(&'static str, usize),
+    /// A size calculation overflowed, holds what was being calculated
+    OverflowError(&'static str),
+    UnsupportedError(String),
     IoError(io::Error),
 }
 
@@ -67,7 +326,23 @@ impl fmt::Display for LoadError {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         match self {
             &LoadError::MagicError => write!(fmt, "Not a valid ktx file"),
-            &LoadError::HeaderError => write!(fmt, "Invalid ktx header"),
+            &LoadError::EndiannessError(v) =>
+                write!(fmt, "Invalid ktx endianness 0x{:08x}", v),
+            &LoadError::HeaderError(field, v) =>
+                write!(fmt, "Invalid ktx header field {}: {}", field, v),
+            &LoadError::TruncatedError(what, offset, needed) =>
+                write!(fmt, "Truncated ktx file reading {} at byte {}, {} bytes needed",
+                    what, offset, needed),
+            &LoadError::KeyValueError(offset) =>
+                write!(fmt, "Malformed ktx key value pair at byte {}", offset),
+            &LoadError::LevelSizeError(level, offset, size, expected) =>
+                write!(fmt, "Ktx mip level {} at byte {} holds {} bytes, expected at least {}",
+                    level, offset, size, expected),
+            &LoadError::MisalignedError(what, offset) =>
+                write!(fmt, "Misaligned ktx {} at byte {}", what, offset),
+            &LoadError::OverflowError(what) =>
+                write!(fmt, "Ktx {} size overflows", what),
+            &LoadError::UnsupportedError(ref s) => write!(fmt, "Unsupported ktx feature: {}", s),
             &LoadError::IoError(ref e) => e.fmt(fmt)
         }
     }
@@ -76,179 +351,1097 @@ impl fmt::Display for LoadError {
 #[macro_export]
 macro_rules! load_ktx_or_panic {
     ($path:expr) => (sb6::ktx::load($path).unwrap_or_else(
-            |e| { panic!("Error loading '{}': {}", $path, e) }))
+            |e| { panic!("Error loading '{}': {}", $path, e) }).name)
 }
 
 const IDENTIFIER: [u8; 12] =
     [ 0xAB, 0x4B, 0x54, 0x58, 0x20, 0x31, 0x31, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A ];
 
-fn calculate_stride(h: &Header, width: i32, pad: usize) -> Result<isize, LoadError> {
-    let channels = match h.gl_base_internal_format {
-        gl::RED => 1,
-        gl::RG => 2,
-        gl::BGR => 3,
-        gl::RGB => 3,
-        gl::BGRA => 4,
-        gl::RGBA => 4,
-        _ => return Err(LoadError::HeaderError)
-    };
-    Ok((((h.gl_type_size * channels * width as u32) as usize + ((pad - 1)) & !(pad - 1))) as isize)
+/// The key used to describe the logical orientation of the texture data
+pub const KEY_ORIENTATION: &'static str = "KTXorientation";
+
+const ENDIANNESS: u32 = 0x04030201;
+const ENDIANNESS_SWAPPED: u32 = 0x01020304;
+
+/// Returns the number of bytes needed to pad `size` to a multiple of four
+fn padding(size: usize) -> usize {
+    3 - ((size + 3) % 4)
 }
 
-fn calculate_face_size(h: &Header) -> Result<isize, LoadError> {
-    let stride = try!(calculate_stride(h, h.pixel_width, 4));
-    Ok(stride * h.pixel_height as isize)
+/// The direction an increasing texture coordinate moves in, see
+/// `KTXorientation` in the KTX specification
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Right,
+    Left,
+    Down,
+    Up,
+    In,
+    Out
 }
 
-pub fn load(filename: &str) -> Result<GLuint, LoadError> {
-    let mut file = try!(fs::File::open(&Path::new(filename)));
-    let mut bytes = Vec::new();
-    try!(file.read_to_end(&mut bytes));
-    let mut reader = BufferReader::new(bytes);
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Orientation {
+    pub s: Direction,
+    pub t: Direction,
+    pub r: Option<Direction>
+}
+
+impl Orientation {
+    /// Parses a `KTXorientation` value such as "S=r,T=d,R=i"
+    pub fn parse(value: &str) -> Option<Orientation> {
+        let mut s = None;
+        let mut t = None;
+        let mut r = None;
+        for item in value.split(',') {
+            let mut parts = item.split('=');
+            let axis = parts.next().map(|a| a.trim());
+            let dir = parts.next().map(|d| d.trim());
+            match (axis, dir) {
+                (Some("S"), Some("r")) => s = Some(Direction::Right),
+                (Some("S"), Some("l")) => s = Some(Direction::Left),
+                (Some("T"), Some("d")) => t = Some(Direction::Down),
+                (Some("T"), Some("u")) => t = Some(Direction::Up),
+                (Some("R"), Some("i")) => r = Some(Direction::In),
+                (Some("R"), Some("o")) => r = Some(Direction::Out),
+                _ => return None
+            }
+        }
+        match (s, t) {
+            (Some(s), Some(t)) => Some(Orientation { s: s, t: t, r: r }),
+            _ => None
+        }
+    }
 
-    // check header magic
-    let id = try!(reader.pop_slice::<u8>(IDENTIFIER.len()));
-    if id != IDENTIFIER {
-        debug!("identifier: {} != {}", str::from_utf8(&IDENTIFIER).unwrap(),
-            str::from_utf8(id).unwrap());
-        return Err(LoadError::MagicError)
+    /// Parses a KTX2 `KTXorientation` value such as "rd" or "rdi", one
+    /// letter for each of S, T and R
+    pub fn parse_ktx2(value: &str) -> Option<Orientation> {
+        let mut letters = value.chars();
+        let s = match letters.next() {
+            Some('r') => Direction::Right,
+            Some('l') => Direction::Left,
+            _ => return None
+        };
+        let t = match letters.next() {
+            Some('d') => Direction::Down,
+            Some('u') => Direction::Up,
+            _ => return None
+        };
+        let r = match letters.next() {
+            Some('i') => Some(Direction::In),
+            Some('o') => Some(Direction::Out),
+            None => None,
+            _ => return None
+        };
+        match letters.next() {
+            Some(_) => None,
+            None => Some(Orientation { s: s, t: t, r: r })
+        }
     }
+}
 
-    // check endianness
-    let endianness = try!(reader.pop_value::<u32>());
-    if *endianness == 0x01020304 {
-        // swap not impemented
-        return Err(LoadError::MagicError)
+impl fmt::Display for Orientation {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let letter = |d| match d {
+            Direction::Right => "r",
+            Direction::Left => "l",
+            Direction::Down => "d",
+            Direction::Up => "u",
+            Direction::In => "i",
+            Direction::Out => "o"
+        };
+        try!(write!(fmt, "S={},T={}", letter(self.s), letter(self.t)));
+        match self.r {
+            Some(r) => write!(fmt, ",R={}", letter(r)),
+            None => Ok(())
+        }
     }
+}
 
-    // read the rest of the header
-    let h = try!(reader.pop_value::<Header>());
+/// The key value pairs stored in a KTX file, in file order
+#[derive(Clone, Debug, Default)]
+pub struct KeyValueData {
+    pairs: Vec<(String, Vec<u8>)>
+}
 
-    // check for insanity
-    if h.pixel_width == 0 || (h.pixel_height == 0 && h.pixel_depth != 0) {
-        return Err(LoadError::HeaderError)
+impl KeyValueData {
+    pub fn new() -> KeyValueData {
+        KeyValueData { pairs: Vec::new() }
     }
 
-    // guess the target (texture type)
-    let target = if h.pixel_height == 0 {
-        if h.array_elements == 0 {
-            gl::TEXTURE_1D
+    /// Parses a block of key value pairs. `offset` is the position of the
+    /// block within the file and is only used for error reporting.
+    pub fn parse(data: &[u8], offset: usize, swapped: bool)
+                 -> Result<KeyValueData, LoadError> {
+        let mut kv = KeyValueData::new();
+        let mut pos = 0;
+        while data.len() - pos >= 4 {
+            let mut size = (data[pos] as u32) | (data[pos + 1] as u32) << 8 |
+                (data[pos + 2] as u32) << 16 | (data[pos + 3] as u32) << 24;
+            if swapped {
+                size = size.swap_bytes();
+            }
+            let start = pos + 4;
+            let size = size as usize;
+            if size > data.len() - start {
+                return Err(LoadError::KeyValueError(offset + pos))
+            }
+            let end = start + size;
+            // the key is a null terminated utf-8 string followed by the value
+            let pair = &data[start..end];
+            let key_len = match pair.iter().position(|&b| b == 0) {
+                Some(n) => n,
+                None => return Err(LoadError::KeyValueError(offset + pos))
+            };
+            let key = match str::from_utf8(&pair[..key_len]) {
+                Ok(k) => String::from(k),
+                Err(_) => return Err(LoadError::KeyValueError(offset + pos))
+            };
+            kv.pairs.push((key, pair[key_len + 1..].to_vec()));
+            pos = ::std::cmp::min(end + padding(size), data.len());
         }
-        else {
-            gl::TEXTURE_1D_ARRAY
+        Ok(kv)
+    }
+
+    pub fn len(&self) -> usize { self.pairs.len() }
+
+    pub fn is_empty(&self) -> bool { self.pairs.is_empty() }
+
+    /// Returns the raw value bytes for the given key
+    pub fn get(&self, key: &str) -> Option<&[u8]> {
+        self.pairs.iter().find(|p| p.0 == key).map(|p| &p.1[..])
+    }
+
+    /// Returns the value for the given key as a string, without the null
+    /// terminator. Returns None if the value is not valid utf-8.
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.get(key).and_then(|v| {
+            let v = match v.last() {
+                Some(&0) => &v[..v.len() - 1],
+                _ => v
+            };
+            str::from_utf8(v).ok()
+        })
+    }
+
+    /// Sets the value for the given key, replacing any existing value
+    pub fn insert(&mut self, key: &str, value: Vec<u8>) {
+        match self.pairs.iter().position(|p| p.0 == key) {
+            Some(i) => self.pairs[i].1 = value,
+            None => self.pairs.push((String::from(key), value))
         }
     }
-    else if h.pixel_depth == 0 {
-        if h.array_elements == 0 {
-            if h.faces == 0 {
-                gl::TEXTURE_2D
+
+    /// Sets a null terminated string value for the given key
+    pub fn insert_str(&mut self, key: &str, value: &str) {
+        let mut bytes = value.as_bytes().to_vec();
+        bytes.push(0);
+        self.insert(key, bytes);
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<Vec<u8>> {
+        self.pairs.iter().position(|p| p.0 == key)
+            .map(|i| self.pairs.remove(i).1)
+    }
+
+    pub fn iter<'a>(&'a self) -> ::std::slice::Iter<'a, (String, Vec<u8>)> {
+        self.pairs.iter()
+    }
+
+    /// Returns the parsed `KTXorientation` value if present and valid
+    pub fn orientation(&self) -> Option<Orientation> {
+        self.get_str(KEY_ORIENTATION).and_then(Orientation::parse)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Level {
+    offset: usize,
+    image_size: usize,
+    width: i32,
+    height: i32,
+    depth: i32
+}
+
+/// A KTX file parsed into memory without touching OpenGL.
+/// The image data is not copied out of the file buffer, the level and image
+/// accessors return slices into it.
+pub struct KtxImage {
+    header: Header,
+    key_values: KeyValueData,
+    levels: Vec<Level>,
+    reader: BufferReader,
+    swapped: bool
+}
+
+fn pop_value<'a, T>(reader: &'a mut BufferReader, what: &'static str)
+                   -> Result<&'a T, LoadError> {
+    let offset = reader.bytes_read();
+    if offset % mem::align_of::<T>() != 0 {
+        return Err(LoadError::MisalignedError(what, offset))
+    }
+    reader.pop_value::<T>().map_err(
+        |_| LoadError::TruncatedError(what, offset, mem::size_of::<T>()))
+}
+
+fn pop_slice<'a, T>(reader: &'a mut BufferReader, what: &'static str, size: usize)
+                    -> Result<&'a [T], LoadError> {
+    let offset = reader.bytes_read();
+    if offset % mem::align_of::<T>() != 0 {
+        return Err(LoadError::MisalignedError(what, offset))
+    }
+    reader.pop_slice::<T>(size).map_err(
+        |_| LoadError::TruncatedError(what, offset, size * mem::size_of::<T>()))
+}
+
+fn skip_bytes(reader: &mut BufferReader, what: &'static str, bytes: usize)
+              -> Result<(), LoadError> {
+    let offset = reader.bytes_read();
+    reader.skip_bytes(bytes).map_err(
+        |_| LoadError::TruncatedError(what, offset, bytes))
+}
+
+impl KtxImage {
+    /// Creates an image from a header, key value data and the image data for
+    /// each mip level. Each level holds every array layer, cube face and
+    /// depth slice in order, with rows padded to four bytes. The header's
+    /// `key_pair_bytes` is ignored and recalculated.
+    pub fn new(header: Header, key_values: KeyValueData, levels: Vec<Vec<u8>>)
+               -> Result<KtxImage, LoadError> {
+        try!(header.validate());
+        if levels.len() != header.num_levels() {
+            return Err(LoadError::HeaderError("mip_levels", levels.len() as i64))
+        }
+        let images_per_level = try!(header.images_per_level());
+        let mut images = Vec::with_capacity(levels.len());
+        for (i, level) in levels.iter().enumerate() {
+            if level.len() % images_per_level != 0 {
+                return Err(LoadError::LevelSizeError(i, 0, level.len(),
+                    images_per_level * (level.len() / images_per_level + 1)))
             }
-            else {
-                gl::TEXTURE_CUBE_MAP
+            let image_size = level.len() / images_per_level;
+            images.push(level.chunks(::std::cmp::max(image_size, 1))
+                .collect::<Vec<&[u8]>>());
+        }
+        let mut bytes = Vec::new();
+        try!(encode(&mut bytes, &header, &key_values, &images));
+        KtxImage::parse(bytes)
+    }
+
+    /// Maps the given KTX file into memory and parses it. The image data is
+    /// read straight from the mapping, the file must not be modified while
+    /// the image is alive.
+    pub fn open(filename: &str) -> Result<KtxImage, LoadError> {
+        KtxImage::from_buffer(try!(BufferReader::open(filename)))
+    }
+
+    /// Reads a KTX 1.1 or KTX2 file to the end of the given reader and parses it
+    pub fn read<R: Read>(mut reader: R) -> Result<KtxImage, LoadError> {
+        let mut bytes = Vec::new();
+        try!(reader.read_to_end(&mut bytes));
+        KtxImage::parse(bytes)
+    }
+
+    /// Parses a KTX 1.1 or KTX2 file from an owned byte buffer
+    pub fn parse(bytes: Vec<u8>) -> Result<KtxImage, LoadError> {
+        KtxImage::from_buffer(BufferReader::new(bytes))
+    }
+
+    fn from_buffer(mut reader: BufferReader) -> Result<KtxImage, LoadError> {
+        if reader.as_slice().starts_with(&ktx2::IDENTIFIER) {
+            return ktx2::parse_buffer(reader)
+        }
+
+        // check header magic
+        {
+            let id = try!(pop_slice::<u8>(&mut reader, "identifier", IDENTIFIER.len()));
+            if id != IDENTIFIER {
+                debug!("identifier: {:?} != {:?}", IDENTIFIER, id);
+                return Err(LoadError::MagicError)
             }
         }
-        else {
-            if h.faces == 0 {
-                gl::TEXTURE_2D_ARRAY
+
+        // check endianness
+        let swapped = match *try!(pop_value::<u32>(&mut reader, "endianness")) {
+            ENDIANNESS => false,
+            ENDIANNESS_SWAPPED => true,
+            v => return Err(LoadError::EndiannessError(v))
+        };
+
+        // read the rest of the header
+        let mut h = *try!(pop_value::<Header>(&mut reader, "header"));
+        if swapped {
+            h.swap_bytes();
+        }
+
+        // check for insanity
+        try!(h.validate());
+
+        let key_value_offset = reader.bytes_read();
+        let key_values = try!(KeyValueData::parse(
+                try!(pop_slice::<u8>(&mut reader, "key value data",
+                    h.key_pair_bytes as usize)),
+                key_value_offset, swapped));
+
+        // walk the mip levels, each one is prefixed with its image size
+        let is_cube = h.array_elements == 0 && h.faces == 6;
+        let images_per_level = try!(h.images_per_level());
+        let mut levels = Vec::with_capacity(h.num_levels());
+        for i in 0..h.num_levels() {
+            let level_offset = reader.bytes_read();
+            let mut image_size = *try!(pop_value::<u32>(&mut reader, "image size"));
+            if swapped {
+                image_size = image_size.swap_bytes();
+            }
+            let image_size = image_size as usize;
+            let offset = reader.bytes_read();
+
+            // every image needs some data and the images must divide the
+            // level evenly
+            if !is_cube && (image_size < images_per_level ||
+                            image_size % images_per_level != 0) {
+                let expected = (image_size / images_per_level + 1) * images_per_level;
+                return Err(LoadError::LevelSizeError(i, level_offset, image_size,
+                    expected))
+            }
+
+            // make sure each image holds at least as much data as the format
+            // requires for this level
+            if let Some(expected) = h.image_size(i) {
+                let actual = if is_cube {
+                    image_size
+                }
+                else {
+                    image_size / images_per_level
+                };
+                if actual < expected {
+                    return Err(LoadError::LevelSizeError(i, level_offset, actual,
+                        expected))
+                }
+            }
+
+            if is_cube {
+                // non-array cube maps store the size of a single face
+                let level_size = match (image_size + padding(image_size)).checked_mul(6) {
+                    Some(n) => n,
+                    None => return Err(LoadError::OverflowError("cube map level"))
+                };
+                try!(skip_bytes(&mut reader, "mip level", level_size));
             }
             else {
-                gl::TEXTURE_CUBE_MAP_ARRAY
+                try!(skip_bytes(&mut reader, "mip level", image_size));
+                // tolerate files that omit the padding after the last level
+                let remaining = reader.len() - reader.bytes_read();
+                try!(skip_bytes(&mut reader, "mip padding",
+                        ::std::cmp::min(padding(image_size), remaining)));
+            }
+            levels.push(Level {
+                offset: offset,
+                image_size: image_size,
+                width: level_dimension(h.pixel_width, i),
+                height: level_dimension(h.pixel_height, i),
+                depth: level_dimension(h.pixel_depth, i)
+            });
+        }
+
+        let mut image = KtxImage {
+            header: h,
+            key_values: key_values,
+            levels: levels,
+            reader: reader,
+            swapped: swapped
+        };
+
+        // convert the pixel data to native byte order in place
+        if swapped && h.gl_type_size > 1 {
+            for i in 0..image.num_levels() {
+                let (start, end) = image.level_range(i);
+                match image.reader.peek_slice_mut(start, end) {
+                    Ok(data) => swap_data(data, h.gl_type_size as usize),
+                    Err(_) => return Err(LoadError::TruncatedError("mip level",
+                        start, end - start))
+                }
             }
         }
+
+        Ok(image)
+    }
+
+    pub fn header(&self) -> &Header { &self.header }
+
+    pub fn target(&self) -> GLenum { self.header.target() }
+
+    /// Changes how the data is interpreted without converting it, e.g. from
+    /// `RGBA8` to `SRGB8_ALPHA8`. The new format must store pixels the same
+    /// way.
+    pub fn set_internal_format(&mut self, internal_format: GLenum) {
+        self.header.gl_internal_format = internal_format;
+    }
+
+    pub fn num_levels(&self) -> usize { self.levels.len() }
+
+    pub fn key_values(&self) -> &KeyValueData { &self.key_values }
+
+    /// Returns the width, height and depth of the given mip level.
+    /// Dimensions that are zero in the header stay zero at every level.
+    pub fn dimensions(&self, level: usize) -> (i32, i32, i32) {
+        let l = &self.levels[level];
+        (l.width, l.height, l.depth)
+    }
+
+    /// Returns true if the file was stored in the opposite byte order. The
+    /// header and image data have already been swapped to native order.
+    pub fn is_swapped(&self) -> bool { self.swapped }
+
+    /// Returns all of the image data for the given mip level
+    pub fn level(&self, level: usize) -> &[u8] {
+        let (start, end) = self.level_range(level);
+        self.reader.peek_slice(start, end).unwrap()
+    }
+
+    /// Returns the image data for a single array layer and cube face of the
+    /// given mip level. For 3D textures this contains every depth slice.
+    pub fn image(&self, level: usize, layer: usize, face: usize) -> &[u8] {
+        let num_layers = self.header.num_layers();
+        let num_faces = self.header.num_faces();
+        assert!(layer < num_layers && face < num_faces);
+        let l = &self.levels[level];
+        let (start, size) = if self.is_cube() {
+            (l.offset + face * (l.image_size + padding(l.image_size)),
+             l.image_size)
+        }
+        else {
+            let size = l.image_size / (num_layers * num_faces);
+            (l.offset + (layer * num_faces + face) * size, size)
+        };
+        self.reader.peek_slice(start, start + size).unwrap()
+    }
+
+    fn level_range(&self, level: usize) -> (usize, usize) {
+        let l = &self.levels[level];
+        let size = if self.is_cube() {
+            6 * (l.image_size + padding(l.image_size))
+        }
+        else {
+            l.image_size
+        };
+        (l.offset, l.offset + size)
+    }
+
+    fn is_cube(&self) -> bool {
+        self.header.array_elements == 0 && self.header.faces == 6
+    }
+}
+
+/// Reverses the bytes of each `type_size` sized element
+fn swap_data(data: &mut [u8], type_size: usize) {
+    match type_size {
+        2 | 4 | 8 => {
+            for element in data.chunks_mut(type_size) {
+                element.reverse();
+            }
+        },
+        _ => ()
+    }
+}
+
+fn level_dimension(size: i32, level: usize) -> i32 {
+    if size == 0 {
+        0
     }
     else {
-        gl::TEXTURE_3D
-    };
+        ::std::cmp::max(1, size >> level)
+    }
+}
 
-    let mut tex:u32 = 0;
-    unsafe {
-        gl::GenTextures(1, &mut tex);
-        gl::BindTexture(target, tex);
+/// Writes a value in native byte order, to match the image data
+fn write_u32<W: Write>(writer: &mut W, value: u32) -> Result<(), io::Error> {
+    let mut bytes = [value as u8, (value >> 8) as u8, (value >> 16) as u8,
+                     (value >> 24) as u8];
+    if cfg!(target_endian = "big") {
+        bytes.reverse();
+    }
+    writer.write_all(&bytes)
+}
+
+/// Writes a KTX file from the header, key value data and per level images,
+/// where each level lists its images in array layer then cube face order.
+/// Everything is written in native byte order, which the endianness field
+/// records for readers on other hosts.
+fn encode<W: Write>(writer: &mut W, header: &Header, key_values: &KeyValueData,
+                    levels: &[Vec<&[u8]>]) -> Result<(), io::Error> {
+    const ZEROES: [u8; 3] = [0; 3];
+
+    let key_pair_bytes = key_values.iter().fold(0, |total, &(ref key, ref value)| {
+        let size = key.len() + 1 + value.len();
+        total + 4 + size + padding(size)
+    });
+
+    try!(writer.write_all(&IDENTIFIER));
+    try!(write_u32(writer, ENDIANNESS));
+    for value in [header.gl_type, header.gl_type_size, header.gl_format,
+                  header.gl_internal_format, header.gl_base_internal_format,
+                  header.pixel_width as u32, header.pixel_height as u32,
+                  header.pixel_depth as u32, header.array_elements as u32,
+                  header.faces as u32, header.mip_levels as u32,
+                  key_pair_bytes as u32].iter() {
+        try!(write_u32(writer, *value));
+    }
+
+    for &(ref key, ref value) in key_values.iter() {
+        let size = key.len() + 1 + value.len();
+        try!(write_u32(writer, size as u32));
+        try!(writer.write_all(key.as_bytes()));
+        try!(writer.write_all(&[0]));
+        try!(writer.write_all(value));
+        try!(writer.write_all(&ZEROES[..padding(size)]));
+    }
+
+    let is_cube = header.array_elements == 0 && header.faces == 6;
+    for images in levels.iter() {
+        if is_cube {
+            // non-array cube maps store the size of a single face and pad
+            // each face rather than the whole level
+            let face_size = images.first().map_or(0, |face| face.len());
+            try!(write_u32(writer, face_size as u32));
+            for face in images.iter() {
+                try!(writer.write_all(face));
+                try!(writer.write_all(&ZEROES[..padding(face.len())]));
+            }
+        }
+        else {
+            let image_size = images.iter().fold(0, |total, image| total + image.len());
+            try!(write_u32(writer, image_size as u32));
+            for image in images.iter() {
+                try!(writer.write_all(image));
+            }
+            try!(writer.write_all(&ZEROES[..padding(image_size)]));
+        }
     }
 
-    // skip unused key pair bytes
-    try!(reader.skip_bytes(h.key_pair_bytes as usize));
+    Ok(())
+}
+
+/// Serialises an image as a KTX 1.1 file in native byte order
+pub fn write<W: Write>(writer: &mut W, image: &KtxImage) -> Result<(), io::Error> {
+    let h = image.header();
+    let levels = (0..image.num_levels()).map(|level| {
+        let mut images = Vec::with_capacity(h.num_layers() * h.num_faces());
+        for layer in 0..h.num_layers() {
+            for face in 0..h.num_faces() {
+                images.push(image.image(level, layer, face));
+            }
+        }
+        images
+    }).collect::<Vec<_>>();
+    encode(writer, h, image.key_values(), &levels)
+}
 
-    let data_size = reader.len() - reader.bytes_read();
-    let data = try!(reader.pop_slice::<u8>(data_size));
+/// Saves an image to the given KTX file
+pub fn save(filename: &str, image: &KtxImage) -> Result<(), io::Error> {
+    let mut file = try!(fs::File::create(&Path::new(filename)));
+    write(&mut file, image)
+}
 
-    let mip_levels = match h.mip_levels {
-        0 => 1,
-        n => n
+/// Reverses the row order of `data`, which holds `images` consecutive
+/// images of `rows` rows each
+fn flip_rows(data: &[u8], rows: usize, images: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let stride = data.len() / (rows * images);
+    if stride == 0 {
+        return data.to_vec()
+    }
+    for image in data.chunks(stride * rows) {
+        for row in image.chunks(stride).rev() {
+            out.extend(row.iter().cloned());
+        }
+    }
+    out
+}
+
+/// A texture object created from a KTX file along with the file's key value
+/// data
+pub struct Texture {
+    pub name: GLuint,
+    pub target: GLenum,
+    pub key_values: KeyValueData
+}
+
+/// Which mip levels a loaded texture gets
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MipPolicy {
+    /// Use the file's levels, generating the rest with `glGenerateMipmap` if
+    /// the file's `numberOfMipmapLevels` is 0
+    Auto,
+    /// Use the file's levels, generating the rest on the CPU with the given
+    /// filter if the file's `numberOfMipmapLevels` is 0
+    Cpu(mipmap::Filter),
+    /// Only upload the base level
+    BaseLevel
+}
+
+/// How a texture is created and sampled. The defaults match OpenGL's own
+/// sampler state.
+#[derive(Clone, Copy, Debug)]
+pub struct TextureOptions {
+    pub min_filter: GLenum,
+    pub mag_filter: GLenum,
+    pub wrap_s: GLenum,
+    pub wrap_t: GLenum,
+    pub wrap_r: GLenum,
+    /// Anisotropic filtering is enabled above 1, up to the context's limit
+    pub max_anisotropy: f32,
+    /// The source of the red, green, blue and alpha channels, e.g.
+    /// `[RED, RED, RED, ONE]` to show a single channel texture as grey
+    pub swizzle: Option<[GLenum; 4]>,
+    /// Treats the data as sRGB encoded if true or linear if false
+    pub srgb: Option<bool>,
+    pub mipmaps: MipPolicy
+}
+
+impl Default for TextureOptions {
+    fn default() -> TextureOptions {
+        TextureOptions {
+            min_filter: gl::NEAREST_MIPMAP_LINEAR,
+            mag_filter: gl::LINEAR,
+            wrap_s: gl::REPEAT,
+            wrap_t: gl::REPEAT,
+            wrap_r: gl::REPEAT,
+            max_anisotropy: 1.0,
+            swizzle: None,
+            srgb: None,
+            mipmaps: MipPolicy::Auto
+        }
+    }
+}
+
+// from GL 4.6 and EXT_texture_filter_anisotropic
+const TEXTURE_MAX_ANISOTROPY: GLenum = 0x84FE;
+const MAX_TEXTURE_MAX_ANISOTROPY: GLenum = 0x84FF;
+
+/// Returns the largest anisotropy the context supports, 1 if it doesn't
+/// support anisotropic filtering
+pub fn max_anisotropy() -> f32 {
+    let mut max = 1.0;
+    if gl_version() >= (4, 6) || has_extension("GL_ARB_texture_filter_anisotropic") ||
+        has_extension("GL_EXT_texture_filter_anisotropic") {
+        unsafe { gl::GetFloatv(MAX_TEXTURE_MAX_ANISOTROPY, &mut max) };
+    }
+    max
+}
+
+impl TextureOptions {
+    fn anisotropy(&self) -> Option<f32> {
+        if self.max_anisotropy <= 1.0 {
+            return None
+        }
+        let anisotropy = self.max_anisotropy.min(max_anisotropy());
+        if anisotropy > 1.0 { Some(anisotropy) } else { None }
+    }
+
+    /// Sets the sampler state and swizzle of the texture bound to `target`
+    pub fn apply(&self, target: GLenum) {
+        unsafe {
+            gl::TexParameteri(target, gl::TEXTURE_MIN_FILTER, self.min_filter as GLint);
+            gl::TexParameteri(target, gl::TEXTURE_MAG_FILTER, self.mag_filter as GLint);
+            gl::TexParameteri(target, gl::TEXTURE_WRAP_S, self.wrap_s as GLint);
+            gl::TexParameteri(target, gl::TEXTURE_WRAP_T, self.wrap_t as GLint);
+            gl::TexParameteri(target, gl::TEXTURE_WRAP_R, self.wrap_r as GLint);
+            if let Some(anisotropy) = self.anisotropy() {
+                gl::TexParameterf(target, TEXTURE_MAX_ANISOTROPY, anisotropy);
+            }
+            if let Some(swizzle) = self.swizzle {
+                let swizzle = [swizzle[0] as GLint, swizzle[1] as GLint,
+                               swizzle[2] as GLint, swizzle[3] as GLint];
+                gl::TexParameteriv(target, gl::TEXTURE_SWIZZLE_RGBA, swizzle.as_ptr());
+            }
+        }
+    }
+
+    /// Creates a sampler object with the filter, wrap and anisotropy
+    /// settings, for sharing them between textures with `glBindSampler`.
+    /// Swizzle is texture state and isn't included.
+    pub fn create_sampler(&self) -> GLuint {
+        let mut sampler = 0;
+        unsafe {
+            gl::GenSamplers(1, &mut sampler);
+            gl::SamplerParameteri(sampler, gl::TEXTURE_MIN_FILTER, self.min_filter as GLint);
+            gl::SamplerParameteri(sampler, gl::TEXTURE_MAG_FILTER, self.mag_filter as GLint);
+            gl::SamplerParameteri(sampler, gl::TEXTURE_WRAP_S, self.wrap_s as GLint);
+            gl::SamplerParameteri(sampler, gl::TEXTURE_WRAP_T, self.wrap_t as GLint);
+            gl::SamplerParameteri(sampler, gl::TEXTURE_WRAP_R, self.wrap_r as GLint);
+            if let Some(anisotropy) = self.anisotropy() {
+                gl::SamplerParameterf(sampler, TEXTURE_MAX_ANISOTROPY, anisotropy);
+            }
+        }
+        sampler
+    }
+}
+
+/// Returns the sRGB encoded or linear form of an internal format, or None
+/// if the format has no sRGB counterpart
+pub fn srgb_format(internal_format: GLenum, srgb: bool) -> Option<GLenum> {
+    const PAIRS: [(GLenum, GLenum); 10] = [
+        (gl::RGB8, gl::SRGB8),
+        (gl::RGBA8, gl::SRGB8_ALPHA8),
+        (COMPRESSED_RGB_S3TC_DXT1_EXT, COMPRESSED_SRGB_S3TC_DXT1_EXT),
+        (COMPRESSED_RGBA_S3TC_DXT1_EXT, COMPRESSED_SRGB_ALPHA_S3TC_DXT1_EXT),
+        (COMPRESSED_RGBA_S3TC_DXT3_EXT, COMPRESSED_SRGB_ALPHA_S3TC_DXT3_EXT),
+        (COMPRESSED_RGBA_S3TC_DXT5_EXT, COMPRESSED_SRGB_ALPHA_S3TC_DXT5_EXT),
+        (gl::COMPRESSED_RGBA_BPTC_UNORM, gl::COMPRESSED_SRGB_ALPHA_BPTC_UNORM),
+        (gl::COMPRESSED_RGB8_ETC2, gl::COMPRESSED_SRGB8_ETC2),
+        (gl::COMPRESSED_RGB8_PUNCHTHROUGH_ALPHA1_ETC2,
+         gl::COMPRESSED_SRGB8_PUNCHTHROUGH_ALPHA1_ETC2),
+        (gl::COMPRESSED_RGBA8_ETC2_EAC, gl::COMPRESSED_SRGB8_ALPHA8_ETC2_EAC)
+    ];
+    const ASTC_SRGB_OFFSET: GLenum =
+        COMPRESSED_SRGB8_ALPHA8_ASTC_4x4_KHR - COMPRESSED_RGBA_ASTC_4x4_KHR;
+    let f = internal_format;
+    if f >= COMPRESSED_RGBA_ASTC_4x4_KHR && f <= COMPRESSED_RGBA_ASTC_12x12_KHR {
+        return Some(if srgb { f + ASTC_SRGB_OFFSET } else { f })
+    }
+    if f >= COMPRESSED_SRGB8_ALPHA8_ASTC_4x4_KHR && f <= COMPRESSED_SRGB8_ALPHA8_ASTC_12x12_KHR {
+        return Some(if srgb { f } else { f - ASTC_SRGB_OFFSET })
+    }
+    PAIRS.iter().find(|p| p.0 == f || p.1 == f).map(|p| if srgb { p.1 } else { p.0 })
+}
+
+pub fn load(filename: &str) -> Result<Texture, LoadError> {
+    create_texture(try!(KtxImage::open(filename)), &TextureOptions::default())
+}
+
+/// Loads a texture, applying the given options
+pub fn load_with_options(filename: &str, options: &TextureOptions)
+                         -> Result<Texture, LoadError> {
+    create_texture(try!(KtxImage::open(filename)), options)
+}
+
+/// Loads a texture, generating any missing mip levels on the CPU with the
+/// given filter instead of with `glGenerateMipmap`
+pub fn load_with_mip_filter(filename: &str, filter: mipmap::Filter)
+                            -> Result<Texture, LoadError> {
+    let options = TextureOptions {
+        mipmaps: MipPolicy::Cpu(filter),
+        .. TextureOptions::default()
     };
+    load_with_options(filename, &options)
+}
+
+/// Loads a texture from KTX data held in memory, e.g. from `include_bytes!`
+pub fn load_from_bytes(bytes: &[u8]) -> Result<Texture, LoadError> {
+    create_texture(try!(KtxImage::parse(bytes.to_vec())), &TextureOptions::default())
+}
+
+/// Loads a texture from KTX data read to the end of `reader`
+pub fn load_from_reader<R: Read>(reader: R) -> Result<Texture, LoadError> {
+    create_texture(try!(KtxImage::read(reader)), &TextureOptions::default())
+}
 
+/// Creates a texture from a parsed image, applying the given options. The
+/// texture is left bound.
+pub fn create_texture(image: KtxImage, options: &TextureOptions) -> Result<Texture, LoadError> {
+    let image = try!(prepare_image(image, options));
+    let name = try!(upload_levels(&image, options.mipmaps != MipPolicy::BaseLevel));
+    let target = image.target();
+    options.apply(target);
+    Ok(Texture {
+        name: name,
+        target: target,
+        key_values: image.key_values().clone()
+    })
+}
+
+/// Applies the mip policy and sRGB override of the options to an image, as
+/// `create_texture` does before uploading it
+pub fn prepare_image(image: KtxImage, options: &TextureOptions) -> Result<KtxImage, LoadError> {
+    let mut image = match options.mipmaps {
+        MipPolicy::Cpu(filter) => try!(mipmap::complete(image, filter)),
+        MipPolicy::BaseLevel if image.num_levels() > 1 => {
+            let mut header = *image.header();
+            header.mip_levels = 1;
+            try!(KtxImage::new(header, image.key_values().clone(),
+                vec![image.level(0).to_vec()]))
+        },
+        _ => image
+    };
+    if let Some(srgb) = options.srgb {
+        let format = image.header().gl_internal_format;
+        match srgb_format(format, srgb) {
+            Some(f) => image.set_internal_format(f),
+            None => warn!("format 0x{:x} has no sRGB counterpart", format)
+        }
+    }
+    Ok(image)
+}
+
+fn gl_version() -> (GLint, GLint) {
+    let mut major = 0;
+    let mut minor = 0;
     unsafe {
-        match target {
-            gl::TEXTURE_1D => {
-                gl::TexStorage1D(gl::TEXTURE_1D, mip_levels,
-                    h.gl_internal_format, h.pixel_width);
-                gl::TexSubImage1D(gl::TEXTURE_1D, 0, 0, h.pixel_width,
-                    h.gl_format, h.gl_internal_format,
-                    mem::transmute(data.as_ptr()));
-            },
-            gl::TEXTURE_2D => {
-                gl::TexStorage2D(gl::TEXTURE_2D, mip_levels,
-                    h.gl_internal_format, h.pixel_width, h.pixel_height);
-                let mut ptr = mem::transmute(data.as_ptr());
-                let mut height = h.pixel_height;
-                let mut width = h.pixel_width;
-                gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
-                for i in (0..mip_levels) {
-                    gl::TexSubImage2D(gl::TEXTURE_2D, i, 0, 0, width, height,
-                        h.gl_format, h.gl_type, ptr);
-                    let stride = try!(calculate_stride(h, width, 1));
-                    ptr = ptr.offset(height as isize * stride);
-                    height >>= 1;
-                    width >>= 1;
-                    if height == 0 {
-                        height = 1;
+        gl::GetIntegerv(gl::MAJOR_VERSION, &mut major);
+        gl::GetIntegerv(gl::MINOR_VERSION, &mut minor);
+    }
+    (major, minor)
+}
+
+fn has_extension(name: &str) -> bool {
+    unsafe {
+        let mut count = 0;
+        gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut count);
+        (0..count as GLuint).any(|i| {
+            let ext = gl::GetStringi(gl::EXTENSIONS, i);
+            !ext.is_null() &&
+                CStr::from_ptr(ext as *const _).to_bytes() == name.as_bytes()
+        })
+    }
+}
+
+/// Returns true if the current context can sample the given compressed
+/// format
+pub fn is_compressed_format_supported(internal_format: GLenum) -> bool {
+    match internal_format {
+        COMPRESSED_RGB_S3TC_DXT1_EXT | COMPRESSED_RGBA_S3TC_DXT1_EXT |
+        COMPRESSED_RGBA_S3TC_DXT3_EXT | COMPRESSED_RGBA_S3TC_DXT5_EXT |
+        COMPRESSED_SRGB_S3TC_DXT1_EXT | COMPRESSED_SRGB_ALPHA_S3TC_DXT1_EXT |
+        COMPRESSED_SRGB_ALPHA_S3TC_DXT3_EXT | COMPRESSED_SRGB_ALPHA_S3TC_DXT5_EXT =>
+            has_extension("GL_EXT_texture_compression_s3tc"),
+        COMPRESSED_ETC1_RGB8_OES =>
+            has_extension("GL_OES_compressed_ETC1_RGB8_texture"),
+        gl::COMPRESSED_RGB8_ETC2 | gl::COMPRESSED_SRGB8_ETC2 |
+        gl::COMPRESSED_RGB8_PUNCHTHROUGH_ALPHA1_ETC2 |
+        gl::COMPRESSED_SRGB8_PUNCHTHROUGH_ALPHA1_ETC2 |
+        gl::COMPRESSED_RGBA8_ETC2_EAC | gl::COMPRESSED_SRGB8_ALPHA8_ETC2_EAC |
+        gl::COMPRESSED_R11_EAC | gl::COMPRESSED_SIGNED_R11_EAC |
+        gl::COMPRESSED_RG11_EAC | gl::COMPRESSED_SIGNED_RG11_EAC =>
+            gl_version() >= (4, 3) || has_extension("GL_ARB_ES3_compatibility"),
+        gl::COMPRESSED_RGBA_BPTC_UNORM | gl::COMPRESSED_SRGB_ALPHA_BPTC_UNORM |
+        gl::COMPRESSED_RGB_BPTC_SIGNED_FLOAT | gl::COMPRESSED_RGB_BPTC_UNSIGNED_FLOAT =>
+            gl_version() >= (4, 2) || has_extension("GL_ARB_texture_compression_bptc"),
+        f if f >= COMPRESSED_RGBA_ASTC_4x4_KHR && f <= COMPRESSED_SRGB8_ALPHA8_ASTC_12x12_KHR =>
+            has_extension("GL_KHR_texture_compression_astc_ldr"),
+        _ => true
+    }
+}
+
+/// The data for one `TexSubImage` call when uploading a KTX image
+pub struct SubImage<'a> {
+    /// The texture target, or the face target for cube map faces
+    pub target: GLenum,
+    pub level: usize,
+    pub width: i32,
+    pub height: i32,
+    pub depth: i32,
+    pub data: Cow<'a, [u8]>
+}
+
+/// Returns true if the rows of the image are flipped when it is uploaded,
+/// see `sub_images`. Decompressed copies of a compressed image must be
+/// uploaded with the orientation of the original.
+pub fn flips_on_upload(image: &KtxImage) -> bool {
+    let h = image.header();
+    let down = h.pixel_height != 0 &&
+        image.key_values().orientation().map_or(false, |o| o.t == Direction::Down);
+    if down && h.is_compressed() {
+        warn!("compressed format 0x{:x} can't be flipped, uploading T=d image upside down",
+            h.gl_internal_format);
+    }
+    down && !h.is_compressed()
+}
+
+/// Splits an image into the `TexSubImage` calls that upload it, one per mip
+/// level or one per face for cube maps. Images with a `KTXorientation` of
+/// `T=d` are flipped so that the first row uploaded is the bottom of the
+/// image. Compressed images can't be flipped without re-encoding their
+/// blocks, so they are uploaded as stored and a warning is logged. This
+/// also applies when they are decompressed on the CPU.
+pub fn sub_images<'a>(image: &'a KtxImage) -> Vec<SubImage<'a>> {
+    sub_images_flipped(image, flips_on_upload(image))
+}
+
+/// Splits an image into its `TexSubImage` calls like `sub_images`, only
+/// flipping the rows if `flip` is true
+pub fn sub_images_flipped<'a>(image: &'a KtxImage, flip: bool) -> Vec<SubImage<'a>> {
+    let h = image.header();
+    let target = image.target();
+    let mut subs = Vec::new();
+    for level in 0..image.num_levels() {
+        let (width, height, depth) = image.dimensions(level);
+        if target == gl::TEXTURE_CUBE_MAP {
+            for face in 0..6 {
+                let data = image.image(level, 0, face);
+                subs.push(SubImage {
+                    target: gl::TEXTURE_CUBE_MAP_POSITIVE_X + face as GLenum,
+                    level: level,
+                    width: width,
+                    height: height,
+                    depth: 1,
+                    data: if flip {
+                        Cow::Owned(flip_rows(data, height as usize, 1))
                     }
-                    if width == 0 {
-                        width = 1;
+                    else {
+                        Cow::Borrowed(data)
                     }
-                }
-            },
-            gl::TEXTURE_1D_ARRAY => {
-                gl::TexStorage2D(gl::TEXTURE_1D_ARRAY, mip_levels,
-                    h.gl_internal_format, h.pixel_width, h.array_elements);
-                gl::TexSubImage2D(gl::TEXTURE_1D_ARRAY, 0, 0, 0, h.pixel_width,
-                    h.array_elements, h.gl_format, h.gl_type,
-                    mem::transmute(data.as_ptr()));
-            }
-            gl::TEXTURE_2D_ARRAY => {
-                gl::TexStorage3D(gl::TEXTURE_2D_ARRAY, mip_levels,
-                    h.gl_internal_format, h.pixel_width, h.pixel_height,
-                    h.array_elements);
-                gl::TexSubImage3D(gl::TEXTURE_2D_ARRAY, 0, 0, 0, 0,
-                    h.pixel_width, h.pixel_height, h.array_elements,
-                    h.gl_format, h.gl_type, mem::transmute(data.as_ptr()));
-            },
-            gl::TEXTURE_CUBE_MAP => {
-                gl::TexStorage2D(gl::TEXTURE_CUBE_MAP, mip_levels,
-                    h.gl_internal_format, h.pixel_width, h.pixel_height);
-                let mut ptr = mem::transmute(data.as_ptr());
-                let face_size = try!(calculate_face_size(h));
-                for i in 0..h.faces as u32 {
-                    gl::TexSubImage2D(gl::TEXTURE_CUBE_MAP_POSITIVE_X + i,
-                        0, 0, 0, h.pixel_width, h.pixel_height, h.gl_format,
-                        h.gl_type, ptr);
-                    ptr = ptr.offset(face_size);
-                }
-            },
-            gl::TEXTURE_CUBE_MAP_ARRAY => {
-                gl::TexStorage3D(gl::TEXTURE_CUBE_MAP_ARRAY, mip_levels,
-                    h.gl_internal_format, h.pixel_width, h.pixel_height,
-                    h.array_elements);
-                gl::TexSubImage3D(gl::TEXTURE_CUBE_MAP_ARRAY, 0, 0, 0, 0,
-                    h.pixel_width, h.pixel_height, h.faces * h.array_elements,
-                    h.gl_format, h.gl_type, mem::transmute(data.as_ptr()));
-            },
-            _ => return Err(LoadError::HeaderError)
-        }
-        if mip_levels == 1 {
-            gl::GenerateMipmap(target);
+                });
+            }
+            continue
         }
+        // layers and cube map array faces are addressed as rows or slices
+        let (height, depth) = match target {
+            gl::TEXTURE_1D => (1, 1),
+            gl::TEXTURE_1D_ARRAY => (h.array_elements, 1),
+            gl::TEXTURE_2D => (height, 1),
+            gl::TEXTURE_2D_ARRAY => (height, h.array_elements),
+            gl::TEXTURE_CUBE_MAP_ARRAY => (height, 6 * h.array_elements),
+            _ => (height, depth)
+        };
+        let data = image.level(level);
+        subs.push(SubImage {
+            target: target,
+            level: level,
+            width: width,
+            height: height,
+            depth: depth,
+            data: if flip {
+                Cow::Owned(flip_rows(data, height as usize, depth as usize))
+            }
+            else {
+                Cow::Borrowed(data)
+            }
+        });
     }
+    subs
+}
 
+/// Creates a texture object with immutable storage for `levels` mip levels
+/// of the image, leaving it bound
+pub unsafe fn create_storage(h: &Header, levels: usize) -> Result<GLuint, LoadError> {
+    let target = h.target();
+    let levels = levels as GLsizei;
+    let mut tex = 0;
+    gl::GenTextures(1, &mut tex);
+    gl::BindTexture(target, tex);
+    match target {
+        gl::TEXTURE_1D =>
+            gl::TexStorage1D(target, levels, h.gl_internal_format, h.pixel_width),
+        gl::TEXTURE_1D_ARRAY =>
+            gl::TexStorage2D(target, levels, h.gl_internal_format, h.pixel_width,
+                h.array_elements),
+        gl::TEXTURE_2D | gl::TEXTURE_CUBE_MAP =>
+            gl::TexStorage2D(target, levels, h.gl_internal_format, h.pixel_width,
+                h.pixel_height),
+        gl::TEXTURE_2D_ARRAY =>
+            gl::TexStorage3D(target, levels, h.gl_internal_format, h.pixel_width,
+                h.pixel_height, h.array_elements),
+        gl::TEXTURE_3D =>
+            gl::TexStorage3D(target, levels, h.gl_internal_format, h.pixel_width,
+                h.pixel_height, h.pixel_depth),
+        // cube map arrays are addressed by layer-face
+        gl::TEXTURE_CUBE_MAP_ARRAY =>
+            gl::TexStorage3D(target, levels, h.gl_internal_format, h.pixel_width,
+                h.pixel_height, 6 * h.array_elements),
+        _ => {
+            gl::DeleteTextures(1, &tex);
+            return Err(LoadError::UnsupportedError(format!(
+                "texture target 0x{:x}", target)))
+        }
+    }
     Ok(tex)
 }
+
+/// Uploads `size` bytes of pixel data for one sub image to the bound
+/// texture. `pixels` is a client pointer, or an offset when a pixel unpack
+/// buffer is bound.
+pub unsafe fn tex_sub_image(h: &Header, sub: &SubImage, size: usize, pixels: *const GLvoid) {
+    let level = sub.level as GLint;
+    match (sub.target, h.is_compressed()) {
+        (gl::TEXTURE_1D, true) =>
+            gl::CompressedTexSubImage1D(sub.target, level, 0, sub.width,
+                h.gl_internal_format, size as GLsizei, pixels),
+        (gl::TEXTURE_1D, false) =>
+            gl::TexSubImage1D(sub.target, level, 0, sub.width, h.gl_format,
+                h.gl_type, pixels),
+        (gl::TEXTURE_2D_ARRAY, true) | (gl::TEXTURE_3D, true) |
+        (gl::TEXTURE_CUBE_MAP_ARRAY, true) =>
+            gl::CompressedTexSubImage3D(sub.target, level, 0, 0, 0, sub.width,
+                sub.height, sub.depth, h.gl_internal_format, size as GLsizei, pixels),
+        (gl::TEXTURE_2D_ARRAY, false) | (gl::TEXTURE_3D, false) |
+        (gl::TEXTURE_CUBE_MAP_ARRAY, false) =>
+            gl::TexSubImage3D(sub.target, level, 0, 0, 0, sub.width, sub.height,
+                sub.depth, h.gl_format, h.gl_type, pixels),
+        // 2D textures, 1D arrays and cube map faces
+        (_, true) =>
+            gl::CompressedTexSubImage2D(sub.target, level, 0, 0, sub.width,
+                sub.height, h.gl_internal_format, size as GLsizei, pixels),
+        (_, false) =>
+            gl::TexSubImage2D(sub.target, level, 0, 0, sub.width, sub.height,
+                h.gl_format, h.gl_type, pixels)
+    }
+}
+
+/// Returns true if the texture should have its mip chain generated by
+/// OpenGL once uploaded, which KTX requests with a `numberOfMipmapLevels`
+/// of 0. Compressed formats can't be rendered to so mipmaps can't be
+/// generated for them.
+pub fn needs_generated_mipmaps(h: &Header) -> bool {
+    h.mip_levels == 0 && !h.is_compressed()
+}
+
+/// Returns the number of mip levels to allocate for the image, a full
+/// chain if the rest will be generated by OpenGL
+pub fn storage_levels(h: &Header) -> usize {
+    if needs_generated_mipmaps(h) {
+        // array layers don't shrink
+        let depth = if h.target() == gl::TEXTURE_3D { h.pixel_depth } else { 0 };
+        let size = ::std::cmp::max(h.pixel_width, ::std::cmp::max(h.pixel_height, depth));
+        32 - (::std::cmp::max(size, 1) as u32).leading_zeros() as usize
+    }
+    else {
+        h.num_levels()
+    }
+}
+
+/// Creates a texture object from a parsed KTX image, see `sub_images` for
+/// how the data is arranged. Compressed images in a format the context
+/// doesn't support are decompressed on the CPU where possible.
+pub fn upload(image: &KtxImage) -> Result<GLuint, LoadError> {
+    upload_levels(image, true)
+}
+
+/// Uploads an image, only generating missing mip levels if `generate` is
+/// true
+fn upload_levels(image: &KtxImage, generate: bool) -> Result<GLuint, LoadError> {
+    let h = image.header();
+    // decompressed data is uploaded the same way as the original would be
+    let flip = flips_on_upload(image);
+
+    if h.is_compressed() && !is_compressed_format_supported(h.gl_internal_format) {
+        debug!("compressed format 0x{:x} not supported, decompressing",
+            h.gl_internal_format);
+        return match decompress::decompress(image) {
+            Some(decompressed) => upload_image(&decompressed, generate, flip),
+            None => Err(LoadError::UnsupportedError(format!(
+                "compressed format 0x{:x} can't be sampled or decompressed",
+                h.gl_internal_format)))
+        }
+    }
+
+    upload_image(image, generate, flip)
+}
+
+fn upload_image(image: &KtxImage, generate: bool, flip: bool) -> Result<GLuint, LoadError> {
+    let h = image.header();
+    unsafe {
+        let generate = generate && needs_generated_mipmaps(h);
+        let levels = if generate { storage_levels(h) } else { h.num_levels() };
+        let tex = try!(create_storage(h, levels));
+        // KTX rows are padded to four bytes
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+        for sub in sub_images_flipped(image, flip) {
+            tex_sub_image(h, &sub, sub.data.len(), sub.data.as_ptr() as *const GLvoid);
+        }
+        if generate {
+            gl::GenerateMipmap(h.target());
+        }
+        Ok(tex)
+    }
+}
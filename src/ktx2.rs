@@ -0,0 +1,309 @@
+/*
+ * Copyright (c) 2014 Cameron Hart
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a
+ * copy of this software and associated documentation files (the "Software"),
+ * to deal in the Software without restriction, including without limitation
+ * the rights to use, copy, modify, merge, publish, distribute, sublicense,
+ * and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+ * THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+ * FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+ * DEALINGS IN THE SOFTWARE.
+ */
+
+extern crate gl;
+
+use gl::types::*;
+use ktx;
+use ktx::{Header, KeyValueData, KtxImage, LoadError, Orientation};
+use reader::BufferReader;
+use std::mem;
+
+pub const IDENTIFIER: [u8; 12] =
+    [ 0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A ];
+
+const SUPERCOMPRESSION_NONE: u32 = 0;
+
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+struct Ktx2Header {
+    vk_format: u32,
+    type_size: u32,
+    pixel_width: u32,
+    pixel_height: u32,
+    pixel_depth: u32,
+    layer_count: u32,
+    face_count: u32,
+    level_count: u32,
+    supercompression_scheme: u32
+}
+
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+struct Ktx2Index {
+    dfd_byte_offset: u32,
+    dfd_byte_length: u32,
+    kvd_byte_offset: u32,
+    kvd_byte_length: u32,
+    sgd_byte_offset: u64,
+    sgd_byte_length: u64
+}
+
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+struct Ktx2LevelIndex {
+    byte_offset: u64,
+    byte_length: u64,
+    uncompressed_byte_length: u64
+}
+
+/// The GL equivalent of a VkFormat
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GlFormat {
+    pub internal_format: GLenum,
+    pub format: GLenum,
+    pub ty: GLenum,
+    pub type_size: u32,
+    pub base_internal_format: GLenum,
+    /// Bytes per texel for uncompressed formats, zero for compressed formats
+    pub texel_size: usize
+}
+
+// vkFormat, internal format, format, type, type size, base internal format,
+// texel size
+const FORMATS: &'static [(u32, GLenum, GLenum, GLenum, u32, GLenum, usize)] = &[
+    (9, gl::R8, gl::RED, gl::UNSIGNED_BYTE, 1, gl::RED, 1),
+    (10, gl::R8_SNORM, gl::RED, gl::BYTE, 1, gl::RED, 1),
+    (13, gl::R8UI, gl::RED_INTEGER, gl::UNSIGNED_BYTE, 1, gl::RED, 1),
+    (14, gl::R8I, gl::RED_INTEGER, gl::BYTE, 1, gl::RED, 1),
+    (16, gl::RG8, gl::RG, gl::UNSIGNED_BYTE, 1, gl::RG, 2),
+    (17, gl::RG8_SNORM, gl::RG, gl::BYTE, 1, gl::RG, 2),
+    (20, gl::RG8UI, gl::RG_INTEGER, gl::UNSIGNED_BYTE, 1, gl::RG, 2),
+    (21, gl::RG8I, gl::RG_INTEGER, gl::BYTE, 1, gl::RG, 2),
+    (23, gl::RGB8, gl::RGB, gl::UNSIGNED_BYTE, 1, gl::RGB, 3),
+    (24, gl::RGB8_SNORM, gl::RGB, gl::BYTE, 1, gl::RGB, 3),
+    (27, gl::RGB8UI, gl::RGB_INTEGER, gl::UNSIGNED_BYTE, 1, gl::RGB, 3),
+    (28, gl::RGB8I, gl::RGB_INTEGER, gl::BYTE, 1, gl::RGB, 3),
+    (29, gl::SRGB8, gl::RGB, gl::UNSIGNED_BYTE, 1, gl::RGB, 3),
+    (30, gl::RGB8, gl::BGR, gl::UNSIGNED_BYTE, 1, gl::RGB, 3),
+    (36, gl::SRGB8, gl::BGR, gl::UNSIGNED_BYTE, 1, gl::RGB, 3),
+    (37, gl::RGBA8, gl::RGBA, gl::UNSIGNED_BYTE, 1, gl::RGBA, 4),
+    (38, gl::RGBA8_SNORM, gl::RGBA, gl::BYTE, 1, gl::RGBA, 4),
+    (41, gl::RGBA8UI, gl::RGBA_INTEGER, gl::UNSIGNED_BYTE, 1, gl::RGBA, 4),
+    (42, gl::RGBA8I, gl::RGBA_INTEGER, gl::BYTE, 1, gl::RGBA, 4),
+    (43, gl::SRGB8_ALPHA8, gl::RGBA, gl::UNSIGNED_BYTE, 1, gl::RGBA, 4),
+    (44, gl::RGBA8, gl::BGRA, gl::UNSIGNED_BYTE, 1, gl::RGBA, 4),
+    (50, gl::SRGB8_ALPHA8, gl::BGRA, gl::UNSIGNED_BYTE, 1, gl::RGBA, 4),
+    (64, gl::RGB10_A2, gl::RGBA, gl::UNSIGNED_INT_2_10_10_10_REV, 4, gl::RGBA, 4),
+    (70, gl::R16, gl::RED, gl::UNSIGNED_SHORT, 2, gl::RED, 2),
+    (71, gl::R16_SNORM, gl::RED, gl::SHORT, 2, gl::RED, 2),
+    (74, gl::R16UI, gl::RED_INTEGER, gl::UNSIGNED_SHORT, 2, gl::RED, 2),
+    (75, gl::R16I, gl::RED_INTEGER, gl::SHORT, 2, gl::RED, 2),
+    (76, gl::R16F, gl::RED, gl::HALF_FLOAT, 2, gl::RED, 2),
+    (77, gl::RG16, gl::RG, gl::UNSIGNED_SHORT, 2, gl::RG, 4),
+    (78, gl::RG16_SNORM, gl::RG, gl::SHORT, 2, gl::RG, 4),
+    (81, gl::RG16UI, gl::RG_INTEGER, gl::UNSIGNED_SHORT, 2, gl::RG, 4),
+    (82, gl::RG16I, gl::RG_INTEGER, gl::SHORT, 2, gl::RG, 4),
+    (83, gl::RG16F, gl::RG, gl::HALF_FLOAT, 2, gl::RG, 4),
+    (84, gl::RGB16, gl::RGB, gl::UNSIGNED_SHORT, 2, gl::RGB, 6),
+    (85, gl::RGB16_SNORM, gl::RGB, gl::SHORT, 2, gl::RGB, 6),
+    (88, gl::RGB16UI, gl::RGB_INTEGER, gl::UNSIGNED_SHORT, 2, gl::RGB, 6),
+    (89, gl::RGB16I, gl::RGB_INTEGER, gl::SHORT, 2, gl::RGB, 6),
+    (90, gl::RGB16F, gl::RGB, gl::HALF_FLOAT, 2, gl::RGB, 6),
+    (91, gl::RGBA16, gl::RGBA, gl::UNSIGNED_SHORT, 2, gl::RGBA, 8),
+    (92, gl::RGBA16_SNORM, gl::RGBA, gl::SHORT, 2, gl::RGBA, 8),
+    (95, gl::RGBA16UI, gl::RGBA_INTEGER, gl::UNSIGNED_SHORT, 2, gl::RGBA, 8),
+    (96, gl::RGBA16I, gl::RGBA_INTEGER, gl::SHORT, 2, gl::RGBA, 8),
+    (97, gl::RGBA16F, gl::RGBA, gl::HALF_FLOAT, 2, gl::RGBA, 8),
+    (98, gl::R32UI, gl::RED_INTEGER, gl::UNSIGNED_INT, 4, gl::RED, 4),
+    (99, gl::R32I, gl::RED_INTEGER, gl::INT, 4, gl::RED, 4),
+    (100, gl::R32F, gl::RED, gl::FLOAT, 4, gl::RED, 4),
+    (101, gl::RG32UI, gl::RG_INTEGER, gl::UNSIGNED_INT, 4, gl::RG, 8),
+    (102, gl::RG32I, gl::RG_INTEGER, gl::INT, 4, gl::RG, 8),
+    (103, gl::RG32F, gl::RG, gl::FLOAT, 4, gl::RG, 8),
+    (104, gl::RGB32UI, gl::RGB_INTEGER, gl::UNSIGNED_INT, 4, gl::RGB, 12),
+    (105, gl::RGB32I, gl::RGB_INTEGER, gl::INT, 4, gl::RGB, 12),
+    (106, gl::RGB32F, gl::RGB, gl::FLOAT, 4, gl::RGB, 12),
+    (107, gl::RGBA32UI, gl::RGBA_INTEGER, gl::UNSIGNED_INT, 4, gl::RGBA, 16),
+    (108, gl::RGBA32I, gl::RGBA_INTEGER, gl::INT, 4, gl::RGBA, 16),
+    (109, gl::RGBA32F, gl::RGBA, gl::FLOAT, 4, gl::RGBA, 16),
+    (122, gl::R11F_G11F_B10F, gl::RGB, gl::UNSIGNED_INT_10F_11F_11F_REV, 4, gl::RGB, 4),
+    (123, gl::RGB9_E5, gl::RGB, gl::UNSIGNED_INT_5_9_9_9_REV, 4, gl::RGB, 4),
+    (131, ktx::COMPRESSED_RGB_S3TC_DXT1_EXT, 0, 0, 1, gl::RGB, 0),
+    (132, ktx::COMPRESSED_SRGB_S3TC_DXT1_EXT, 0, 0, 1, gl::RGB, 0),
+    (133, ktx::COMPRESSED_RGBA_S3TC_DXT1_EXT, 0, 0, 1, gl::RGBA, 0),
+    (134, ktx::COMPRESSED_SRGB_ALPHA_S3TC_DXT1_EXT, 0, 0, 1, gl::RGBA, 0),
+    (135, ktx::COMPRESSED_RGBA_S3TC_DXT3_EXT, 0, 0, 1, gl::RGBA, 0),
+    (136, ktx::COMPRESSED_SRGB_ALPHA_S3TC_DXT3_EXT, 0, 0, 1, gl::RGBA, 0),
+    (137, ktx::COMPRESSED_RGBA_S3TC_DXT5_EXT, 0, 0, 1, gl::RGBA, 0),
+    (138, ktx::COMPRESSED_SRGB_ALPHA_S3TC_DXT5_EXT, 0, 0, 1, gl::RGBA, 0),
+    (139, gl::COMPRESSED_RED_RGTC1, 0, 0, 1, gl::RED, 0),
+    (140, gl::COMPRESSED_SIGNED_RED_RGTC1, 0, 0, 1, gl::RED, 0),
+    (141, gl::COMPRESSED_RG_RGTC2, 0, 0, 1, gl::RG, 0),
+    (142, gl::COMPRESSED_SIGNED_RG_RGTC2, 0, 0, 1, gl::RG, 0),
+    (143, gl::COMPRESSED_RGB_BPTC_UNSIGNED_FLOAT, 0, 0, 1, gl::RGB, 0),
+    (144, gl::COMPRESSED_RGB_BPTC_SIGNED_FLOAT, 0, 0, 1, gl::RGB, 0),
+    (145, gl::COMPRESSED_RGBA_BPTC_UNORM, 0, 0, 1, gl::RGBA, 0),
+    (146, gl::COMPRESSED_SRGB_ALPHA_BPTC_UNORM, 0, 0, 1, gl::RGBA, 0),
+    (147, gl::COMPRESSED_RGB8_ETC2, 0, 0, 1, gl::RGB, 0),
+    (148, gl::COMPRESSED_SRGB8_ETC2, 0, 0, 1, gl::RGB, 0),
+    (149, gl::COMPRESSED_RGB8_PUNCHTHROUGH_ALPHA1_ETC2, 0, 0, 1, gl::RGBA, 0),
+    (150, gl::COMPRESSED_SRGB8_PUNCHTHROUGH_ALPHA1_ETC2, 0, 0, 1, gl::RGBA, 0),
+    (151, gl::COMPRESSED_RGBA8_ETC2_EAC, 0, 0, 1, gl::RGBA, 0),
+    (152, gl::COMPRESSED_SRGB8_ALPHA8_ETC2_EAC, 0, 0, 1, gl::RGBA, 0),
+    (153, gl::COMPRESSED_R11_EAC, 0, 0, 1, gl::RED, 0),
+    (154, gl::COMPRESSED_SIGNED_R11_EAC, 0, 0, 1, gl::RED, 0),
+    (155, gl::COMPRESSED_RG11_EAC, 0, 0, 1, gl::RG, 0),
+    (156, gl::COMPRESSED_SIGNED_RG11_EAC, 0, 0, 1, gl::RG, 0),
+];
+
+/// Returns the GL format for the given VkFormat, if it is supported
+pub fn gl_format(vk_format: u32) -> Option<GlFormat> {
+    FORMATS.iter().find(|f| f.0 == vk_format).map(|f| GlFormat {
+        internal_format: f.1,
+        format: f.2,
+        ty: f.3,
+        type_size: f.4,
+        base_internal_format: f.5,
+        texel_size: f.6
+    })
+}
+
+/// Copies the first `rows` tightly packed rows into rows padded to four
+/// bytes as KTX 1.1 expects
+fn pad_rows(data: &[u8], row_size: usize, rows: usize) -> Vec<u8> {
+    let padded_size = (row_size + 3) & !3;
+    let mut out = Vec::with_capacity(rows * padded_size);
+    for row in data.chunks(row_size).take(rows) {
+        out.extend_from_slice(row);
+        out.resize(out.len() + padded_size - row_size, 0);
+    }
+    out
+}
+
+fn truncated(what: &'static str, offset: usize, needed: usize) -> LoadError {
+    LoadError::TruncatedError(what, offset, needed)
+}
+
+fn peek_slice<'a>(reader: &'a BufferReader, what: &'static str, start: usize,
+                  length: usize) -> Result<&'a [u8], LoadError> {
+    match start.checked_add(length) {
+        Some(end) => reader.peek_slice(start, end).map_err(
+            |_| truncated(what, start, length)),
+        None => Err(LoadError::OverflowError(what))
+    }
+}
+
+/// Parses a KTX2 file into a `KtxImage`, converting the format and layout to
+/// their KTX 1.1 equivalents so both containers share the GL upload path.
+/// Only files without supercompression are supported.
+pub fn parse(bytes: Vec<u8>) -> Result<KtxImage, LoadError> {
+    parse_buffer(BufferReader::new(bytes))
+}
+
+pub(crate) fn parse_buffer(mut reader: BufferReader) -> Result<KtxImage, LoadError> {
+
+    // check header magic
+    {
+        let id = try!(reader.pop_slice::<u8>(IDENTIFIER.len()).map_err(
+                |_| truncated("identifier", 0, IDENTIFIER.len())));
+        if id != IDENTIFIER {
+            return Err(LoadError::MagicError)
+        }
+    }
+
+    let h = *try!(reader.pop_value::<Ktx2Header>().map_err(
+            |_| truncated("header", IDENTIFIER.len(), mem::size_of::<Ktx2Header>())));
+    let index_offset = reader.bytes_read();
+    let index = *try!(reader.pop_value::<Ktx2Index>().map_err(
+            |_| truncated("index", index_offset, mem::size_of::<Ktx2Index>())));
+    debug!("{:?} {:?}", h, index);
+
+    if h.supercompression_scheme != SUPERCOMPRESSION_NONE {
+        return Err(LoadError::UnsupportedError(format!(
+            "supercompression scheme {}", h.supercompression_scheme)))
+    }
+
+    let format = match gl_format(h.vk_format) {
+        Some(f) => f,
+        None => return Err(LoadError::UnsupportedError(format!(
+            "vkFormat {}", h.vk_format)))
+    };
+
+    let header = Header {
+        gl_type: format.ty,
+        gl_type_size: format.type_size,
+        gl_format: format.format,
+        gl_internal_format: format.internal_format,
+        gl_base_internal_format: format.base_internal_format,
+        pixel_width: h.pixel_width as i32,
+        pixel_height: h.pixel_height as i32,
+        pixel_depth: h.pixel_depth as i32,
+        array_elements: h.layer_count as i32,
+        faces: h.face_count as i32,
+        mip_levels: h.level_count as i32,
+        key_pair_bytes: 0
+    };
+
+    try!(header.validate());
+
+    let level_index_offset = reader.bytes_read();
+    let level_index = try!(reader.pop_slice::<Ktx2LevelIndex>(header.num_levels())
+        .map_err(|_| truncated("level index", level_index_offset,
+            header.num_levels() * mem::size_of::<Ktx2LevelIndex>()))).to_vec();
+
+    let kvd_start = index.kvd_byte_offset as usize;
+    let kvd_length = index.kvd_byte_length as usize;
+    let mut key_values = try!(KeyValueData::parse(
+            try!(peek_slice(&reader, "key value data", kvd_start, kvd_length)),
+            kvd_start, false));
+    // KTX2 orientations are a letter per axis, store them the KTX 1.1 way
+    let orientation = key_values.get_str(ktx::KEY_ORIENTATION).and_then(Orientation::parse_ktx2);
+    if let Some(orientation) = orientation {
+        key_values.insert_str(ktx::KEY_ORIENTATION, &orientation.to_string());
+    }
+
+    let images_per_level = header.num_layers() * header.num_faces();
+    let mut levels = Vec::with_capacity(level_index.len());
+    for (i, l) in level_index.iter().enumerate() {
+        let start = l.byte_offset as usize;
+        let data = try!(peek_slice(&reader, "mip level", start, l.byte_length as usize));
+        // KTX2 rows are tightly packed, the size after padding is checked
+        // before copying anything
+        let row_size = format.texel_size *
+            ::std::cmp::max(1, h.pixel_width >> i) as usize;
+        let padded_size = (row_size + 3) & !3;
+        let available = if row_size != padded_size {
+            data.len() / row_size * padded_size
+        }
+        else {
+            data.len()
+        };
+        let expected = match header.image_size(i).map(|size| size.checked_mul(images_per_level)) {
+            Some(Some(n)) => n,
+            Some(None) => return Err(LoadError::OverflowError("mip level")),
+            None => available
+        };
+        if available < expected {
+            return Err(LoadError::LevelSizeError(i, start, available, expected))
+        }
+        levels.push(if row_size != padded_size {
+            pad_rows(data, row_size, expected / padded_size)
+        }
+        else {
+            data[..expected].to_vec()
+        });
+    }
+
+    KtxImage::new(header, key_values, levels)
+}
@@ -24,15 +24,32 @@
 
 #[macro_use] extern crate log;
 
+extern crate flate2;
 extern crate gl;
 extern crate glfw;
+extern crate memmap2;
 extern crate num;
+extern crate serde_json;
 
 pub use app::*;
 
 mod app;
+pub mod atlas;
+pub mod convert;
+pub mod decompress;
+pub mod envmap;
+pub mod gltf;
+pub mod image;
 pub mod ktx;
+pub mod ktx2;
+pub mod mesh;
+pub mod mipmap;
+pub mod obj;
 pub mod object;
+pub mod ply;
 pub mod program;
+pub mod readback;
 pub mod shader;
+pub mod stl;
+pub mod stream;
 mod reader;
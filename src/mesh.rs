@@ -0,0 +1,217 @@
+/*
+ * Copyright (c) 2014 Cameron Hart
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a
+ * copy of this software and associated documentation files (the "Software"),
+ * to deal in the Software without restriction, including without limitation
+ * the rights to use, copy, modify, merge, publish, distribute, sublicense,
+ * and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+ * THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+ * FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+ * DEALINGS IN THE SOFTWARE.
+ */
+
+extern crate gl;
+
+use gltf;
+use gltf::GltfError;
+use obj;
+use obj::ObjError;
+use object::{LoadError, Object, SbmMesh, SubObject, VertexAttrib};
+use ply;
+use ply::PlyError;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use stl;
+use stl::StlError;
+
+/// An indexed triangle mesh with separate attribute arrays, as built by the
+/// model importers before it's packed into an `SbmMesh`
+#[derive(Clone, Debug, Default)]
+pub struct MeshData {
+    pub positions: Vec<[f32; 3]>,
+    /// Empty or one per position
+    pub normals: Vec<[f32; 3]>,
+    /// Empty or one per position
+    pub texcoords: Vec<[f32; 2]>,
+    /// Three per triangle
+    pub indices: Vec<u32>,
+    /// Ranges of `indices`, empty to draw the whole mesh as one
+    pub sub_objects: Vec<SubObject>
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+/// Returns the cross product of two vectors
+pub fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+/// Returns the normal of a triangle, scaled by twice its area
+pub fn face_normal(a: [f32; 3], b: [f32; 3], c: [f32; 3]) -> [f32; 3] {
+    cross(sub(b, a), sub(c, a))
+}
+
+/// Scales a vector to unit length, leaving zero vectors alone
+pub fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let length = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    if length > 0.0 {
+        [v[0] / length, v[1] / length, v[2] / length]
+    }
+    else {
+        v
+    }
+}
+
+impl MeshData {
+    pub fn new() -> MeshData {
+        MeshData::default()
+    }
+
+    /// Replaces the normals with smooth ones, averaging the normals of the
+    /// triangles around each vertex weighted by their area. Vertices that
+    /// aren't shared between triangles get flat normals.
+    pub fn generate_normals(&mut self) {
+        let mut normals = vec![[0.0f32; 3]; self.positions.len()];
+        for triangle in self.indices.chunks(3).filter(|t| t.len() == 3) {
+            let n = face_normal(self.positions[triangle[0] as usize],
+                                self.positions[triangle[1] as usize],
+                                self.positions[triangle[2] as usize]);
+            for &i in triangle {
+                let normal = &mut normals[i as usize];
+                for c in 0..3 {
+                    normal[c] += n[c];
+                }
+            }
+        }
+        self.normals = normals.into_iter().map(normalize).collect();
+    }
+
+    /// Merges vertices whose attributes are identical, so meshes stored as
+    /// separate triangles can share vertices and get smooth normals
+    pub fn weld(&mut self) {
+        let mut vertices = HashMap::new();
+        let mut remap = Vec::with_capacity(self.positions.len());
+        let mut kept = Vec::new();
+        for i in 0..self.positions.len() {
+            let bits = |v: &[f32]| v.iter().map(|c| c.to_bits()).collect::<Vec<u32>>();
+            let mut key = bits(&self.positions[i]);
+            if let Some(n) = self.normals.get(i) {
+                key.extend(bits(n));
+            }
+            if let Some(t) = self.texcoords.get(i) {
+                key.extend(bits(t));
+            }
+            let next = kept.len() as u32;
+            let vertex = *vertices.entry(key).or_insert(next);
+            if vertex == next {
+                kept.push(i);
+            }
+            remap.push(vertex);
+        }
+        self.positions = kept.iter().map(|&i| self.positions[i]).collect();
+        if !self.normals.is_empty() {
+            self.normals = kept.iter().map(|&i| self.normals[i]).collect();
+        }
+        if !self.texcoords.is_empty() {
+            self.texcoords = kept.iter().map(|&i| self.texcoords[i]).collect();
+        }
+        for index in self.indices.iter_mut() {
+            *index = remap[*index as usize];
+        }
+    }
+
+    /// Packs the mesh into an `SbmMesh` with each attribute stored one after
+    /// another: position at location 0, then normal and texcoord at 1 and 2
+    /// if present
+    pub fn to_sbm(&self, comments: Vec<String>) -> Result<SbmMesh, LoadError> {
+        let mut attribs = Vec::new();
+        let mut data = Vec::new();
+        {
+            let mut add = |name: &str, size: u32, values: Vec<f32>| {
+                attribs.push(VertexAttrib {
+                    name: String::from(name),
+                    size: size,
+                    ty: gl::FLOAT,
+                    stride: 0,
+                    normalized: false,
+                    data_offset: data.len() as u32
+                });
+                for v in values {
+                    data.extend_from_slice(&v.to_bits().to_le_bytes());
+                }
+            };
+            add("position", 3, self.positions.iter().flat_map(|p| p.to_vec()).collect());
+            if !self.normals.is_empty() {
+                add("normal", 3, self.normals.iter().flat_map(|n| n.to_vec()).collect());
+            }
+            if !self.texcoords.is_empty() {
+                add("texcoord", 2, self.texcoords.iter().flat_map(|t| t.to_vec()).collect());
+            }
+        }
+        SbmMesh::new(attribs, data, self.positions.len() as u32, Some(&self.indices),
+            self.sub_objects.clone(), comments)
+    }
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    SbmError(LoadError),
+    ObjError(ObjError),
+    GltfError(GltfError),
+    PlyError(PlyError),
+    StlError(StlError)
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &ImportError::SbmError(ref e) => e.fmt(fmt),
+            &ImportError::ObjError(ref e) => e.fmt(fmt),
+            &ImportError::GltfError(ref e) => e.fmt(fmt),
+            &ImportError::PlyError(ref e) => e.fmt(fmt),
+            &ImportError::StlError(ref e) => e.fmt(fmt)
+        }
+    }
+}
+
+/// Reads a mesh in any of the supported formats, picked by the file's
+/// extension: .obj, .gltf, .glb, .ply or .stl, otherwise SBM
+pub fn open(filename: &str) -> Result<SbmMesh, ImportError> {
+    let extension = Path::new(filename).extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+    match extension.as_ref().map(|e| &e[..]) {
+        Some("obj") => obj::open(filename).and_then(|m| Ok(try!(m.to_sbm())))
+            .map_err(ImportError::ObjError),
+        Some("gltf") | Some("glb") => gltf::open(filename).map(|m| m.mesh)
+            .map_err(ImportError::GltfError),
+        Some("ply") => ply::open(filename).and_then(|m| Ok(try!(m.to_sbm(vec![]))))
+            .map_err(ImportError::PlyError),
+        Some("stl") => stl::open(filename).and_then(|m| Ok(try!(m.to_sbm(vec![]))))
+            .map_err(ImportError::StlError),
+        _ => SbmMesh::open(filename).map_err(ImportError::SbmError)
+    }
+}
+
+/// Reads a mesh in any of the supported formats and creates an `Object`
+/// from it
+pub fn load_object(filename: &str) -> Result<Object, ImportError> {
+    let mesh = try!(open(filename));
+    let mut object = Object::new();
+    object.load_mesh(&mesh);
+    Ok(object)
+}
@@ -0,0 +1,219 @@
+/*
+ * Copyright (c) 2014 Cameron Hart
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a
+ * copy of this software and associated documentation files (the "Software"),
+ * to deal in the Software without restriction, including without limitation
+ * the rights to use, copy, modify, merge, publish, distribute, sublicense,
+ * and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+ * THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+ * FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+ * DEALINGS IN THE SOFTWARE.
+ */
+
+extern crate gl;
+
+use convert;
+use ktx::{Header, KtxImage, LoadError};
+use std::cmp;
+use std::f32::consts::PI;
+
+/// The filter used to downsample each mip level
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Filter {
+    /// Averages each 2x2 block, fast but blurry
+    Box,
+    /// A Kaiser windowed sinc, sharper than box with little ringing
+    Kaiser,
+    /// A three lobed Lanczos windowed sinc, the sharpest of the three
+    Lanczos
+}
+
+impl Filter {
+    /// Parses a filter name such as "box" or "kaiser"
+    pub fn parse(name: &str) -> Option<Filter> {
+        match &name.to_lowercase()[..] {
+            "box" => Some(Filter::Box),
+            "kaiser" => Some(Filter::Kaiser),
+            "lanczos" => Some(Filter::Lanczos),
+            _ => None
+        }
+    }
+
+    /// Returns the radius of the filter in destination pixels
+    fn support(&self) -> f32 {
+        match *self {
+            Filter::Box => 0.5,
+            Filter::Kaiser | Filter::Lanczos => 3.0
+        }
+    }
+
+    fn evaluate(&self, x: f32) -> f32 {
+        let x = x.abs();
+        match *self {
+            Filter::Box => if x <= 0.5 { 1.0 } else { 0.0 },
+            Filter::Kaiser => {
+                const ALPHA: f32 = 4.0;
+                let t = x / self.support();
+                if t >= 1.0 {
+                    0.0
+                }
+                else {
+                    sinc(x) * bessel_i0(ALPHA * (1.0 - t * t).sqrt()) / bessel_i0(ALPHA)
+                }
+            },
+            Filter::Lanczos => {
+                if x >= self.support() {
+                    0.0
+                }
+                else {
+                    sinc(x) * sinc(x / self.support())
+                }
+            }
+        }
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1.0e-5 {
+        1.0
+    }
+    else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// The zeroth order modified Bessel function of the first kind
+fn bessel_i0(x: f32) -> f32 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let mut k = 1.0;
+    while term > sum * 1.0e-8 {
+        term *= (x / (2.0 * k)) * (x / (2.0 * k));
+        sum += term;
+        k += 1.0;
+    }
+    sum
+}
+
+/// Returns true if mip levels can be generated for the image's format
+pub fn is_supported(header: &Header) -> bool {
+    !header.is_compressed() && convert::pixel_format(header).is_some()
+}
+
+/// Returns the source pixels and normalised weights contributing to each
+/// destination pixel when resampling from `src_size` to `dst_size`. Samples
+/// past the edges are clamped.
+pub fn weights(src_size: usize, dst_size: usize, filter: Filter) -> Vec<Vec<(usize, f32)>> {
+    let scale = src_size as f32 / dst_size as f32;
+    let radius = filter.support() * scale;
+    (0..dst_size).map(|i| {
+        let center = (i as f32 + 0.5) * scale;
+        let first = (center - radius).floor() as isize;
+        let last = (center + radius).ceil() as isize;
+        let mut taps: Vec<(usize, f32)> = Vec::new();
+        for j in first..last + 1 {
+            let w = filter.evaluate((j as f32 + 0.5 - center) / scale);
+            if w == 0.0 {
+                continue
+            }
+            let j = cmp::max(0, cmp::min(j, src_size as isize - 1)) as usize;
+            match taps.iter().position(|t| t.0 == j) {
+                Some(n) => taps[n].1 += w,
+                None => taps.push((j, w))
+            }
+        }
+        let total = taps.iter().fold(0.0, |sum, t| sum + t.1);
+        for t in taps.iter_mut() {
+            t.1 /= total;
+        }
+        taps
+    }).collect()
+}
+
+/// Resamples a volume of `size` pixels along one axis
+fn resample(src: &[f32], size: [usize; 3], channels: usize, axis: usize,
+            dst_size: usize, filter: Filter) -> Vec<f32> {
+    let taps = weights(size[axis], dst_size, filter);
+    let mut dst_dims = size;
+    dst_dims[axis] = dst_size;
+    let mut dst = vec![0.0; dst_dims[0] * dst_dims[1] * dst_dims[2] * channels];
+    let index = |p: [usize; 3], dims: [usize; 3]| ((p[2] * dims[1] + p[1]) * dims[0] + p[0]) * channels;
+    for z in 0..dst_dims[2] {
+        for y in 0..dst_dims[1] {
+            for x in 0..dst_dims[0] {
+                let p = [x, y, z];
+                let d = index(p, dst_dims);
+                for &(j, w) in &taps[p[axis]] {
+                    let mut s = p;
+                    s[axis] = j;
+                    let s = index(s, size);
+                    for c in 0..channels {
+                        dst[d + c] += src[s + c] * w;
+                    }
+                }
+            }
+        }
+    }
+    dst
+}
+
+/// Builds a full mip chain from the image's base level. Every array layer
+/// and cube face is filtered separately and sRGB formats are filtered in
+/// linear space. Only uncompressed formats are supported.
+pub fn generate(image: &KtxImage, filter: Filter) -> Result<KtxImage, LoadError> {
+    let mut header = *image.header();
+    let format = match convert::pixel_format(&header) {
+        Some(f) if !header.is_compressed() => f,
+        _ => return Err(LoadError::UnsupportedError(format!(
+            "mipmap generation for format 0x{:x}", header.gl_internal_format)))
+    };
+    let (width, height, depth) = image.dimensions(0);
+    let base = [width as usize, cmp::max(height, 1) as usize, cmp::max(depth, 1) as usize];
+    let max_size = cmp::max(base[0], cmp::max(base[1], base[2]));
+    let num_levels = (0usize.leading_zeros() - max_size.leading_zeros()) as usize;
+
+    let mut levels = vec![Vec::new(); num_levels];
+    for layer in 0..header.num_layers() {
+        for face in 0..header.num_faces() {
+            let data = image.image(0, layer, face);
+            levels[0].extend_from_slice(data);
+            let mut values = convert::decode_pixels(data, &format, base);
+            let mut size = base;
+            for level in 1..num_levels {
+                for axis in 0..3 {
+                    let next = cmp::max(1, size[axis] / 2);
+                    if next != size[axis] {
+                        values = resample(&values, size, format.channels, axis, next, filter);
+                        size[axis] = next;
+                    }
+                }
+                levels[level].extend(convert::encode_pixels(&values, &format, size));
+            }
+        }
+    }
+
+    header.mip_levels = num_levels as i32;
+    KtxImage::new(header, image.key_values().clone(), levels)
+}
+
+/// Returns the image with a full mip chain, generating one if the file asks
+/// for it with a `numberOfMipmapLevels` of 0 and the format is supported
+pub fn complete(image: KtxImage, filter: Filter) -> Result<KtxImage, LoadError> {
+    if image.header().mip_levels != 0 || !is_supported(image.header()) {
+        Ok(image)
+    }
+    else {
+        generate(&image, filter)
+    }
+}
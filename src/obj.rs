@@ -0,0 +1,356 @@
+/*
+ * Copyright (c) 2014 Cameron Hart
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a
+ * copy of this software and associated documentation files (the "Software"),
+ * to deal in the Software without restriction, including without limitation
+ * the rights to use, copy, modify, merge, publish, distribute, sublicense,
+ * and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+ * THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+ * FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+ * DEALINGS IN THE SOFTWARE.
+ */
+
+use mesh::MeshData;
+use object;
+use object::{LoadError, Object, SbmMesh, SubObject};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+
+/// A material from an MTL file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Material {
+    pub name: String,
+    pub ambient: [f32; 3],
+    pub diffuse: [f32; 3],
+    pub specular: [f32; 3],
+    pub shininess: f32,
+    /// The opacity, 1 is opaque
+    pub dissolve: f32,
+    /// The diffuse texture file, relative to the MTL file
+    pub diffuse_map: Option<String>
+}
+
+impl Material {
+    fn new(name: &str) -> Material {
+        Material {
+            name: String::from(name),
+            ambient: [0.2; 3],
+            diffuse: [0.8; 3],
+            specular: [1.0; 3],
+            shininess: 0.0,
+            dissolve: 1.0,
+            diffuse_map: None
+        }
+    }
+}
+
+/// The group name and material of a sub-object
+#[derive(Clone, Debug, PartialEq)]
+pub struct Group {
+    pub name: String,
+    pub material: Option<String>
+}
+
+/// A model imported from a Wavefront OBJ file
+#[derive(Clone, Debug)]
+pub struct ObjModel {
+    pub mesh: MeshData,
+    /// One per sub-object of the mesh
+    pub groups: Vec<Group>,
+    /// The MTL files named by `mtllib` statements
+    pub material_libraries: Vec<String>,
+    pub materials: Vec<Material>
+}
+
+impl ObjModel {
+    /// Returns the material with the given name
+    pub fn material(&self, name: &str) -> Option<&Material> {
+        self.materials.iter().find(|m| m.name == name)
+    }
+
+    /// Packs the model into an `SbmMesh`, recording each sub-object's group
+    /// and material in a comment
+    pub fn to_sbm(&self) -> Result<SbmMesh, LoadError> {
+        let comments = self.groups.iter().map(|g| match g.material {
+            Some(ref material) => format!("g {}\nusemtl {}", g.name, material),
+            None => format!("g {}", g.name)
+        }).collect();
+        self.mesh.to_sbm(comments)
+    }
+}
+
+#[derive(Debug)]
+pub enum ObjError {
+    /// A malformed statement, holds the line number and a description
+    ParseError(usize, &'static str),
+    /// A face refers to a vertex that doesn't exist, holds the line number
+    IndexError(usize),
+    NoFacesError,
+    MeshError(LoadError),
+    IoError(io::Error)
+}
+
+impl From<io::Error> for ObjError {
+    fn from(e: io::Error) -> ObjError {
+        ObjError::IoError(e)
+    }
+}
+
+impl From<LoadError> for ObjError {
+    fn from(e: LoadError) -> ObjError {
+        ObjError::MeshError(e)
+    }
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &ObjError::ParseError(line, s) => write!(fmt, "Invalid OBJ file: {} on line {}", s, line),
+            &ObjError::IndexError(line) => write!(fmt, "Invalid OBJ file: vertex index out of range on line {}", line),
+            &ObjError::NoFacesError => write!(fmt, "OBJ file has no faces"),
+            &ObjError::MeshError(ref e) => e.fmt(fmt),
+            &ObjError::IoError(ref e) => e.fmt(fmt)
+        }
+    }
+}
+
+fn read_text(filename: &Path) -> Result<String, io::Error> {
+    let mut file = try!(fs::File::open(filename));
+    let mut text = String::new();
+    try!(file.read_to_string(&mut text));
+    Ok(text)
+}
+
+/// Parses the first `count` numbers of a statement, using `default` for
+/// any past the first that are missing
+fn floats<'a, I: Iterator<Item=&'a str>>(words: I, count: usize, default: f32, line: usize)
+                                         -> Result<[f32; 3], ObjError> {
+    let mut out = [default; 3];
+    let mut n = 0;
+    for word in words.take(count) {
+        out[n] = try!(word.parse().map_err(|_| ObjError::ParseError(line, "invalid number")));
+        n += 1;
+    }
+    if n == 0 || (n < count && default.is_nan()) {
+        return Err(ObjError::ParseError(line, "missing number"))
+    }
+    Ok(out)
+}
+
+/// Resolves a one-based or negative relative index into an array of `count`
+/// elements. Empty indices, as in `1//2`, are None.
+fn index(part: Option<&str>, count: usize, line: usize) -> Result<Option<usize>, ObjError> {
+    let part = match part {
+        Some(p) if !p.is_empty() => p,
+        _ => return Ok(None)
+    };
+    let i: i64 = try!(part.parse().map_err(|_| ObjError::ParseError(line, "invalid index")));
+    let resolved = if i < 0 { count as i64 + i } else { i - 1 };
+    if i == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(ObjError::IndexError(line))
+    }
+    Ok(Some(resolved as usize))
+}
+
+/// Returns everything after the statement keyword
+fn rest<'a>(line: &'a str, keyword: &str) -> &'a str {
+    line.trim_start()[keyword.len()..].trim()
+}
+
+/// Parses an OBJ file held in memory. Polygons are split into triangle fans
+/// and each distinct position, texcoord and normal combination becomes one
+/// vertex. A sub-object starts at every `o`, `g` or `usemtl` statement that
+/// is followed by faces. Smooth normals are generated if the file has none.
+/// Materials aren't loaded, see `open`.
+pub fn parse(text: &str) -> Result<ObjModel, ObjError> {
+    let mut positions = Vec::new();
+    let mut texcoords = Vec::new();
+    let mut normals = Vec::new();
+    let mut vertices = HashMap::new();
+    let mut keys = Vec::new();
+    let mut corners = Vec::new();
+    let mut mesh = MeshData::new();
+    let mut groups = Vec::new();
+    let mut group = Group { name: String::from("default"), material: None };
+    let mut group_start = 0;
+    let mut libraries = Vec::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let number = i + 1;
+        let line = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => line
+        };
+        let mut words = line.split_whitespace();
+        let keyword = match words.next() {
+            Some(keyword) => keyword,
+            None => continue
+        };
+        match keyword {
+            "v" => positions.push(try!(floats(words, 3, ::std::f32::NAN, number))),
+            "vn" => normals.push(try!(floats(words, 3, ::std::f32::NAN, number))),
+            "vt" => {
+                let t = try!(floats(words, 2, 0.0, number));
+                texcoords.push([t[0], t[1]]);
+            },
+            "f" => {
+                corners.clear();
+                for word in words {
+                    let mut parts = word.split('/');
+                    let v = match try!(index(parts.next(), positions.len(), number)) {
+                        Some(v) => v,
+                        None => return Err(ObjError::ParseError(number, "face vertex without a position"))
+                    };
+                    let vt = try!(index(parts.next(), texcoords.len(), number));
+                    let vn = try!(index(parts.next(), normals.len(), number));
+                    let next = keys.len() as u32;
+                    let vertex = *vertices.entry((v, vt, vn)).or_insert(next);
+                    if vertex == next {
+                        keys.push((v, vt, vn));
+                    }
+                    corners.push(vertex);
+                }
+                if corners.len() < 3 {
+                    return Err(ObjError::ParseError(number, "face with fewer than three vertices"))
+                }
+                for k in 1..corners.len() - 1 {
+                    mesh.indices.extend_from_slice(&[corners[0], corners[k], corners[k + 1]]);
+                }
+            },
+            "o" | "g" | "usemtl" => {
+                if mesh.indices.len() > group_start {
+                    mesh.sub_objects.push(SubObject {
+                        first: group_start as u32,
+                        count: (mesh.indices.len() - group_start) as u32
+                    });
+                    groups.push(group.clone());
+                    group_start = mesh.indices.len();
+                }
+                let value = rest(line, keyword);
+                if keyword == "usemtl" {
+                    group.material = if value.is_empty() { None } else { Some(String::from(value)) };
+                }
+                else {
+                    group.name = String::from(if value.is_empty() { "default" } else { value });
+                }
+            },
+            "mtllib" => libraries.push(String::from(rest(line, keyword))),
+            // smoothing groups, lines, points and free-form geometry
+            _ => {}
+        }
+    }
+    if mesh.indices.is_empty() {
+        return Err(ObjError::NoFacesError)
+    }
+    if mesh.indices.len() > group_start {
+        mesh.sub_objects.push(SubObject {
+            first: group_start as u32,
+            count: (mesh.indices.len() - group_start) as u32
+        });
+        groups.push(group);
+    }
+
+    let has_texcoords = keys.iter().any(|k| k.1.is_some());
+    let has_normals = keys.iter().any(|k| k.2.is_some());
+    for &(v, vt, vn) in &keys {
+        mesh.positions.push(positions[v]);
+        if has_texcoords {
+            mesh.texcoords.push(vt.map_or([0.0; 2], |t| texcoords[t]));
+        }
+        if has_normals {
+            mesh.normals.push(vn.map_or([0.0; 3], |n| normals[n]));
+        }
+    }
+    if !has_normals {
+        mesh.generate_normals();
+    }
+
+    Ok(ObjModel {
+        mesh: mesh,
+        groups: groups,
+        material_libraries: libraries,
+        materials: Vec::new()
+    })
+}
+
+/// Parses an MTL file held in memory
+pub fn parse_mtl(text: &str) -> Result<Vec<Material>, ObjError> {
+    let mut materials: Vec<Material> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let number = i + 1;
+        let line = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => line
+        };
+        let mut words = line.split_whitespace();
+        let keyword = match words.next() {
+            Some(keyword) => keyword,
+            None => continue
+        };
+        if keyword == "newmtl" {
+            materials.push(Material::new(rest(line, keyword)));
+            continue
+        }
+        let material = match materials.last_mut() {
+            Some(m) => m,
+            None => return Err(ObjError::ParseError(number, "statement before newmtl"))
+        };
+        match keyword {
+            "Ka" => material.ambient = try!(floats(words, 3, ::std::f32::NAN, number)),
+            "Kd" => material.diffuse = try!(floats(words, 3, ::std::f32::NAN, number)),
+            "Ks" => material.specular = try!(floats(words, 3, ::std::f32::NAN, number)),
+            "Ns" => material.shininess = try!(floats(words, 1, 0.0, number))[0],
+            "d" => material.dissolve = try!(floats(words, 1, 0.0, number))[0],
+            "Tr" => material.dissolve = 1.0 - try!(floats(words, 1, 0.0, number))[0],
+            // the file name follows any options
+            "map_Kd" => material.diffuse_map = words.last().map(String::from),
+            _ => {}
+        }
+    }
+    Ok(materials)
+}
+
+/// Reads and parses the given OBJ file along with the MTL files it names,
+/// which are looked for next to it. MTL files that can't be read are
+/// skipped with a warning.
+pub fn open(filename: &str) -> Result<ObjModel, ObjError> {
+    let path = Path::new(filename);
+    let mut model = try!(parse(&try!(read_text(path))));
+    let dir = path.parent().unwrap_or(Path::new(""));
+    for library in &model.material_libraries {
+        match read_text(&dir.join(library)) {
+            Ok(text) => model.materials.extend(try!(parse_mtl(&text))),
+            Err(e) => warn!("couldn't read material library '{}': {}", library, e)
+        }
+    }
+    Ok(model)
+}
+
+/// Imports an OBJ file and creates an `Object` from it
+pub fn load_object(filename: &str) -> Result<Object, ObjError> {
+    let mesh = try!(try!(open(filename)).to_sbm());
+    let mut object = Object::new();
+    object.load_mesh(&mesh);
+    Ok(object)
+}
+
+/// Imports an OBJ file and saves it as an SBM file
+pub fn convert(obj_filename: &str, sbm_filename: &str) -> Result<(), ObjError> {
+    let mesh = try!(try!(open(obj_filename)).to_sbm());
+    Ok(try!(object::save(sbm_filename, &mesh)))
+}
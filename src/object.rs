@@ -28,7 +28,7 @@ use gl::types::*;
 use std::fmt;
 use std::fs;
 use std::io;
-use std::io::Read;
+use std::io::{Read, Write};
 use std::mem;
 use std::path::Path;
 use std::ptr;
@@ -49,31 +49,52 @@ const COMMENT_TYPE: u32 = fourcc!('C','M','N','T');
 
 const VERTEX_ATTRIB_FLAG_NORMALIZED: u32 = 0x00000001;
 
+/// A mesh header flag marking the sub-objects of an indexed mesh as ranges
+/// of indices. Without it they're ranges of vertices and the whole index
+/// buffer is drawn, as sb6 does.
+///
+/// This is an extension to the SB6M format: the header `flags` field is
+/// always zero in the sb6 media pack and the C++ loader ignores it, so
+/// files with the flag still load there, only drawing every sub-object as
+/// the whole mesh. Bit 0 is the only flag defined; `SbmMesh::new` sets it
+/// for every indexed mesh it builds.
+pub const MESH_FLAG_INDEX_RANGES: u32 = 0x00000001;
+
+const MAGIC: &'static [u8] = b"SB6M";
+
+#[derive(Clone, Copy)]
+#[repr(C)]
 struct MeshHeader {
     size: u32,
     num_chunks: u32,
     flags: u32
 }
 
+#[derive(Clone, Copy)]
+#[repr(C)]
 struct ChunkHeader {
     chunk_type: u32,
     size: u32
 }
 
+#[derive(Clone, Copy)]
+#[repr(C)]
 struct IndexData {
     index_type: u32,
     index_count: u32,
     index_data_offset: u32
 }
 
+#[derive(Clone, Copy)]
+#[repr(C)]
 struct VertexData {
     data_size: u32,
     data_offset: u32,
     total_vertices: u32
 }
 
+#[repr(C)]
 struct VertexAttribDecl {
-    #[allow(dead_code)]
     name: [u8; 64],
     size: u32,
     ty: u32,
@@ -89,6 +110,9 @@ pub enum LoadError {
     ChunkSizeError(usize, usize),
     VertexDataError,
     VertexAttribDataError,
+    /// The named data lies outside the file
+    RangeError(&'static str),
+    IndexTypeError(u32),
     IoError(io::Error),
 }
 
@@ -106,6 +130,8 @@ impl fmt::Display for LoadError {
             &LoadError::ChunkSizeError(_, _) => write!(fmt, "Unexpected chunk size"),
             &LoadError::VertexDataError => write!(fmt, "Missing vertex data"),
             &LoadError::VertexAttribDataError => write!(fmt, "Missing vertex data attribute"),
+            &LoadError::RangeError(s) => write!(fmt, "Object {} out of range", s),
+            &LoadError::IndexTypeError(t) => write!(fmt, "Unsupported index type 0x{:x}", t),
             &LoadError::IoError(ref ioerror) => ioerror.fmt(fmt),
         }
     }
@@ -117,165 +143,511 @@ macro_rules! load_object_or_panic {
         .unwrap_or_else(|e| { panic!("Error loading '{}': {}", $path, e) }))
 }
 
-#[derive(Clone, Copy)]
-struct SubObjectDecl {
-    first: u32,
-    count: u32
+/// A vertex attribute of an SBM mesh
+#[derive(Clone, Debug, PartialEq)]
+pub struct VertexAttrib {
+    pub name: String,
+    /// The number of components
+    pub size: u32,
+    /// The component type, e.g. `gl::FLOAT`
+    pub ty: GLenum,
+    /// The distance between vertices in bytes, 0 if tightly packed
+    pub stride: u32,
+    pub normalized: bool,
+    /// The offset of the first vertex from the start of the vertex data
+    pub data_offset: u32
 }
 
-pub struct Object {
-    vertex_buffer: GLuint,
-    index_buffer: GLuint,
-    vao: GLuint,
-    num_indices: GLuint,
-    index_type: GLuint,
-    num_sub_objects: usize,
-    sub_object: [SubObjectDecl; 256]
+/// A part of a mesh drawn on its own. The range is of indices for indexed
+/// meshes with `MESH_FLAG_INDEX_RANGES` set, otherwise of vertices.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct SubObject {
+    pub first: u32,
+    pub count: u32
 }
 
-// TODO: #[derive(Clone)] is currently limited to arrays up to 32 length
-impl Clone for Object {
-    #[inline]
-    fn clone(&self) -> Object { *self }
+/// Returns the size in bytes of an index of the given type
+pub fn index_size(index_type: GLenum) -> Option<usize> {
+    match index_type {
+        gl::UNSIGNED_BYTE => Some(mem::size_of::<GLubyte>()),
+        gl::UNSIGNED_SHORT => Some(mem::size_of::<GLushort>()),
+        gl::UNSIGNED_INT => Some(mem::size_of::<GLuint>()),
+        _ => None
+    }
 }
 
-impl Copy for Object {
+/// An SB6M mesh parsed into memory without touching OpenGL.
+/// The vertex and index data are not copied out of the file buffer.
+pub struct SbmMesh {
+    flags: u32,
+    /// The chunk types in file order, so they're written back the same way
+    chunks: Vec<u32>,
+    attribs: Vec<VertexAttrib>,
+    total_vertices: u32,
+    index_type: GLenum,
+    index_count: u32,
+    sub_objects: Vec<SubObject>,
+    comments: Vec<String>,
+    reader: BufferReader,
+    vertex_range: (usize, usize),
+    index_range: (usize, usize)
 }
 
-impl Object {
-    pub fn new() -> Object {
-        Object {
-            vertex_buffer: 0,
-            index_buffer: 0,
-            vao: 0,
-            num_indices: 0,
-            index_type: 0,
-            num_sub_objects: 0,
-            sub_object: [SubObjectDecl { first: 0, count: 0 }; 256]
+/// Returns the range of `size` bytes at `offset`, checking it lies within
+/// the buffer
+fn data_range(reader: &BufferReader, what: &'static str, offset: usize, size: usize)
+              -> Result<(usize, usize), LoadError> {
+    match offset.checked_add(size) {
+        Some(end) if end <= reader.len() => Ok((offset, end)),
+        _ => Err(LoadError::RangeError(what))
+    }
+}
+
+impl SbmMesh {
+    /// Creates a mesh from vertex data described by `attribs`. Indices are
+    /// stored as `UNSIGNED_SHORT` if they fit, otherwise `UNSIGNED_INT`, and
+    /// the sub-objects of an indexed mesh are ranges of indices. An empty
+    /// sub-object list draws the whole mesh as one sub-object.
+    pub fn new(attribs: Vec<VertexAttrib>, vertex_data: Vec<u8>, total_vertices: u32,
+               indices: Option<&[u32]>, sub_objects: Vec<SubObject>, comments: Vec<String>)
+               -> Result<SbmMesh, LoadError> {
+        if attribs.is_empty() {
+            return Err(LoadError::VertexAttribDataError)
+        }
+        let mut chunks = vec![VERTEX_ATTRIBS_TYPE, VERTEX_DATA_TYPE];
+        let mut data = vertex_data;
+        let vertex_range = (0, data.len());
+
+        let (index_type, index_count, index_range) = match indices {
+            Some(indices) => {
+                chunks.push(INDEX_DATA_TYPE);
+                let start = data.len();
+                let index_type = if indices.iter().all(|&i| i <= 0xffff) {
+                    for &i in indices {
+                        data.extend_from_slice(&[i as u8, (i >> 8) as u8]);
+                    }
+                    gl::UNSIGNED_SHORT
+                }
+                else {
+                    for &i in indices {
+                        data.extend_from_slice(&[i as u8, (i >> 8) as u8, (i >> 16) as u8,
+                            (i >> 24) as u8]);
+                    }
+                    gl::UNSIGNED_INT
+                };
+                (index_type, indices.len() as u32, (start, data.len()))
+            },
+            None => (0, 0, (0, 0))
+        };
+
+        let sub_objects = if sub_objects.is_empty() {
+            let count = if index_type != 0 { index_count } else { total_vertices };
+            vec![SubObject { first: 0, count: count }]
         }
+        else {
+            chunks.push(SUB_OBJECT_LIST_TYPE);
+            sub_objects
+        };
+        chunks.extend(comments.iter().map(|_| COMMENT_TYPE));
+
+        Ok(SbmMesh {
+            flags: if index_type != 0 { MESH_FLAG_INDEX_RANGES } else { 0 },
+            chunks: chunks,
+            attribs: attribs,
+            total_vertices: total_vertices,
+            index_type: index_type,
+            index_count: index_count,
+            sub_objects: sub_objects,
+            comments: comments,
+            reader: BufferReader::new(data),
+            vertex_range: vertex_range,
+            index_range: index_range
+        })
     }
 
-    pub fn load(&mut self, filename: &str) -> Result<(), LoadError> {
-        let mut file = try!(fs::File::open(&Path::new(filename)));
+    /// Maps the given SBM file into memory and parses it
+    pub fn open(filename: &str) -> Result<SbmMesh, LoadError> {
+        SbmMesh::from_buffer(try!(BufferReader::open(filename)))
+    }
+
+    /// Reads an SBM file to the end of the given reader and parses it
+    pub fn read<R: Read>(mut reader: R) -> Result<SbmMesh, LoadError> {
         let mut bytes = Vec::new();
-        try!(file.read_to_end(&mut bytes));
+        try!(reader.read_to_end(&mut bytes));
+        SbmMesh::parse(bytes)
+    }
 
-        let mut reader = BufferReader::new(bytes);
-        let mut bytes_read = 0;
+    /// Parses an SBM file from an owned byte buffer
+    pub fn parse(bytes: Vec<u8>) -> Result<SbmMesh, LoadError> {
+        SbmMesh::from_buffer(BufferReader::new(bytes))
+    }
 
+    fn from_buffer(mut reader: BufferReader) -> Result<SbmMesh, LoadError> {
         // check header magic
-        let magic = try!(reader.pop_slice::<u8>(4));
-        match str::from_utf8(magic) {
-            Ok(v) if v == "SB6M" => (),
-            Ok(v) => return Err(LoadError::MagicError(Some(String::from(v)))),
-            Err(_) => return Err(LoadError::MagicError(None))
+        let magic = try!(reader.pop_slice::<u8>(MAGIC.len()));
+        if magic != MAGIC {
+            return Err(LoadError::MagicError(str::from_utf8(magic).ok().map(String::from)))
         }
 
-        debug!("magic: {}", str::from_utf8(magic).unwrap());
-
-        let header = try!(reader.pop_value::<MeshHeader>());
-        bytes_read += header.size as usize;
-
+        let header = *try!(reader.pop_value::<MeshHeader>());
         debug!("size: {}, num_chunks: {}, flags: {}",
             header.size, header.num_chunks, header.flags);
-        assert!(bytes_read == reader.bytes_read());
+        // the header size includes the magic
+        if (header.size as usize) < reader.bytes_read() {
+            return Err(LoadError::ChunkSizeError(reader.bytes_read(), header.size as usize))
+        }
+        let extra = header.size as usize - reader.bytes_read();
+        try!(reader.skip_bytes(extra));
 
-        let mut vertex_attrib_data_ref: Option<&[VertexAttribDecl]> = None;
-        let mut vertex_data_chunk_ref: Option<&VertexData> = None;
-        let mut index_data_chunk_ref: Option<&IndexData> = None;
-        let mut sub_object_data_ref: Option<&[SubObjectDecl]> = None;
+        let mut attribs = Vec::new();
+        let mut vertex_data = None;
+        let mut index_data = None;
+        let mut sub_objects = Vec::new();
+        let mut comments = Vec::new();
+        let mut chunks = Vec::new();
 
         for _ in 0..header.num_chunks {
-            let chunk_header = try!(reader.pop_value::<ChunkHeader>());
+            let chunk_start = reader.bytes_read();
+            let chunk_header = *try!(reader.pop_value::<ChunkHeader>());
+            let chunk_end = chunk_start + chunk_header.size as usize;
+            if chunk_end < reader.bytes_read() {
+                return Err(LoadError::ChunkSizeError(reader.bytes_read() - chunk_start,
+                    chunk_header.size as usize))
+            }
             match chunk_header.chunk_type {
                 INDEX_DATA_TYPE => {
                     debug!("INDX");
-                    // read in index data struct
-                    index_data_chunk_ref = Some(
-                        try!(reader.pop_value::<IndexData>()));
-                }
+                    index_data = Some(*try!(reader.pop_value::<IndexData>()));
+                },
                 VERTEX_DATA_TYPE => {
                     debug!("VRTX");
-                    // read in vertex data struct
-                    vertex_data_chunk_ref = Some(
-                        try!(reader.pop_value::<VertexData>()));
+                    vertex_data = Some(*try!(reader.pop_value::<VertexData>()));
                 },
                 VERTEX_ATTRIBS_TYPE => {
                     debug!("ATRB");
-                    // read attribute count
-                    let attrib_count = try!(reader.pop_value::<u32>());
-                    // read in all the attributes
-                    vertex_attrib_data_ref = Some(
-                        try!(reader.pop_slice::<VertexAttribDecl>(
-                                *attrib_count as usize)));
+                    let attrib_count = *try!(reader.pop_value::<u32>());
+                    let decls = try!(reader.pop_slice::<VertexAttribDecl>(
+                            attrib_count as usize));
+                    attribs = decls.iter().map(|decl| {
+                        let len = decl.name.iter().position(|&c| c == 0)
+                            .unwrap_or(decl.name.len());
+                        VertexAttrib {
+                            name: String::from_utf8_lossy(&decl.name[..len]).into_owned(),
+                            size: decl.size,
+                            ty: decl.ty,
+                            stride: decl.stride,
+                            normalized: decl.flags & VERTEX_ATTRIB_FLAG_NORMALIZED != 0,
+                            data_offset: decl.data_offset
+                        }
+                    }).collect();
                 },
                 SUB_OBJECT_LIST_TYPE => {
                     debug!("OLST");
-                    // read sub object count
-                    let sub_object_count = try!(reader.pop_value::<u32>());
+                    let sub_object_count = *try!(reader.pop_value::<u32>());
                     debug!("sub_object_count: {}", sub_object_count);
-                    // read in sub object data
-                    sub_object_data_ref = Some(
-                        try!(reader.pop_slice::<SubObjectDecl>(
-                                *sub_object_count as usize)));
+                    sub_objects = try!(reader.pop_slice::<SubObject>(
+                            sub_object_count as usize)).to_vec();
                 },
                 COMMENT_TYPE => {
                     debug!("CMNT");
-                    let comment_len = chunk_header.size as usize -
-                        mem::size_of::<ChunkHeader>();
-                    let comment_bytes_ref = try!(reader.pop_slice::<u8>(
-                            comment_len));
-                    match str::from_utf8(comment_bytes_ref) {
-                        Ok(v) => debug!("{}", v),
-                        _ => panic!("couldn't read comment")
-                    };
+                    let comment = try!(reader.pop_slice::<u8>(chunk_end - reader.bytes_read()));
+                    let len = comment.iter().position(|&c| c == 0).unwrap_or(comment.len());
+                    comments.push(String::from_utf8_lossy(&comment[..len]).into_owned());
                 },
-                _ => return Err(LoadError::ChunkTypeError(chunk_header.chunk_type))
+                t => return Err(LoadError::ChunkTypeError(t))
             }
-            bytes_read += chunk_header.size as usize;
-            assert!(bytes_read == reader.bytes_read());
-        }
-
-        // check the expected number of bytes read
-        if bytes_read != reader.bytes_read() {
-            return Err(LoadError::ChunkSizeError(bytes_read, reader.bytes_read()))
+            chunks.push(chunk_header.chunk_type);
+            if reader.bytes_read() > chunk_end {
+                return Err(LoadError::ChunkSizeError(reader.bytes_read() - chunk_start,
+                    chunk_header.size as usize))
+            }
+            try!(reader.skip_bytes(chunk_end - reader.bytes_read()));
         }
 
-        // vertex data required
-        let vertex_data_chunk = match vertex_data_chunk_ref {
+        // vertex data and attributes are required
+        let vertex_data = match vertex_data {
             Some(v) => v,
             None => return Err(LoadError::VertexDataError)
         };
+        if attribs.is_empty() {
+            return Err(LoadError::VertexAttribDataError)
+        }
+        let vertex_range = try!(data_range(&reader, "vertex data",
+            vertex_data.data_offset as usize, vertex_data.data_size as usize));
+
+        let (index_type, index_count, index_range) = match index_data {
+            Some(index_data) => {
+                let size = match index_size(index_data.index_type) {
+                    Some(size) => size,
+                    None => return Err(LoadError::IndexTypeError(index_data.index_type))
+                };
+                let range = match size.checked_mul(index_data.index_count as usize) {
+                    Some(n) => try!(data_range(&reader, "index data",
+                        index_data.index_data_offset as usize, n)),
+                    None => return Err(LoadError::RangeError("index data"))
+                };
+                (index_data.index_type, index_data.index_count, range)
+            },
+            None => (0, 0, (0, 0))
+        };
 
-        // vertex attribute required
-        let vertex_attrib_data = match vertex_attrib_data_ref {
-            Some(v) => v,
-            None => return Err(LoadError::VertexAttribDataError)
+        if sub_objects.is_empty() {
+            let count = if index_type != 0 && header.flags & MESH_FLAG_INDEX_RANGES != 0 {
+                index_count
+            }
+            else {
+                vertex_data.total_vertices
+            };
+            sub_objects.push(SubObject { first: 0, count: count });
+        }
+
+        Ok(SbmMesh {
+            flags: header.flags,
+            chunks: chunks,
+            attribs: attribs,
+            total_vertices: vertex_data.total_vertices,
+            index_type: index_type,
+            index_count: index_count,
+            sub_objects: sub_objects,
+            comments: comments,
+            reader: reader,
+            vertex_range: vertex_range,
+            index_range: index_range
+        })
+    }
+
+    pub fn flags(&self) -> u32 { self.flags }
+
+    /// Returns true if the sub-objects are ranges of indices
+    pub fn has_index_ranges(&self) -> bool {
+        self.is_indexed() && self.flags & MESH_FLAG_INDEX_RANGES != 0
+    }
+
+    pub fn attribs(&self) -> &[VertexAttrib] { &self.attribs }
+
+    /// Returns the attribute with the given name
+    pub fn attrib(&self, name: &str) -> Option<&VertexAttrib> {
+        self.attribs.iter().find(|a| a.name == name)
+    }
+
+    pub fn total_vertices(&self) -> u32 { self.total_vertices }
+
+    /// Returns the raw interleaved or packed vertex data
+    pub fn vertex_data(&self) -> &[u8] {
+        self.reader.peek_slice(self.vertex_range.0, self.vertex_range.1).unwrap()
+    }
+
+    pub fn is_indexed(&self) -> bool { self.index_type != 0 }
+
+    /// Returns the index type, 0 if the mesh isn't indexed
+    pub fn index_type(&self) -> GLenum { self.index_type }
+
+    pub fn index_count(&self) -> u32 { self.index_count }
+
+    /// Returns the raw index data, empty if the mesh isn't indexed
+    pub fn index_data(&self) -> &[u8] {
+        self.reader.peek_slice(self.index_range.0, self.index_range.1).unwrap()
+    }
+
+    /// Returns the indices widened to `u32`
+    pub fn indices(&self) -> Vec<u32> {
+        let data = self.index_data();
+        match self.index_type {
+            gl::UNSIGNED_BYTE => data.iter().map(|&i| i as u32).collect(),
+            gl::UNSIGNED_SHORT => data.chunks(2)
+                .map(|i| (i[0] as u32) | (i[1] as u32) << 8).collect(),
+            gl::UNSIGNED_INT => data.chunks(4)
+                .map(|i| (i[0] as u32) | (i[1] as u32) << 8 | (i[2] as u32) << 16 |
+                    (i[3] as u32) << 24).collect(),
+            _ => Vec::new()
+        }
+    }
+
+    /// Returns the sub-objects, a single one covering the whole mesh if the
+    /// file doesn't list any
+    pub fn sub_objects(&self) -> &[SubObject] { &self.sub_objects }
+
+    pub fn comments(&self) -> &[String] { &self.comments }
+}
+
+fn write_u32<W: Write>(writer: &mut W, value: u32) -> Result<(), io::Error> {
+    writer.write_all(&[value as u8, (value >> 8) as u8, (value >> 16) as u8,
+                       (value >> 24) as u8])
+}
+
+fn padding(size: usize) -> usize {
+    3 - ((size + 3) % 4)
+}
+
+fn missing_comment() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "more comment chunks than comments")
+}
+
+/// Serialises a mesh as an SB6M file. Chunks are written in the order they
+/// were read, followed by the vertex data and then the index data.
+pub fn write<W: Write>(writer: &mut W, mesh: &SbmMesh) -> Result<(), io::Error> {
+    const ZEROES: [u8; 4] = [0; 4];
+    let chunk_header_size = mem::size_of::<ChunkHeader>();
+    // comments are stored with a terminating nul
+    let comment_size = |comment: &String| {
+        let size = comment.len() + 1;
+        chunk_header_size + size + padding(size)
+    };
+
+    let mut comments = mesh.comments().iter();
+    let mut data_offset = MAGIC.len() + mem::size_of::<MeshHeader>();
+    for &chunk in &mesh.chunks {
+        data_offset += match chunk {
+            VERTEX_ATTRIBS_TYPE => chunk_header_size + 4 +
+                mesh.attribs().len() * mem::size_of::<VertexAttribDecl>(),
+            VERTEX_DATA_TYPE => chunk_header_size + mem::size_of::<VertexData>(),
+            INDEX_DATA_TYPE => chunk_header_size + mem::size_of::<IndexData>(),
+            SUB_OBJECT_LIST_TYPE => chunk_header_size + 4 +
+                mesh.sub_objects().len() * mem::size_of::<SubObject>(),
+            _ => match comments.next() {
+                Some(comment) => comment_size(comment),
+                None => return Err(missing_comment())
+            }
         };
+    }
+    let vertex_data = mesh.vertex_data();
+    let index_data = mesh.index_data();
 
-        match sub_object_data_ref {
-            Some(sub_object_data) => {
-                debug!("sub_object_count: {}", sub_object_data.len());
-                self.num_sub_objects = sub_object_data.len();
-                for i in 0..self.num_sub_objects {
-                    self.sub_object[i] = sub_object_data[i];
+    try!(writer.write_all(MAGIC));
+    for &value in &[(MAGIC.len() + mem::size_of::<MeshHeader>()) as u32,
+                    mesh.chunks.len() as u32, mesh.flags()] {
+        try!(write_u32(writer, value));
+    }
+
+    let mut comments = mesh.comments().iter();
+    for &chunk in &mesh.chunks {
+        try!(write_u32(writer, chunk));
+        match chunk {
+            VERTEX_ATTRIBS_TYPE => {
+                let attribs = mesh.attribs();
+                try!(write_u32(writer, (chunk_header_size + 4 +
+                    attribs.len() * mem::size_of::<VertexAttribDecl>()) as u32));
+                try!(write_u32(writer, attribs.len() as u32));
+                for attrib in attribs {
+                    let mut name = [0u8; 64];
+                    if attrib.name.len() >= name.len() {
+                        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                            "attribute name longer than 63 bytes"))
+                    }
+                    name[..attrib.name.len()].copy_from_slice(attrib.name.as_bytes());
+                    try!(writer.write_all(&name));
+                    let flags = if attrib.normalized { VERTEX_ATTRIB_FLAG_NORMALIZED } else { 0 };
+                    for &value in &[attrib.size, attrib.ty, attrib.stride, flags,
+                                    attrib.data_offset] {
+                        try!(write_u32(writer, value));
+                    }
+                }
+            },
+            VERTEX_DATA_TYPE => {
+                for &value in &[(chunk_header_size + mem::size_of::<VertexData>()) as u32,
+                                vertex_data.len() as u32, data_offset as u32,
+                                mesh.total_vertices()] {
+                    try!(write_u32(writer, value));
+                }
+            },
+            INDEX_DATA_TYPE => {
+                for &value in &[(chunk_header_size + mem::size_of::<IndexData>()) as u32,
+                                mesh.index_type(), mesh.index_count(),
+                                (data_offset + vertex_data.len()) as u32] {
+                    try!(write_u32(writer, value));
                 }
             },
-            None => {
-                self.num_sub_objects = 1;
-                self.sub_object[0].count = vertex_data_chunk.total_vertices;
+            SUB_OBJECT_LIST_TYPE => {
+                let sub_objects = mesh.sub_objects();
+                try!(write_u32(writer, (chunk_header_size + 4 +
+                    sub_objects.len() * mem::size_of::<SubObject>()) as u32));
+                try!(write_u32(writer, sub_objects.len() as u32));
+                for sub_object in sub_objects {
+                    try!(write_u32(writer, sub_object.first));
+                    try!(write_u32(writer, sub_object.count));
+                }
+            },
+            _ => {
+                let comment = match comments.next() {
+                    Some(comment) => comment,
+                    None => return Err(missing_comment())
+                };
+                try!(write_u32(writer, comment_size(comment) as u32));
+                try!(writer.write_all(comment.as_bytes()));
+                try!(writer.write_all(&ZEROES[..1 + padding(comment.len() + 1)]));
             }
         }
+    }
+
+    try!(writer.write_all(vertex_data));
+    writer.write_all(index_data)
+}
+
+/// Saves a mesh to the given SBM file
+pub fn save(filename: &str, mesh: &SbmMesh) -> Result<(), io::Error> {
+    let mut file = io::BufWriter::new(try!(fs::File::create(&Path::new(filename))));
+    try!(write(&mut file, mesh));
+    file.flush()
+}
+
+pub struct Object {
+    vertex_buffer: GLuint,
+    index_buffer: GLuint,
+    vao: GLuint,
+    num_indices: GLuint,
+    index_type: GLuint,
+    index_ranges: bool,
+    num_sub_objects: usize,
+    sub_object: [SubObject; 256]
+}
+
+// TODO: #[derive(Clone)] is currently limited to arrays up to 32 length
+impl Clone for Object {
+    #[inline]
+    fn clone(&self) -> Object { *self }
+}
+
+impl Copy for Object {
+}
+
+impl Object {
+    pub fn new() -> Object {
+        Object {
+            vertex_buffer: 0,
+            index_buffer: 0,
+            vao: 0,
+            num_indices: 0,
+            index_type: 0,
+            index_ranges: false,
+            num_sub_objects: 0,
+            sub_object: [SubObject { first: 0, count: 0 }; 256]
+        }
+    }
+
+    pub fn load(&mut self, filename: &str) -> Result<(), LoadError> {
+        self.load_mesh(&try!(SbmMesh::open(filename)));
+        Ok(())
+    }
+
+    /// Creates the buffers and vertex array object for a parsed mesh
+    pub fn load_mesh(&mut self, mesh: &SbmMesh) {
+        let sub_objects = mesh.sub_objects();
+        if sub_objects.len() > self.sub_object.len() {
+            warn!("only the first {} of {} sub-objects will be drawn",
+                self.sub_object.len(), sub_objects.len());
+        }
+        self.num_sub_objects = ::std::cmp::min(sub_objects.len(), self.sub_object.len());
+        self.sub_object[..self.num_sub_objects].copy_from_slice(
+            &sub_objects[..self.num_sub_objects]);
 
         // bind vertex data
-        let vertex_data_start = vertex_data_chunk.data_offset as usize;
-        let vertex_data_end = vertex_data_start + vertex_data_chunk.data_size as usize;
-        let vertex_data = try!(reader.peek_slice(vertex_data_start, vertex_data_end));
+        let vertex_data = mesh.vertex_data();
         unsafe {
             gl::GenBuffers(1, &mut self.vertex_buffer);
             gl::BindBuffer(gl::ARRAY_BUFFER, self.vertex_buffer);
             gl::BufferData(gl::ARRAY_BUFFER,
-                           vertex_data_chunk.data_size as GLsizeiptr,
+                           vertex_data.len() as GLsizeiptr,
                            mem::transmute(vertex_data.as_ptr()),
                            gl::STATIC_DRAW);
             gl::GenVertexArrays(1, &mut self.vao);
@@ -283,64 +655,43 @@ impl Object {
         }
 
         // bind vertex attributes
-        for i in 0..vertex_attrib_data.len() {
-            let attrib_decl = &vertex_attrib_data[i];
-            let attrib_flags =
-                if attrib_decl.flags & VERTEX_ATTRIB_FLAG_NORMALIZED != 0 {
-                    gl::TRUE
-                } else {
-                    gl::FALSE
-                };
-            let attrib_data_offset = attrib_decl.data_offset as usize;
+        for (i, attrib) in mesh.attribs().iter().enumerate() {
+            let attrib_flags = if attrib.normalized { gl::TRUE } else { gl::FALSE };
+            let attrib_data_offset = attrib.data_offset as usize;
             unsafe {
                 gl::VertexAttribPointer(i as u32,
-                                        attrib_decl.size as i32,
-                                        attrib_decl.ty,
+                                        attrib.size as i32,
+                                        attrib.ty,
                                         attrib_flags,
-                                        attrib_decl.stride as i32,
+                                        attrib.stride as i32,
                                         mem::transmute(attrib_data_offset));
                 gl::EnableVertexAttribArray(i as u32);
             }
         }
 
         // bind index data
-        match index_data_chunk_ref {
-            Some(index_data_chunk) => {
-                let indice_size = 
-                    if index_data_chunk.index_type == gl::UNSIGNED_SHORT as u32 {
-                        mem::size_of::<GLushort>()
-                    } else {
-                        mem::size_of::<GLubyte>()
-                    };
-                let index_data_size =
-                    index_data_chunk.index_count as usize * indice_size;
-                let index_data_start =
-                    index_data_chunk.index_data_offset as usize;
-                let index_data_end = index_data_start + index_data_size;
-                let index_data = try!(reader.peek_slice(index_data_start,
-                                                        index_data_end));
-                unsafe {
-                    gl::GenBuffers(1, &mut self.index_buffer);
-                    gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.index_buffer);
-                    gl::BufferData(gl::ELEMENT_ARRAY_BUFFER,
-                                   index_data_size as GLsizeiptr,
-                                   mem::transmute(index_data.as_ptr()),
-                                   gl::STATIC_DRAW);
-                }
-                self.num_indices = index_data_chunk.index_count;
-                self.index_type = index_data_chunk.index_type;
-            },
-            None => {
-                self.num_indices = vertex_data_chunk.total_vertices;
+        if mesh.is_indexed() {
+            let index_data = mesh.index_data();
+            unsafe {
+                gl::GenBuffers(1, &mut self.index_buffer);
+                gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.index_buffer);
+                gl::BufferData(gl::ELEMENT_ARRAY_BUFFER,
+                               index_data.len() as GLsizeiptr,
+                               mem::transmute(index_data.as_ptr()),
+                               gl::STATIC_DRAW);
             }
+            self.num_indices = mesh.index_count();
+            self.index_type = mesh.index_type();
+            self.index_ranges = mesh.has_index_ranges();
+        }
+        else {
+            self.num_indices = mesh.total_vertices();
         }
 
         unsafe {
             gl::BindVertexArray(0);
             gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, 0);
         }
-
-        Ok(())
     }
 
     pub fn free(&mut self) {
@@ -361,16 +712,35 @@ impl Object {
     }
 
     pub fn render_instances(&self, instance_count: u32, base_instance: u32) {
-        self.render_sub_object(0, instance_count, base_instance);
+        if self.index_buffer != 0 {
+            unsafe {
+                gl::BindVertexArray(self.vao);
+                gl::DrawElementsInstancedBaseInstance(
+                    gl::TRIANGLES,
+                    self.num_indices as i32,
+                    self.index_type,
+                    ptr::null(),
+                    instance_count as i32,
+                    base_instance);
+            }
+        } else {
+            self.render_sub_object(0, instance_count, base_instance);
+        }
+    }
+
+    pub fn num_sub_objects(&self) -> usize {
+        self.num_sub_objects
     }
 
+    /// Draws one sub-object. Indexed meshes without index ranges draw the
+    /// whole index buffer whichever sub-object is asked for.
     pub fn render_sub_object(&self, object_index: u32, instance_count: u32,
                              base_instance: u32) {
 
         unsafe {
             gl::BindVertexArray(self.vao);
 
-            if self.index_buffer != 0 {
+            if self.index_buffer != 0 && !self.index_ranges {
                 gl::DrawElementsInstancedBaseInstance(
                     gl::TRIANGLES,
                     self.num_indices as i32,
@@ -378,6 +748,16 @@ impl Object {
                     ptr::null(),
                     instance_count as i32,
                     base_instance);
+            } else if self.index_buffer != 0 {
+                let sub_object = &self.sub_object[object_index as usize];
+                let index_size = index_size(self.index_type).unwrap_or(0);
+                gl::DrawElementsInstancedBaseInstance(
+                    gl::TRIANGLES,
+                    sub_object.count as i32,
+                    self.index_type,
+                    (sub_object.first as usize * index_size) as *const GLvoid,
+                    instance_count as i32,
+                    base_instance);
             } else {
                 gl::DrawArraysInstancedBaseInstance(
                     gl::TRIANGLES,
@@ -0,0 +1,368 @@
+/*
+ * Copyright (c) 2014 Cameron Hart
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a
+ * copy of this software and associated documentation files (the "Software"),
+ * to deal in the Software without restriction, including without limitation
+ * the rights to use, copy, modify, merge, publish, distribute, sublicense,
+ * and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+ * THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+ * FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+ * DEALINGS IN THE SOFTWARE.
+ */
+
+use mesh::MeshData;
+use object;
+use object::{LoadError, Object};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::str;
+
+#[derive(Debug)]
+pub enum PlyError {
+    /// A malformed header, holds a description
+    HeaderError(String),
+    /// The body ended early or holds a malformed value
+    DataError,
+    /// A face refers to a vertex that doesn't exist, holds the face's index
+    IndexError(usize),
+    NoFacesError,
+    MeshError(LoadError),
+    IoError(io::Error)
+}
+
+impl From<io::Error> for PlyError {
+    fn from(e: io::Error) -> PlyError {
+        PlyError::IoError(e)
+    }
+}
+
+impl From<LoadError> for PlyError {
+    fn from(e: LoadError) -> PlyError {
+        PlyError::MeshError(e)
+    }
+}
+
+impl fmt::Display for PlyError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &PlyError::HeaderError(ref s) => write!(fmt, "Invalid PLY header: {}", s),
+            &PlyError::DataError => write!(fmt, "Invalid or truncated PLY data"),
+            &PlyError::IndexError(face) => write!(fmt, "Invalid PLY file: face {} has a vertex index out of range", face),
+            &PlyError::NoFacesError => write!(fmt, "PLY file has no faces"),
+            &PlyError::MeshError(ref e) => e.fmt(fmt),
+            &PlyError::IoError(ref e) => e.fmt(fmt)
+        }
+    }
+}
+
+fn header_error<T>(s: &str) -> Result<T, PlyError> {
+    Err(PlyError::HeaderError(String::from(s)))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Type {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64
+}
+
+impl Type {
+    fn parse(name: &str) -> Option<Type> {
+        match name {
+            "char" | "int8" => Some(Type::Int8),
+            "uchar" | "uint8" => Some(Type::UInt8),
+            "short" | "int16" => Some(Type::Int16),
+            "ushort" | "uint16" => Some(Type::UInt16),
+            "int" | "int32" => Some(Type::Int32),
+            "uint" | "uint32" => Some(Type::UInt32),
+            "float" | "float32" => Some(Type::Float32),
+            "double" | "float64" => Some(Type::Float64),
+            _ => None
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            &Type::Int8 | &Type::UInt8 => 1,
+            &Type::Int16 | &Type::UInt16 => 2,
+            &Type::Int32 | &Type::UInt32 | &Type::Float32 => 4,
+            &Type::Float64 => 8
+        }
+    }
+}
+
+struct Property {
+    name: String,
+    ty: Type,
+    /// The type of the item count, if this is a list
+    count: Option<Type>
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>
+}
+
+impl Element {
+    /// Returns the index of the first scalar property with one of the given
+    /// names
+    fn find(&self, names: &[&str]) -> Option<usize> {
+        self.properties.iter().position(|p| p.count.is_none() && names.contains(&&p.name[..]))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian
+}
+
+/// Parses the header, returning the elements and where the body starts
+fn parse_header(bytes: &[u8]) -> Result<(Format, Vec<Element>, usize), PlyError> {
+    if !bytes.starts_with(b"ply\n") && !bytes.starts_with(b"ply\r\n") {
+        return header_error("not a PLY file")
+    }
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut start = bytes.iter().position(|&b| b == b'\n').unwrap() + 1;
+    loop {
+        let end = match bytes[start..].iter().position(|&b| b == b'\n') {
+            Some(end) => start + end,
+            None => return header_error("no end_header")
+        };
+        let line = String::from_utf8_lossy(&bytes[start..end]);
+        start = end + 1;
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("format") => format = match (words.next(), words.next()) {
+                (Some("ascii"), Some("1.0")) => Some(Format::Ascii),
+                (Some("binary_little_endian"), Some("1.0")) => Some(Format::BinaryLittleEndian),
+                (Some("binary_big_endian"), Some("1.0")) => Some(Format::BinaryBigEndian),
+                _ => return header_error("unknown format")
+            },
+            Some("element") => {
+                let name = words.next().unwrap_or("");
+                let count = match words.next().and_then(|c| c.parse().ok()) {
+                    Some(count) => count,
+                    None => return header_error("element without a count")
+                };
+                elements.push(Element { name: String::from(name), count: count, properties: Vec::new() });
+            },
+            Some("property") => {
+                let element = match elements.last_mut() {
+                    Some(element) => element,
+                    None => return header_error("property before any element")
+                };
+                let words: Vec<&str> = words.collect();
+                let property = match &words[..] {
+                    &["list", count, ty, name] => Type::parse(count).and_then(|count| {
+                        Type::parse(ty).map(|ty| Property { name: String::from(name), ty: ty, count: Some(count) })
+                    }),
+                    &[ty, name] => Type::parse(ty).map(|ty| {
+                        Property { name: String::from(name), ty: ty, count: None }
+                    }),
+                    _ => None
+                };
+                match property {
+                    Some(property) => element.properties.push(property),
+                    None => return header_error("malformed property")
+                }
+            },
+            Some("end_header") => break,
+            Some("comment") | Some("obj_info") | None => {},
+            Some(_) => return header_error("unknown keyword")
+        }
+    }
+    // nothing in the body bounds the count of an element without properties
+    if elements.iter().any(|e| e.properties.is_empty() && e.count > 0) {
+        return header_error("element without properties")
+    }
+    match format {
+        Some(format) => Ok((format, elements, start)),
+        None => header_error("no format")
+    }
+}
+
+/// The values following the header
+enum Body<'a> {
+    Ascii(str::SplitWhitespace<'a>),
+    Binary(&'a [u8], usize, Format)
+}
+
+impl<'a> Body<'a> {
+    fn read(&mut self, ty: Type) -> Result<f64, PlyError> {
+        match self {
+            &mut Body::Ascii(ref mut words) => {
+                words.next().and_then(|w| w.parse().ok()).ok_or(PlyError::DataError)
+            },
+            &mut Body::Binary(data, ref mut pos, format) => {
+                let size = ty.size();
+                if data.len() - *pos < size {
+                    return Err(PlyError::DataError)
+                }
+                let mut b = [0u8; 8];
+                b[..size].copy_from_slice(&data[*pos..*pos + size]);
+                if format == Format::BinaryBigEndian {
+                    b[..size].reverse();
+                }
+                *pos += size;
+                Ok(match ty {
+                    Type::Int8 => b[0] as i8 as f64,
+                    Type::UInt8 => b[0] as f64,
+                    Type::Int16 => i16::from_le_bytes([b[0], b[1]]) as f64,
+                    Type::UInt16 => u16::from_le_bytes([b[0], b[1]]) as f64,
+                    Type::Int32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    Type::UInt32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    Type::Float32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    Type::Float64 => f64::from_le_bytes(b)
+                })
+            }
+        }
+    }
+
+    /// Reads one instance of an element, storing its scalar properties in
+    /// `values` and the items of the property `list_index` in `list`
+    fn read_element(&mut self, element: &Element, list_index: Option<usize>,
+                    values: &mut Vec<f64>, list: &mut Vec<f64>) -> Result<(), PlyError> {
+        values.clear();
+        for (i, property) in element.properties.iter().enumerate() {
+            match property.count {
+                Some(count) => {
+                    let count = try!(self.read(count));
+                    if count < 0.0 {
+                        return Err(PlyError::DataError)
+                    }
+                    if list_index == Some(i) {
+                        list.clear();
+                    }
+                    for _ in 0..count as usize {
+                        let value = try!(self.read(property.ty));
+                        if list_index == Some(i) {
+                            list.push(value);
+                        }
+                    }
+                    values.push(0.0);
+                },
+                None => values.push(try!(self.read(property.ty)))
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses a PLY file held in memory, in ASCII or binary form. Positions,
+/// normals and texcoords are read from the vertex element and polygons from
+/// the face element's vertex index list, split into triangle fans. Other
+/// elements and properties are skipped. Smooth normals are generated if the
+/// file has none.
+pub fn parse(bytes: &[u8]) -> Result<MeshData, PlyError> {
+    let (format, elements, start) = try!(parse_header(bytes));
+    let mut body = match format {
+        Format::Ascii => match str::from_utf8(&bytes[start..]) {
+            Ok(text) => Body::Ascii(text.split_whitespace()),
+            Err(_) => return Err(PlyError::DataError)
+        },
+        _ => Body::Binary(bytes, start, format)
+    };
+
+    let mut mesh = MeshData::new();
+    let mut values = Vec::new();
+    let mut list = Vec::new();
+    let vertices = elements.iter().find(|e| e.name == "vertex").map_or(0, |e| e.count);
+    for element in &elements {
+        match &element.name[..] {
+            "vertex" => {
+                let position = match (element.find(&["x"]), element.find(&["y"]), element.find(&["z"])) {
+                    (Some(x), Some(y), Some(z)) => [x, y, z],
+                    _ => return header_error("vertices without positions")
+                };
+                let normal = match (element.find(&["nx"]), element.find(&["ny"]), element.find(&["nz"])) {
+                    (Some(x), Some(y), Some(z)) => Some([x, y, z]),
+                    _ => None
+                };
+                let texcoord = match (element.find(&["u", "s", "texture_u", "texture_s"]),
+                                      element.find(&["v", "t", "texture_v", "texture_t"])) {
+                    (Some(u), Some(v)) => Some([u, v]),
+                    _ => None
+                };
+                for _ in 0..element.count {
+                    try!(body.read_element(element, None, &mut values, &mut list));
+                    mesh.positions.push([values[position[0]] as f32, values[position[1]] as f32,
+                                         values[position[2]] as f32]);
+                    if let Some(n) = normal {
+                        mesh.normals.push([values[n[0]] as f32, values[n[1]] as f32, values[n[2]] as f32]);
+                    }
+                    if let Some(t) = texcoord {
+                        mesh.texcoords.push([values[t[0]] as f32, values[t[1]] as f32]);
+                    }
+                }
+            },
+            "face" => {
+                let indices = element.properties.iter().position(|p| {
+                    p.count.is_some() && (p.name == "vertex_indices" || p.name == "vertex_index")
+                });
+                if indices.is_none() {
+                    return header_error("faces without vertex indices")
+                }
+                for face in 0..element.count {
+                    try!(body.read_element(element, indices, &mut values, &mut list));
+                    if list.iter().any(|&i| i < 0.0 || i >= vertices as f64) {
+                        return Err(PlyError::IndexError(face))
+                    }
+                    for k in 2..list.len() {
+                        mesh.indices.extend_from_slice(&[list[0] as u32, list[k - 1] as u32, list[k] as u32]);
+                    }
+                }
+            },
+            _ => for _ in 0..element.count {
+                try!(body.read_element(element, None, &mut values, &mut list));
+            }
+        }
+    }
+
+    if mesh.indices.is_empty() {
+        return Err(PlyError::NoFacesError)
+    }
+    if mesh.normals.is_empty() {
+        mesh.generate_normals();
+    }
+    Ok(mesh)
+}
+
+/// Reads and parses the given PLY file
+pub fn open(filename: &str) -> Result<MeshData, PlyError> {
+    parse(&try!(fs::read(filename)))
+}
+
+/// Imports a PLY file and creates an `Object` from it
+pub fn load_object(filename: &str) -> Result<Object, PlyError> {
+    let mesh = try!(try!(open(filename)).to_sbm(Vec::new()));
+    let mut object = Object::new();
+    object.load_mesh(&mesh);
+    Ok(object)
+}
+
+/// Imports a PLY file and saves it as an SBM file
+pub fn convert(ply_filename: &str, sbm_filename: &str) -> Result<(), PlyError> {
+    let mesh = try!(try!(open(ply_filename)).to_sbm(Vec::new()));
+    Ok(try!(object::save(sbm_filename, &mesh)))
+}
@@ -0,0 +1,269 @@
+/*
+ * Copyright (c) 2014 Cameron Hart
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a
+ * copy of this software and associated documentation files (the "Software"),
+ * to deal in the Software without restriction, including without limitation
+ * the rights to use, copy, modify, merge, publish, distribute, sublicense,
+ * and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+ * THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+ * FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+ * DEALINGS IN THE SOFTWARE.
+ */
+
+use convert;
+use convert::ConvertError;
+use gl::types::*;
+use image;
+use image::Image;
+use ktx;
+use ktx::{Header, KeyValueData, KtxImage, LoadError};
+use std::path::Path;
+
+/// Returns the format, type and type size used to read back pixels of the
+/// given internal format, along with the internal format the data is stored
+/// as. Formats with no exact client representation are read as floats.
+fn read_format(internal_format: GLenum) -> (GLenum, GLenum, u32, GLenum) {
+    let f = internal_format;
+    match f {
+        gl::RGBA | gl::RGBA8 | gl::SRGB8_ALPHA8 => (gl::RGBA, gl::UNSIGNED_BYTE, 1, f),
+        gl::RGB | gl::RGB8 | gl::SRGB8 => (gl::RGB, gl::UNSIGNED_BYTE, 1, f),
+        gl::RG8 => (gl::RG, gl::UNSIGNED_BYTE, 1, f),
+        gl::R8 => (gl::RED, gl::UNSIGNED_BYTE, 1, f),
+        gl::RGBA16 => (gl::RGBA, gl::UNSIGNED_SHORT, 2, f),
+        gl::RGB16 => (gl::RGB, gl::UNSIGNED_SHORT, 2, f),
+        gl::RG16 => (gl::RG, gl::UNSIGNED_SHORT, 2, f),
+        gl::R16 => (gl::RED, gl::UNSIGNED_SHORT, 2, f),
+        gl::RGBA16F => (gl::RGBA, gl::HALF_FLOAT, 2, f),
+        gl::RGB16F => (gl::RGB, gl::HALF_FLOAT, 2, f),
+        gl::RG16F => (gl::RG, gl::HALF_FLOAT, 2, f),
+        gl::R16F => (gl::RED, gl::HALF_FLOAT, 2, f),
+        gl::R11F_G11F_B10F => (gl::RGB, gl::HALF_FLOAT, 2, gl::RGB16F),
+        gl::RGB9_E5 => (gl::RGB, gl::UNSIGNED_INT_5_9_9_9_REV, 4, f),
+        gl::RGB32F => (gl::RGB, gl::FLOAT, 4, f),
+        gl::RG32F => (gl::RG, gl::FLOAT, 4, f),
+        gl::R32F => (gl::RED, gl::FLOAT, 4, f),
+        gl::RGBA8UI => (gl::RGBA_INTEGER, gl::UNSIGNED_BYTE, 1, f),
+        gl::RGBA16UI => (gl::RGBA_INTEGER, gl::UNSIGNED_SHORT, 2, f),
+        gl::RGBA32UI => (gl::RGBA_INTEGER, gl::UNSIGNED_INT, 4, f),
+        gl::RGBA8I => (gl::RGBA_INTEGER, gl::BYTE, 1, f),
+        gl::RGBA16I => (gl::RGBA_INTEGER, gl::SHORT, 2, f),
+        gl::RGBA32I => (gl::RGBA_INTEGER, gl::INT, 4, f),
+        gl::R32UI => (gl::RED_INTEGER, gl::UNSIGNED_INT, 4, f),
+        gl::RG32UI => (gl::RG_INTEGER, gl::UNSIGNED_INT, 4, f),
+        gl::R32I => (gl::RED_INTEGER, gl::INT, 4, f),
+        gl::RG32I => (gl::RG_INTEGER, gl::INT, 4, f),
+        gl::DEPTH_COMPONENT | gl::DEPTH_COMPONENT16 | gl::DEPTH_COMPONENT24 |
+        gl::DEPTH_COMPONENT32 | gl::DEPTH_COMPONENT32F =>
+            (gl::DEPTH_COMPONENT, gl::FLOAT, 4, gl::DEPTH_COMPONENT32F),
+        // packed into 32 bit words, 8 bytes per pixel for the float depth
+        gl::DEPTH_STENCIL | gl::DEPTH24_STENCIL8 =>
+            (gl::DEPTH_STENCIL, gl::UNSIGNED_INT_24_8, 4, gl::DEPTH24_STENCIL8),
+        gl::DEPTH32F_STENCIL8 =>
+            (gl::DEPTH_STENCIL, gl::FLOAT_32_UNSIGNED_INT_24_8_REV, 4, f),
+        _ => (gl::RGBA, gl::FLOAT, 4, gl::RGBA32F)
+    }
+}
+
+/// Returns the base internal format matching a pixel transfer format
+fn base_format(format: GLenum) -> GLenum {
+    match format {
+        gl::RED_INTEGER => gl::RED,
+        gl::RG_INTEGER => gl::RG,
+        gl::RGBA_INTEGER => gl::RGBA,
+        _ => format
+    }
+}
+
+/// Fills in the format fields of a header for reading back pixels of the
+/// given internal format
+fn set_format(header: &mut Header, internal_format: GLenum) {
+    let (format, ty, type_size, stored) = read_format(internal_format);
+    header.gl_type = ty;
+    header.gl_type_size = type_size;
+    header.gl_format = format;
+    header.gl_internal_format = stored;
+    header.gl_base_internal_format = base_format(format);
+}
+
+/// Reads a mip level of a texture back to the CPU. Every array layer and
+/// cube face is read, compressed textures are read without decompressing
+/// them. The texture is left bound to `target`.
+pub fn read_texture(texture: GLuint, target: GLenum, level: usize)
+                    -> Result<KtxImage, LoadError> {
+    let targets: Vec<GLenum> = if target == gl::TEXTURE_CUBE_MAP {
+        (0..6).map(|face| gl::TEXTURE_CUBE_MAP_POSITIVE_X + face).collect()
+    }
+    else {
+        vec![target]
+    };
+    let level = level as GLint;
+    let query = |target: GLenum, pname: GLenum| {
+        let mut value = 0;
+        unsafe { gl::GetTexLevelParameteriv(target, level, pname, &mut value) };
+        value
+    };
+
+    unsafe { gl::BindTexture(target, texture) };
+    let (width, height, depth) = (query(targets[0], gl::TEXTURE_WIDTH),
+                                  query(targets[0], gl::TEXTURE_HEIGHT),
+                                  query(targets[0], gl::TEXTURE_DEPTH));
+    if width == 0 {
+        return Err(LoadError::UnsupportedError(format!(
+            "reading level {} of texture {}, which has no storage", level, texture)))
+    }
+    let mut header = Header {
+        pixel_width: width,
+        faces: 1,
+        mip_levels: 1,
+        .. Header::default()
+    };
+    match target {
+        gl::TEXTURE_1D => {},
+        gl::TEXTURE_1D_ARRAY => header.array_elements = height,
+        gl::TEXTURE_2D | gl::TEXTURE_RECTANGLE => header.pixel_height = height,
+        gl::TEXTURE_CUBE_MAP => {
+            header.pixel_height = height;
+            header.faces = 6;
+        },
+        gl::TEXTURE_2D_ARRAY => {
+            header.pixel_height = height;
+            header.array_elements = depth;
+        },
+        // cube map arrays report layer-faces as depth
+        gl::TEXTURE_CUBE_MAP_ARRAY => {
+            header.pixel_height = height;
+            header.array_elements = depth / 6;
+            header.faces = 6;
+        },
+        gl::TEXTURE_3D => {
+            header.pixel_height = height;
+            header.pixel_depth = depth;
+        },
+        _ => return Err(LoadError::UnsupportedError(format!(
+            "texture target 0x{:x}", target)))
+    }
+
+    let internal_format = query(targets[0], gl::TEXTURE_INTERNAL_FORMAT) as GLenum;
+    let compressed = query(targets[0], gl::TEXTURE_COMPRESSED) != 0;
+    let mut data = Vec::new();
+    unsafe {
+        gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+        // KTX rows are padded to four bytes
+        gl::PixelStorei(gl::PACK_ALIGNMENT, 4);
+    }
+    if compressed {
+        header.gl_type_size = 1;
+        header.gl_internal_format = internal_format;
+        header.gl_base_internal_format = if query(targets[0], gl::TEXTURE_ALPHA_SIZE) > 0 {
+            gl::RGBA
+        }
+        else if query(targets[0], gl::TEXTURE_GREEN_SIZE) == 0 {
+            gl::RED
+        }
+        else if query(targets[0], gl::TEXTURE_BLUE_SIZE) == 0 {
+            gl::RG
+        }
+        else {
+            gl::RGB
+        };
+        for &t in &targets {
+            let start = data.len();
+            data.resize(start + query(t, gl::TEXTURE_COMPRESSED_IMAGE_SIZE) as usize, 0);
+            unsafe {
+                gl::GetCompressedTexImage(t, level, data[start..].as_mut_ptr() as *mut GLvoid);
+            }
+        }
+    }
+    else {
+        set_format(&mut header, internal_format);
+        let image_size = match header.image_size(0) {
+            Some(size) => size,
+            None => return Err(LoadError::OverflowError("image size"))
+        };
+        let size = image_size * header.num_layers() * header.num_faces() / targets.len();
+        for &t in &targets {
+            let start = data.len();
+            data.resize(start + size, 0);
+            unsafe {
+                gl::GetTexImage(t, level, header.gl_format, header.gl_type,
+                    data[start..].as_mut_ptr() as *mut GLvoid);
+            }
+        }
+    }
+    KtxImage::new(header, KeyValueData::new(), vec![data])
+}
+
+/// Reads a rectangle of the read buffer of the bound read framebuffer, bind
+/// framebuffer 0 to read the default framebuffer. `internal_format` picks
+/// how the pixels are read and stored, such as `RGBA8` for the default
+/// framebuffer or `RGBA16F` for floating point attachments.
+pub fn read_framebuffer(x: i32, y: i32, width: i32, height: i32, internal_format: GLenum)
+                        -> Result<KtxImage, LoadError> {
+    let mut header = Header {
+        pixel_width: width,
+        pixel_height: height,
+        faces: 1,
+        mip_levels: 1,
+        .. Header::default()
+    };
+    set_format(&mut header, internal_format);
+    let size = match header.image_size(0) {
+        Some(size) => size,
+        None => return Err(LoadError::OverflowError("image size"))
+    };
+    let mut data = vec![0u8; size];
+    unsafe {
+        gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+        gl::PixelStorei(gl::PACK_ALIGNMENT, 4);
+        gl::ReadPixels(x, y, width, height, header.gl_format, header.gl_type,
+            data.as_mut_ptr() as *mut GLvoid);
+    }
+    KtxImage::new(header, KeyValueData::new(), vec![data])
+}
+
+/// Saves the base level of an image as a KTX, PNG or PFM file, chosen by
+/// the file extension. Array layers and cube faces are stacked top to
+/// bottom in PNG and PFM files.
+pub fn save(filename: &str, image: &KtxImage) -> Result<(), ConvertError> {
+    let extension = Path::new(filename).extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+    if extension.as_ref().map_or(false, |e| e == "ktx") {
+        return Ok(try!(ktx::save(filename, image)))
+    }
+    let images = try!(convert::ktx_images(image, 0));
+    let mut stacked = Image::new(images[0].width, 0, images[0].linear);
+    for i in &images {
+        stacked.height += i.height;
+        stacked.pixels.extend_from_slice(&i.pixels);
+    }
+    Ok(try!(image::save(filename, &stacked)))
+}
+
+/// Reads a mip level of a texture and saves it, see `read_texture` and
+/// `save`
+pub fn save_texture(filename: &str, texture: GLuint, target: GLenum, level: usize)
+                    -> Result<(), ConvertError> {
+    save(filename, &try!(read_texture(texture, target, level)))
+}
+
+/// Reads the back buffer of the default framebuffer as 8 bit RGBA and
+/// saves it, see `save`
+pub fn save_framebuffer(filename: &str, width: i32, height: i32) -> Result<(), ConvertError> {
+    unsafe {
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+        gl::ReadBuffer(gl::BACK);
+    }
+    save(filename, &try!(read_framebuffer(0, 0, width, height, gl::RGBA8)))
+}
@@ -21,76 +21,135 @@
  * DEALINGS IN THE SOFTWARE.
  */
 
+use memmap2::{MmapMut, MmapOptions};
+use std::fs;
 use std::io;
 use std::mem;
+use std::path::Path;
 use std::slice;
 
-/// Reads from an owned byte vector.
+enum Buffer {
+    Owned(Vec<u8>),
+    /// A private copy-on-write mapping, writes never reach the file
+    Mapped(MmapMut)
+}
+
+/// Reads from an owned byte vector or a memory-mapped file.
 /// This is similar to the built in std::io::MemReader, the main differences
 /// being we are not concerned with endian conversion, and we don't copy the
 /// data out of the buffer, we just return references to in the internal
 /// buffer.
 pub struct BufferReader {
-    buf: Vec<u8>,
+    buf: Buffer,
     pos: usize
 }
 
 impl BufferReader {
     pub fn new(buf: Vec<u8>) -> BufferReader {
         BufferReader {
-            buf: buf,
+            buf: Buffer::Owned(buf),
+            pos: 0
+        }
+    }
+
+    /// Maps the given file into memory rather than reading it. The file
+    /// must not be modified while the reader is alive.
+    pub fn open(filename: &str) -> Result<BufferReader, io::Error> {
+        let file = try!(fs::File::open(&Path::new(filename)));
+        // empty files can't be mapped
+        if try!(file.metadata()).len() == 0 {
+            return Ok(BufferReader::new(Vec::new()))
+        }
+        let map = try!(unsafe { MmapOptions::new().map_copy(&file) });
+        Ok(BufferReader {
+            buf: Buffer::Mapped(map),
             pos: 0
+        })
+    }
+
+    /// Returns the whole buffer
+    pub fn as_slice(&self) -> &[u8] {
+        match self.buf {
+            Buffer::Owned(ref buf) => buf,
+            Buffer::Mapped(ref map) => map
+        }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        match self.buf {
+            Buffer::Owned(ref mut buf) => buf,
+            Buffer::Mapped(ref mut map) => map
         }
     }
 
     /// Returns the buffer length
-    pub fn len(&self) -> usize { self.buf.len() }
+    pub fn len(&self) -> usize { self.as_slice().len() }
 
     /// Returns the number of bytes read from the buffer
     pub fn bytes_read(&self) -> usize { self.pos }
 
     /// Skip the given number of bytes
     pub fn skip_bytes(&mut self, bytes: usize) -> Result<(), io::Error> {
-        let skip_end = self.pos + bytes;
-        if skip_end > self.buf.len() {
+        let skip_end = match self.pos.checked_add(bytes) {
+            Some(end) => end,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Buffer overrun"))
+        };
+        if skip_end > self.len() {
             return Err(io::Error::new(io::ErrorKind::InvalidInput, "Buffer overrun"))
         }
         self.pos = skip_end;
         Ok(())
     }
 
-    /// Pop a slice of T items
-    pub fn pop_slice<'a, T>(&mut self, size: usize) -> Result<&'a [T], io::Error> {
-        let pop_bytes = mem::size_of::<T>() * size;
-        let pop_end = self.pos + pop_bytes;
-        if pop_end > self.buf.len() {
+    /// Pop a slice of T items, borrowed from the reader
+    pub fn pop_slice<T>(&mut self, size: usize) -> Result<&[T], io::Error> {
+        let pop_end = match mem::size_of::<T>().checked_mul(size)
+                .and_then(|pop_bytes| self.pos.checked_add(pop_bytes)) {
+            Some(end) => end,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Buffer overrun"))
+        };
+        if pop_end > self.len() {
             return Err(io::Error::new(io::ErrorKind::InvalidInput, "Buffer overrun"))
         }
-        let ptr = unsafe { self.buf.as_ptr().offset(self.pos as isize) as *const T };
-        let out = unsafe { slice::from_raw_parts(ptr, size) };
+        let ptr = unsafe { self.as_slice().as_ptr().offset(self.pos as isize) };
+        if (ptr as usize) % mem::align_of::<T>() != 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Misaligned read"))
+        }
+        let out = unsafe { slice::from_raw_parts(ptr as *const T, size) };
         self.pos = pop_end;
         Ok(out)
     }
 
-    /// Pop a reference to T
-    pub fn pop_value<'a, T>(&mut self) -> Result<&'a T, io::Error> {
+    /// Pop a reference to T, borrowed from the reader
+    pub fn pop_value<T>(&mut self) -> Result<&T, io::Error> {
         let pop_end = self.pos + mem::size_of::<T>();
-        if pop_end > self.buf.len() {
+        if pop_end > self.len() {
             return Err(io::Error::new(io::ErrorKind::InvalidInput, "Buffer overrun"))
         }
-        let ptr = unsafe { self.buf.as_ptr().offset(self.pos as isize) };
+        let ptr = unsafe { self.as_slice().as_ptr().offset(self.pos as isize) };
+        if (ptr as usize) % mem::align_of::<T>() != 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Misaligned read"))
+        }
         self.pos = pop_end;
         Ok(unsafe { &*(ptr as *const T) })
     }
 
     pub fn peek_slice<'a>(&'a self, start: usize, end: usize) -> Result<&'a [u8], io::Error> {
         assert!(start <= end);
-        if end > self.buf.len() {
+        if end > self.len() {
             return Err(io::Error::new(io::ErrorKind::InvalidInput, "Buffer overrun"))
         }
         Ok(unsafe {
-            slice::from_raw_parts(self.buf.as_ptr().offset(start as isize), end - start)
+            slice::from_raw_parts(self.as_slice().as_ptr().offset(start as isize), end - start)
         })
     }
+
+    pub fn peek_slice_mut<'a>(&'a mut self, start: usize, end: usize) -> Result<&'a mut [u8], io::Error> {
+        assert!(start <= end);
+        if end > self.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Buffer overrun"))
+        }
+        Ok(&mut self.as_mut_slice()[start..end])
+    }
 }
 
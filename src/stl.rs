@@ -0,0 +1,210 @@
+/*
+ * Copyright (c) 2014 Cameron Hart
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a
+ * copy of this software and associated documentation files (the "Software"),
+ * to deal in the Software without restriction, including without limitation
+ * the rights to use, copy, modify, merge, publish, distribute, sublicense,
+ * and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+ * THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+ * FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+ * DEALINGS IN THE SOFTWARE.
+ */
+
+use mesh::{face_normal, normalize, MeshData};
+use object;
+use object::{LoadError, Object, SubObject};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::str::SplitWhitespace;
+
+const HEADER_SIZE: usize = 84;
+const TRIANGLE_SIZE: usize = 50;
+
+#[derive(Debug)]
+pub enum StlError {
+    /// A malformed ASCII statement, holds the line number and a description
+    ParseError(usize, &'static str),
+    /// A binary file is shorter than its triangle count says
+    SizeError(usize, usize),
+    NoFacesError,
+    MeshError(LoadError),
+    IoError(io::Error)
+}
+
+impl From<io::Error> for StlError {
+    fn from(e: io::Error) -> StlError {
+        StlError::IoError(e)
+    }
+}
+
+impl From<LoadError> for StlError {
+    fn from(e: LoadError) -> StlError {
+        StlError::MeshError(e)
+    }
+}
+
+impl fmt::Display for StlError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &StlError::ParseError(line, s) => write!(fmt, "Invalid STL file: {} on line {}", s, line),
+            &StlError::SizeError(actual, expected) =>
+                write!(fmt, "Truncated STL file: {} bytes, expected {}", actual, expected),
+            &StlError::NoFacesError => write!(fmt, "STL file has no facets"),
+            &StlError::MeshError(ref e) => e.fmt(fmt),
+            &StlError::IoError(ref e) => e.fmt(fmt)
+        }
+    }
+}
+
+fn read_f32(bytes: &[u8], offset: usize) -> f32 {
+    f32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+/// Adds a polygon as a fan of triangles, each with its own vertices. Facet
+/// normals that are zero or invalid are computed from the winding.
+fn add_facet(mesh: &mut MeshData, normal: [f32; 3], corners: &[[f32; 3]]) {
+    for k in 2..corners.len() {
+        let triangle = [corners[0], corners[k - 1], corners[k]];
+        let valid = normal.iter().all(|c| c.is_finite()) && normal != [0.0; 3];
+        let n = if valid {
+            normalize(normal)
+        } else {
+            normalize(face_normal(triangle[0], triangle[1], triangle[2]))
+        };
+        for &p in &triangle {
+            mesh.indices.push(mesh.positions.len() as u32);
+            mesh.positions.push(p);
+            mesh.normals.push(n);
+        }
+    }
+}
+
+fn parse_binary(bytes: &[u8]) -> Result<MeshData, StlError> {
+    if bytes.len() < HEADER_SIZE {
+        return Err(StlError::SizeError(bytes.len(), HEADER_SIZE))
+    }
+    let count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize;
+    let expected = count.saturating_mul(TRIANGLE_SIZE).saturating_add(HEADER_SIZE);
+    if bytes.len() < expected {
+        return Err(StlError::SizeError(bytes.len(), expected))
+    }
+    let mut mesh = MeshData::new();
+    for i in 0..count {
+        let t = HEADER_SIZE + i * TRIANGLE_SIZE;
+        let v = |k: usize| [read_f32(bytes, t + k * 12), read_f32(bytes, t + k * 12 + 4),
+                            read_f32(bytes, t + k * 12 + 8)];
+        add_facet(&mut mesh, v(0), &[v(1), v(2), v(3)]);
+    }
+    Ok(mesh)
+}
+
+fn vector(words: &mut SplitWhitespace, line: usize) -> Result<[f32; 3], StlError> {
+    let mut v = [0.0; 3];
+    for c in 0..3 {
+        v[c] = match words.next().and_then(|w| w.parse().ok()) {
+            Some(value) => value,
+            None => return Err(StlError::ParseError(line, "invalid vector"))
+        };
+    }
+    Ok(v)
+}
+
+fn parse_ascii(text: &str) -> Result<MeshData, StlError> {
+    let mut mesh = MeshData::new();
+    let mut normal = [0.0; 3];
+    let mut corners = Vec::new();
+    let mut solid_start = 0;
+    for (i, line) in text.lines().enumerate() {
+        let number = i + 1;
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("facet") => {
+                corners.clear();
+                normal = match words.next() {
+                    Some("normal") => try!(vector(&mut words, number)),
+                    _ => [0.0; 3]
+                };
+            },
+            Some("vertex") => corners.push(try!(vector(&mut words, number))),
+            Some("endfacet") => {
+                if corners.len() < 3 {
+                    return Err(StlError::ParseError(number, "facet with fewer than three vertices"))
+                }
+                add_facet(&mut mesh, normal, &corners);
+                corners.clear();
+            },
+            Some("endsolid") => {
+                if mesh.indices.len() > solid_start {
+                    mesh.sub_objects.push(SubObject {
+                        first: solid_start as u32,
+                        count: (mesh.indices.len() - solid_start) as u32
+                    });
+                    solid_start = mesh.indices.len();
+                }
+            },
+            _ => {}
+        }
+    }
+    // sub-objects are only worth keeping for files with several solids
+    if mesh.sub_objects.len() < 2 {
+        mesh.sub_objects.clear();
+    }
+    Ok(mesh)
+}
+
+/// Parses an STL file held in memory, in ASCII or binary form. Each solid of
+/// an ASCII file with several becomes a sub-object. Every triangle gets its
+/// own vertices with the facet normal, so the mesh is flat shaded; call
+/// `MeshData::weld` and `MeshData::generate_normals` to smooth it.
+pub fn parse(bytes: &[u8]) -> Result<MeshData, StlError> {
+    // binary files may start with "solid" too, but their size gives them away
+    let binary_size = if bytes.len() >= HEADER_SIZE {
+        let count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize;
+        count.checked_mul(TRIANGLE_SIZE).and_then(|size| size.checked_add(HEADER_SIZE))
+    } else {
+        None
+    };
+    let ascii = binary_size != Some(bytes.len()) &&
+        String::from_utf8_lossy(&bytes[..::std::cmp::min(bytes.len(), 512)])
+            .trim_start().starts_with("solid");
+    let mesh = if ascii {
+        try!(parse_ascii(&String::from_utf8_lossy(bytes)))
+    } else {
+        try!(parse_binary(bytes))
+    };
+    if mesh.indices.is_empty() {
+        return Err(StlError::NoFacesError)
+    }
+    Ok(mesh)
+}
+
+/// Reads and parses the given STL file
+pub fn open(filename: &str) -> Result<MeshData, StlError> {
+    parse(&try!(fs::read(filename)))
+}
+
+/// Imports an STL file and creates an `Object` from it
+pub fn load_object(filename: &str) -> Result<Object, StlError> {
+    let mesh = try!(try!(open(filename)).to_sbm(Vec::new()));
+    let mut object = Object::new();
+    object.load_mesh(&mesh);
+    Ok(object)
+}
+
+/// Imports an STL file and saves it as an SBM file
+pub fn convert(stl_filename: &str, sbm_filename: &str) -> Result<(), StlError> {
+    let mesh = try!(try!(open(stl_filename)).to_sbm(Vec::new()));
+    Ok(try!(object::save(sbm_filename, &mesh)))
+}
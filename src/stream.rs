@@ -0,0 +1,447 @@
+/*
+ * Copyright (c) 2014 Cameron Hart
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a
+ * copy of this software and associated documentation files (the "Software"),
+ * to deal in the Software without restriction, including without limitation
+ * the rights to use, copy, modify, merge, publish, distribute, sublicense,
+ * and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+ * THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+ * FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+ * DEALINGS IN THE SOFTWARE.
+ */
+
+use decompress;
+use gl::types::*;
+use ktx;
+use ktx::{Header, KtxImage, LoadError, SubImage};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::ptr;
+use std::rc::Rc;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+
+/// The loading state of a streamed texture
+#[derive(Clone, Debug, PartialEq)]
+pub enum Status {
+    Loading,
+    Ready,
+    Failed(String)
+}
+
+struct State {
+    status: Status,
+    name: GLuint,
+    target: GLenum,
+    placeholder: GLuint
+}
+
+/// A texture being loaded by a `TextureStreamer`. Until it's ready the
+/// handle refers to a single texel placeholder texture.
+#[derive(Clone)]
+pub struct StreamedTexture {
+    state: Rc<RefCell<State>>
+}
+
+impl StreamedTexture {
+    pub fn status(&self) -> Status {
+        self.state.borrow().status.clone()
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.state.borrow().status == Status::Ready
+    }
+
+    /// Returns the texture object once it's ready, the placeholder
+    /// otherwise. The texture belongs to the caller once it's ready.
+    pub fn name(&self) -> GLuint {
+        let state = self.state.borrow();
+        match state.status {
+            Status::Ready => state.name,
+            _ => state.placeholder
+        }
+    }
+
+    /// Returns the texture target, `TEXTURE_2D` until the file is parsed
+    pub fn target(&self) -> GLenum {
+        self.state.borrow().target
+    }
+}
+
+enum Source {
+    File(String),
+    Bytes(Vec<u8>)
+}
+
+/// A parsed texture split into owned sub images, ready to upload
+struct Prepared {
+    header: Header,
+    subs: Vec<SubImage<'static>>
+}
+
+/// Parses a texture on the worker thread, decompressing it if the context
+/// can't sample its format
+fn prepare(source: Source, unsupported: &[GLenum]) -> Result<Prepared, LoadError> {
+    let image = try!(match source {
+        Source::File(filename) => KtxImage::open(&filename),
+        Source::Bytes(bytes) => KtxImage::parse(bytes)
+    });
+    let h = *image.header();
+    let flip = ktx::flips_on_upload(&image);
+    let image = if h.is_compressed() && unsupported.contains(&h.gl_internal_format) {
+        match decompress::decompress(&image) {
+            Some(decompressed) => decompressed,
+            None => return Err(LoadError::UnsupportedError(format!(
+                "compressed format 0x{:x}", h.gl_internal_format)))
+        }
+    }
+    else {
+        image
+    };
+    let subs = ktx::sub_images_flipped(&image, flip).into_iter().map(|sub| SubImage {
+        target: sub.target,
+        level: sub.level,
+        width: sub.width,
+        height: sub.height,
+        depth: sub.depth,
+        data: Cow::Owned(sub.data.into_owned())
+    }).collect();
+    Ok(Prepared {
+        header: *image.header(),
+        subs: subs
+    })
+}
+
+/// A texture whose storage has been created and whose sub images are being
+/// uploaded
+struct Upload {
+    state: Rc<RefCell<State>>,
+    header: Header,
+    subs: VecDeque<SubImage<'static>>
+}
+
+/// Offsets into the staging buffer are kept aligned to this
+const STAGING_ALIGNMENT: usize = 16;
+
+/// Loads KTX textures without blocking the render thread. Files are read
+/// and parsed on a worker thread, then `update` uploads a few mip levels
+/// each frame through a persistently mapped pixel buffer object. If the
+/// context lacks `ARB_buffer_storage` the data is uploaded from client
+/// memory instead.
+///
+/// `update` binds textures on the active texture unit. Placeholders are
+/// deleted along with the streamer.
+pub struct TextureStreamer {
+    jobs: Option<Sender<(usize, Source)>>,
+    results: Receiver<(usize, Result<Prepared, LoadError>)>,
+    worker: Option<thread::JoinHandle<()>>,
+    loading: HashMap<usize, Rc<RefCell<State>>>,
+    uploads: VecDeque<Upload>,
+    next_id: usize,
+    placeholders: Vec<(GLenum, GLuint)>,
+    buffer: GLuint,
+    mapped: *mut u8,
+    buffer_size: usize,
+    head: usize,
+    fences: VecDeque<(usize, usize, GLsync)>,
+    /// The number of sub images, each a mip level or cube map face, to
+    /// upload per call to `update`
+    pub levels_per_frame: usize
+}
+
+impl TextureStreamer {
+    /// Creates a streamer with a staging buffer of `buffer_size` bytes.
+    /// Levels larger than the buffer are uploaded from client memory.
+    pub fn new(buffer_size: usize) -> TextureStreamer {
+        // the worker can't query the context so tell it up front which
+        // formats to decompress
+        let unsupported: Vec<GLenum> = decompress::FORMATS.iter().cloned()
+            .filter(|&f| !ktx::is_compressed_format_supported(f))
+            .collect();
+        let (jobs, job_receiver) = channel::<(usize, Source)>();
+        let (result_sender, results) = channel();
+        let worker = thread::spawn(move || {
+            for (id, source) in job_receiver.iter() {
+                if result_sender.send((id, prepare(source, &unsupported))).is_err() {
+                    break
+                }
+            }
+        });
+
+        let mut buffer = 0;
+        let mut mapped = ptr::null_mut();
+        if gl::BufferStorage::is_loaded() && buffer_size > 0 {
+            let flags = gl::MAP_WRITE_BIT | gl::MAP_PERSISTENT_BIT | gl::MAP_COHERENT_BIT;
+            unsafe {
+                gl::GenBuffers(1, &mut buffer);
+                gl::BindBuffer(gl::PIXEL_UNPACK_BUFFER, buffer);
+                gl::BufferStorage(gl::PIXEL_UNPACK_BUFFER, buffer_size as GLsizeiptr,
+                    ptr::null(), flags);
+                mapped = gl::MapBufferRange(gl::PIXEL_UNPACK_BUFFER, 0,
+                    buffer_size as GLsizeiptr, flags) as *mut u8;
+                gl::BindBuffer(gl::PIXEL_UNPACK_BUFFER, 0);
+            }
+        }
+
+        TextureStreamer {
+            jobs: Some(jobs),
+            results: results,
+            worker: Some(worker),
+            loading: HashMap::new(),
+            uploads: VecDeque::new(),
+            next_id: 0,
+            placeholders: Vec::new(),
+            buffer: buffer,
+            mapped: mapped,
+            buffer_size: buffer_size,
+            head: 0,
+            fences: VecDeque::new(),
+            levels_per_frame: 2
+        }
+    }
+
+    /// Starts loading the given KTX file
+    pub fn load(&mut self, filename: &str) -> StreamedTexture {
+        self.submit(Source::File(String::from(filename)))
+    }
+
+    /// Starts loading KTX data held in memory
+    pub fn load_from_bytes(&mut self, bytes: Vec<u8>) -> StreamedTexture {
+        self.submit(Source::Bytes(bytes))
+    }
+
+    fn submit(&mut self, source: Source) -> StreamedTexture {
+        let state = Rc::new(RefCell::new(State {
+            status: Status::Loading,
+            name: 0,
+            target: gl::TEXTURE_2D,
+            placeholder: self.placeholder(gl::TEXTURE_2D)
+        }));
+        let id = self.next_id;
+        self.next_id += 1;
+        let sent = self.jobs.as_ref().map_or(false, |jobs| jobs.send((id, source)).is_ok());
+        if sent {
+            self.loading.insert(id, state.clone());
+        }
+        else {
+            state.borrow_mut().status = Status::Failed(String::from("texture streamer stopped"));
+        }
+        StreamedTexture { state: state }
+    }
+
+    /// Returns true if no textures are loading
+    pub fn is_idle(&self) -> bool {
+        self.loading.is_empty() && self.uploads.is_empty()
+    }
+
+    /// Returns a single grey texel texture for the given target, creating
+    /// it the first time it's needed
+    fn placeholder(&mut self, target: GLenum) -> GLuint {
+        if let Some(&(_, name)) = self.placeholders.iter().find(|p| p.0 == target) {
+            return name
+        }
+        const GREY: [u8; 24] = [128; 24];
+        let mut name = 0;
+        unsafe {
+            gl::GenTextures(1, &mut name);
+            gl::BindTexture(target, name);
+            let pixels = GREY.as_ptr() as *const GLvoid;
+            match target {
+                gl::TEXTURE_1D => {
+                    gl::TexStorage1D(target, 1, gl::RGBA8, 1);
+                    gl::TexSubImage1D(target, 0, 0, 1, gl::RGBA, gl::UNSIGNED_BYTE, pixels);
+                },
+                gl::TEXTURE_CUBE_MAP => {
+                    gl::TexStorage2D(target, 1, gl::RGBA8, 1, 1);
+                    for face in 0..6 {
+                        gl::TexSubImage2D(gl::TEXTURE_CUBE_MAP_POSITIVE_X + face, 0, 0, 0,
+                            1, 1, gl::RGBA, gl::UNSIGNED_BYTE, pixels);
+                    }
+                },
+                gl::TEXTURE_2D_ARRAY | gl::TEXTURE_3D => {
+                    gl::TexStorage3D(target, 1, gl::RGBA8, 1, 1, 1);
+                    gl::TexSubImage3D(target, 0, 0, 0, 0, 1, 1, 1, gl::RGBA,
+                        gl::UNSIGNED_BYTE, pixels);
+                },
+                gl::TEXTURE_CUBE_MAP_ARRAY => {
+                    gl::TexStorage3D(target, 1, gl::RGBA8, 1, 1, 6);
+                    gl::TexSubImage3D(target, 0, 0, 0, 0, 1, 1, 6, gl::RGBA,
+                        gl::UNSIGNED_BYTE, pixels);
+                },
+                // 2D textures and 1D arrays
+                _ => {
+                    gl::TexStorage2D(target, 1, gl::RGBA8, 1, 1);
+                    gl::TexSubImage2D(target, 0, 0, 0, 1, 1, gl::RGBA, gl::UNSIGNED_BYTE,
+                        pixels);
+                }
+            }
+        }
+        self.placeholders.push((target, name));
+        name
+    }
+
+    /// Creates storage for a parsed texture and queues its sub images
+    fn begin(&mut self, state: Rc<RefCell<State>>, prepared: Prepared) {
+        let h = prepared.header;
+        let target = h.target();
+        let placeholder = self.placeholder(target);
+        {
+            let mut s = state.borrow_mut();
+            s.target = target;
+            s.placeholder = placeholder;
+        }
+        if h.is_compressed() && !ktx::is_compressed_format_supported(h.gl_internal_format) {
+            state.borrow_mut().status = Status::Failed(format!(
+                "Unsupported ktx feature: compressed format 0x{:x}", h.gl_internal_format));
+            return
+        }
+        match unsafe { ktx::create_storage(&h, ktx::storage_levels(&h)) } {
+            Ok(name) => {
+                state.borrow_mut().name = name;
+                self.uploads.push_back(Upload {
+                    state: state,
+                    header: h,
+                    subs: prepared.subs.into_iter().collect()
+                });
+            },
+            Err(e) => state.borrow_mut().status = Status::Failed(e.to_string())
+        }
+    }
+
+    /// Copies data into the staging buffer, waiting for any earlier uploads
+    /// still reading that part of it. Returns the offset of the data, or
+    /// None if it must be uploaded from client memory.
+    unsafe fn stage(&mut self, data: &[u8]) -> Option<usize> {
+        if self.mapped.is_null() || data.len() > self.buffer_size {
+            return None
+        }
+        let size = (data.len() + STAGING_ALIGNMENT - 1) & !(STAGING_ALIGNMENT - 1);
+        if self.head + size > self.buffer_size {
+            self.head = 0;
+        }
+        let (start, end) = (self.head, self.head + size);
+        while let Some(i) = self.fences.iter().position(|f| f.0 < end && start < f.1) {
+            let (_, _, fence) = self.fences.remove(i).unwrap();
+            gl::ClientWaitSync(fence, gl::SYNC_FLUSH_COMMANDS_BIT, gl::TIMEOUT_IGNORED);
+            gl::DeleteSync(fence);
+        }
+        ptr::copy_nonoverlapping(data.as_ptr(), self.mapped.offset(start as isize), data.len());
+        self.head = end;
+        Some(start)
+    }
+
+    /// Deletes fences the GPU has passed so they don't accumulate
+    unsafe fn retire_fences(&mut self) {
+        while let Some(&(_, _, fence)) = self.fences.front() {
+            match gl::ClientWaitSync(fence, 0, 0) {
+                gl::ALREADY_SIGNALED | gl::CONDITION_SATISFIED => {
+                    gl::DeleteSync(fence);
+                    self.fences.pop_front();
+                },
+                _ => break
+            }
+        }
+    }
+
+    /// Uploads the next sub image of the oldest queued texture, returning
+    /// true if that finished it
+    unsafe fn upload_next(&mut self) -> bool {
+        let (name, h, sub) = {
+            let upload = self.uploads.front_mut().unwrap();
+            (upload.state.borrow().name, upload.header, upload.subs.pop_front())
+        };
+        if let Some(sub) = sub {
+            gl::BindTexture(h.target(), name);
+            // KTX rows are padded to four bytes
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+            match self.stage(&sub.data) {
+                Some(offset) => {
+                    gl::BindBuffer(gl::PIXEL_UNPACK_BUFFER, self.buffer);
+                    ktx::tex_sub_image(&h, &sub, sub.data.len(), offset as *const GLvoid);
+                    gl::BindBuffer(gl::PIXEL_UNPACK_BUFFER, 0);
+                    let fence = gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0);
+                    self.fences.push_back((offset, offset + sub.data.len(), fence));
+                },
+                None => ktx::tex_sub_image(&h, &sub, sub.data.len(),
+                    sub.data.as_ptr() as *const GLvoid)
+            }
+        }
+        self.uploads.front().unwrap().subs.is_empty()
+    }
+
+    /// Collects parsed textures and uploads up to `levels_per_frame` sub
+    /// images. Call once per frame from the thread that owns the context.
+    pub fn update(&mut self) {
+        while let Ok((id, result)) = self.results.try_recv() {
+            let state = match self.loading.remove(&id) {
+                Some(state) => state,
+                None => continue
+            };
+            match result {
+                Ok(prepared) => self.begin(state, prepared),
+                Err(e) => state.borrow_mut().status = Status::Failed(e.to_string())
+            }
+        }
+
+        unsafe {
+            self.retire_fences();
+            for _ in 0..self.levels_per_frame {
+                if self.uploads.is_empty() {
+                    break
+                }
+                if self.upload_next() {
+                    let upload = self.uploads.pop_front().unwrap();
+                    gl::BindTexture(upload.header.target(), upload.state.borrow().name);
+                    if ktx::needs_generated_mipmaps(&upload.header) {
+                        gl::GenerateMipmap(upload.header.target());
+                    }
+                    upload.state.borrow_mut().status = Status::Ready;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for TextureStreamer {
+    fn drop(&mut self) {
+        // closing the job channel stops the worker
+        self.jobs = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+        unsafe {
+            for upload in self.uploads.drain(..) {
+                let mut state = upload.state.borrow_mut();
+                gl::DeleteTextures(1, &state.name);
+                state.status = Status::Failed(String::from("texture streamer stopped"));
+            }
+            for (_, _, fence) in self.fences.drain(..) {
+                gl::DeleteSync(fence);
+            }
+            if self.buffer != 0 {
+                gl::BindBuffer(gl::PIXEL_UNPACK_BUFFER, self.buffer);
+                gl::UnmapBuffer(gl::PIXEL_UNPACK_BUFFER);
+                gl::BindBuffer(gl::PIXEL_UNPACK_BUFFER, 0);
+                gl::DeleteBuffers(1, &self.buffer);
+            }
+            for &(_, name) in &self.placeholders {
+                gl::DeleteTextures(1, &name);
+            }
+        }
+        for (_, state) in self.loading.drain() {
+            state.borrow_mut().status = Status::Failed(String::from("texture streamer stopped"));
+        }
+    }
+}
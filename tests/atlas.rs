@@ -0,0 +1,141 @@
+/*
+ * Copyright (c) 2014 Cameron Hart
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a
+ * copy of this software and associated documentation files (the "Software"),
+ * to deal in the Software without restriction, including without limitation
+ * the rights to use, copy, modify, merge, publish, distribute, sublicense,
+ * and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+ * THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+ * FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+ * DEALINGS IN THE SOFTWARE.
+ */
+
+extern crate sb6;
+
+use sb6::atlas;
+use sb6::atlas::Rect;
+use sb6::convert::{Format, Options};
+use sb6::image::Image;
+use sb6::ktx;
+use sb6::ktx::{KeyValueData, KtxImage};
+
+#[test]
+fn pack_without_overlap() {
+    // a mix of sizes from a simple linear congruential generator
+    let mut seed = 12345u32;
+    let mut next = |max: usize| {
+        seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+        1 + (seed >> 16) as usize % max
+    };
+    let sizes: Vec<(usize, usize)> = (0..60).map(|_| (next(40), next(40))).collect();
+    let (width, height, positions) = atlas::pack(&sizes).unwrap();
+    assert!(width.is_power_of_two() && height.is_power_of_two());
+    assert!(height <= width);
+    let area = sizes.iter().fold(0, |total, &(w, h)| total + w * h);
+    assert!(width * height >= area);
+
+    let rects: Vec<(usize, usize, usize, usize)> = sizes.iter().zip(positions.iter())
+        .map(|(&(w, h), &(x, y))| (x, y, x + w, y + h))
+        .collect();
+    for (i, a) in rects.iter().enumerate() {
+        assert!(a.2 <= width && a.3 <= height, "{:?} outside {}x{}", a, width, height);
+        for b in &rects[i + 1..] {
+            assert!(a.2 <= b.0 || b.2 <= a.0 || a.3 <= b.1 || b.3 <= a.1,
+                    "{:?} overlaps {:?}", a, b);
+        }
+    }
+
+    assert_eq!(atlas::pack(&[(3, 5)]).unwrap(), (8, 8, vec![(0, 0)]));
+    assert!(atlas::pack(&[(20000, 1)]).is_none());
+}
+
+fn image(width: usize, height: usize, seed: usize) -> Image {
+    let mut image = Image::new(width, height, false);
+    for y in 0..height {
+        for x in 0..width {
+            let v = |c: usize| ((seed * 67 + x * 31 + y * 17 + c * 7) % 256) as f32 / 255.0;
+            image.set(x, y, [v(0), v(1), v(2), 1.0]);
+        }
+    }
+    image
+}
+
+/// Returns an RGBA8 pixel of the atlas's top to bottom level 0
+fn pixel(ktx: &KtxImage, x: isize, y: isize) -> [f32; 4] {
+    let width = ktx.dimensions(0).0 as isize;
+    let i = ((y * width + x) * 4) as usize;
+    let p = &ktx.level(0)[i..i + 4];
+    [p[0] as f32 / 255.0, p[1] as f32 / 255.0, p[2] as f32 / 255.0, p[3] as f32 / 255.0]
+}
+
+#[test]
+fn build_with_padding() {
+    let images = [image(5, 3, 1), image(2, 7, 2), image(4, 4, 3)];
+    let named = [("wide", &images[0]), ("tall", &images[1]), ("square", &images[2])];
+    let options = Options { format: Format::Rgba8, mipmaps: None };
+    let padding = 2;
+    let ktx = atlas::build(&named, padding, &options).unwrap();
+    let (width, height, _) = ktx.dimensions(0);
+    let rects = atlas::rects(ktx.key_values()).unwrap();
+    assert_eq!(rects.iter().map(|r| &r.name[..]).collect::<Vec<_>>(),
+               vec!["wide", "tall", "square"]);
+
+    for (rect, image) in rects.iter().zip(images.iter()) {
+        // the table is in texture coordinates with the origin at the bottom
+        let x0 = (rect.u0 * width as f32).round() as isize;
+        let y0 = ((1.0 - rect.v1) * height as f32).round() as isize;
+        assert_eq!((rect.u1 - rect.u0) * width as f32, image.width as f32);
+        assert_eq!((rect.v1 - rect.v0) * height as f32, image.height as f32);
+        let p = padding as isize;
+        for dy in -p..image.height as isize + p {
+            for dx in -p..image.width as isize + p {
+                // padding repeats the nearest edge pixel
+                let sx = dx.max(0).min(image.width as isize - 1) as usize;
+                let sy = dy.max(0).min(image.height as isize - 1) as usize;
+                let expected = image.get(sx, sy);
+                let actual = pixel(&ktx, x0 + dx, y0 + dy);
+                for c in 0..4 {
+                    assert!((actual[c] - expected[c]).abs() <= 0.5 / 255.0,
+                            "{} at {}, {}: {:?} != {:?}", rect.name, dx, dy, actual, expected);
+                }
+            }
+        }
+    }
+
+    assert!(atlas::build(&[], 0, &options).is_err());
+    assert!(atlas::build(&[("two words", &images[0])], 0, &options).is_err());
+}
+
+#[test]
+fn rects_round_trip() {
+    let images = [image(8, 8, 1), image(16, 4, 2)];
+    let options = Options::default();
+    let ktx = atlas::build(&[("a", &images[0]), ("b", &images[1])], 1, &options).unwrap();
+    let expected = atlas::rects(ktx.key_values()).unwrap();
+
+    let mut bytes = Vec::new();
+    ktx::write(&mut bytes, &ktx).unwrap();
+    let read = KtxImage::parse(bytes).unwrap();
+    assert_eq!(atlas::rects(read.key_values()).unwrap(), expected);
+
+    let mut key_values = KeyValueData::new();
+    assert!(atlas::rects(&key_values).is_none());
+    key_values.insert_str(atlas::KEY_ATLAS, "a 0 0.5 0.25 1\n");
+    assert_eq!(atlas::rects(&key_values).unwrap(),
+               vec![Rect { name: String::from("a"), u0: 0.0, v0: 0.5, u1: 0.25, v1: 1.0 }]);
+    key_values.insert_str(atlas::KEY_ATLAS, "a 0 0.5 0.25\n");
+    assert!(atlas::rects(&key_values).is_none());
+    key_values.insert_str(atlas::KEY_ATLAS, "a 0 0.5 0.25 x\n");
+    assert!(atlas::rects(&key_values).is_none());
+}
@@ -0,0 +1,274 @@
+/*
+ * Copyright (c) 2014 Cameron Hart
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a
+ * copy of this software and associated documentation files (the "Software"),
+ * to deal in the Software without restriction, including without limitation
+ * the rights to use, copy, modify, merge, publish, distribute, sublicense,
+ * and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+ * THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+ * FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+ * DEALINGS IN THE SOFTWARE.
+ */
+
+//! Tests the block decoders against texels worked out by hand from the
+//! S3TC, RGTC and ETC2/EAC specifications.
+
+extern crate gl;
+extern crate sb6;
+
+use sb6::decompress;
+use sb6::ktx;
+use sb6::ktx::{Header, KeyValueData, KtxImage};
+
+type Texel = [u8; 4];
+
+/// Decodes a single 4x4 block, returning its texels in row order
+fn decode(internal_format: gl::types::GLenum, block: &[u8]) -> Vec<Texel> {
+    let pixels = decompress::decompress_image(internal_format, 4, 4, 1, block).unwrap();
+    pixels.chunks(4).map(|p| [p[0], p[1], p[2], p[3]]).collect()
+}
+
+/// Expands four texels into a block where every row is the same
+fn same_rows(row: [Texel; 4]) -> Vec<Texel> {
+    (0..4).flat_map(|_| row.iter().cloned()).collect()
+}
+
+/// Expands four texels into a block where every column is the same
+fn same_columns(column: [Texel; 4]) -> Vec<Texel> {
+    column.iter().flat_map(|&texel| (0..4).map(move |_| texel)).collect()
+}
+
+/// Packs 3 bit BC4 indices, pixels in row order from the least significant bit
+fn bc4_indices(indices: &[u64; 16]) -> Vec<u8> {
+    let bits = indices.iter().enumerate().fold(0, |bits, (i, &index)| bits | index << (3 * i));
+    (0..6).map(|i| (bits >> (8 * i)) as u8).collect()
+}
+
+/// Packs 3 bit EAC indices, pixels in column order from the most significant bit
+fn eac_indices(indices: &[u64; 16]) -> Vec<u8> {
+    let bits = indices.iter().enumerate().fold(0, |bits, (i, &index)| bits | index << (45 - 3 * i));
+    (0..6).map(|i| (bits >> (40 - 8 * i)) as u8).collect()
+}
+
+const RED: Texel = [255, 0, 0, 255];
+const BLUE: Texel = [0, 0, 255, 255];
+
+/// c0 = red, c1 = blue, each row uses indices 0 to 3
+const BC1_FOUR_COLOR: [u8; 8] = [0x00, 0xf8, 0x1f, 0x00, 0xe4, 0xe4, 0xe4, 0xe4];
+/// c0 = black, c1 = 66 red, c0 <= c1 selects the three colour mode
+const BC1_THREE_COLOR: [u8; 8] = [0x00, 0x00, 0x00, 0x40, 0xe4, 0xe4, 0xe4, 0xe4];
+
+#[test]
+fn bc1() {
+    assert_eq!(decode(ktx::COMPRESSED_RGB_S3TC_DXT1_EXT, &BC1_FOUR_COLOR),
+               same_rows([RED, BLUE, [170, 0, 85, 255], [85, 0, 170, 255]]));
+    assert_eq!(decode(ktx::COMPRESSED_RGBA_S3TC_DXT1_EXT, &BC1_THREE_COLOR),
+               same_rows([[0, 0, 0, 255], [66, 0, 0, 255], [33, 0, 0, 255], [0, 0, 0, 0]]));
+    // without alpha the transparent black texel is opaque
+    assert_eq!(decode(ktx::COMPRESSED_RGB_S3TC_DXT1_EXT, &BC1_THREE_COLOR),
+               same_rows([[0, 0, 0, 255], [66, 0, 0, 255], [33, 0, 0, 255], [0, 0, 0, 255]]));
+}
+
+#[test]
+fn bc2() {
+    let mut block = vec![0x10, 0x32, 0x54, 0x76, 0x98, 0xba, 0xdc, 0xfe];
+    block.extend_from_slice(&BC1_THREE_COLOR);
+    // BC2 colour blocks always use the four colour mode
+    let colors = [[0, 0, 0], [66, 0, 0], [22, 0, 0], [44, 0, 0]];
+    let expected = (0..16).map(|i| {
+        let c = colors[i % 4];
+        [c[0], c[1], c[2], i as u8 * 17]
+    }).collect::<Vec<_>>();
+    assert_eq!(decode(ktx::COMPRESSED_RGBA_S3TC_DXT3_EXT, &block), expected);
+}
+
+const INDICES: [u64; 16] = [0, 1, 2, 3, 4, 5, 6, 7, 0, 1, 2, 3, 4, 5, 6, 7];
+
+#[test]
+fn bc3() {
+    let mut block = vec![70, 0];
+    block.extend(bc4_indices(&INDICES));
+    block.extend_from_slice(&BC1_FOUR_COLOR);
+    let colors = [RED, BLUE, [170, 0, 85, 255], [85, 0, 170, 255]];
+    let alphas = [70, 0, 60, 50, 40, 30, 20, 10];
+    let expected = (0..16).map(|i| {
+        let c = colors[i % 4];
+        [c[0], c[1], c[2], alphas[i % 8]]
+    }).collect::<Vec<_>>();
+    assert_eq!(decode(ktx::COMPRESSED_RGBA_S3TC_DXT5_EXT, &block), expected);
+}
+
+#[test]
+fn bc4() {
+    // eight value mode
+    let mut block = vec![70, 0];
+    block.extend(bc4_indices(&INDICES));
+    let reds = [70, 0, 60, 50, 40, 30, 20, 10];
+    assert_eq!(decode(gl::COMPRESSED_RED_RGTC1, &block),
+               (0..16).map(|i| [reds[i % 8], 0, 0, 255]).collect::<Vec<_>>());
+
+    // six value mode with explicit 0 and 255
+    let mut block = vec![0, 50];
+    block.extend(bc4_indices(&INDICES));
+    let reds = [0, 50, 10, 20, 30, 40, 0, 255];
+    assert_eq!(decode(gl::COMPRESSED_RED_RGTC1, &block),
+               (0..16).map(|i| [reds[i % 8], 0, 0, 255]).collect::<Vec<_>>());
+
+    // signed, -70 is 0xba
+    let mut block = vec![70, 0xba];
+    block.extend(bc4_indices(&INDICES));
+    let reds = [70, 0xba, 50, 30, 10, 0xf6, 0xe2, 0xce];
+    assert_eq!(decode(gl::COMPRESSED_SIGNED_RED_RGTC1, &block),
+               (0..16).map(|i| [reds[i % 8], 0, 0, 127]).collect::<Vec<_>>());
+
+    // -128 decodes as -127
+    let mut block = vec![0x80, 0x7f];
+    block.extend(bc4_indices(&[0, 1, 6, 7, 0, 1, 6, 7, 0, 1, 6, 7, 0, 1, 6, 7]));
+    let reds = [0x81, 0x7f, 0x81, 0x7f];
+    assert_eq!(decode(gl::COMPRESSED_SIGNED_RED_RGTC1, &block),
+               (0..16).map(|i| [reds[i % 4], 0, 0, 127]).collect::<Vec<_>>());
+}
+
+#[test]
+fn bc5() {
+    let mut block = vec![70, 0];
+    block.extend(bc4_indices(&INDICES));
+    block.extend_from_slice(&[0, 50]);
+    block.extend(bc4_indices(&INDICES));
+    let reds = [70, 0, 60, 50, 40, 30, 20, 10];
+    let greens = [0, 50, 10, 20, 30, 40, 0, 255];
+    assert_eq!(decode(gl::COMPRESSED_RG_RGTC2, &block),
+               (0..16).map(|i| [reds[i % 8], greens[i % 8], 0, 255]).collect::<Vec<_>>());
+}
+
+/// Individual mode with colours (8, 4, 15) and (4, 8, 0), tables 0 and 1
+/// side by side, every pixel uses its row as the index
+const ETC1_INDIVIDUAL: [u8; 8] = [0x84, 0x48, 0xf0, 0x04, 0xcc, 0xcc, 0xaa, 0xaa];
+
+fn etc1_individual_texels() -> Vec<Texel> {
+    let left = [[138, 70, 255, 255], [144, 76, 255, 255], [134, 66, 253, 255],
+                [128, 60, 247, 255]];
+    let right = [[73, 141, 5, 255], [85, 153, 17, 255], [63, 131, 0, 255],
+                 [51, 119, 0, 255]];
+    (0..16).map(|i| if i % 4 < 2 { left[i / 4] } else { right[i / 4] }).collect()
+}
+
+/// Differential mode with colours (16, 0, 31) and (15, 0, 31), tables 2 and
+/// 0 flipped to top and bottom, every pixel uses its column as the index
+const ETC2_DIFFERENTIAL: [u8; 8] = [0x87, 0x00, 0xf8, 0x43, 0xff, 0x00, 0xf0, 0xf0];
+
+fn etc2_differential_texels() -> Vec<Texel> {
+    let top = [[141, 9, 255, 255], [161, 29, 255, 255], [123, 0, 246, 255],
+               [103, 0, 226, 255]];
+    let bottom = [[125, 2, 255, 255], [131, 8, 255, 255], [121, 0, 253, 255],
+                  [115, 0, 247, 255]];
+    (0..16).map(|i| if i < 8 { top[i % 4] } else { bottom[i % 4] }).collect()
+}
+
+/// T mode with colours (10, 5, 0) and (8, 8, 8), distance 11, every pixel
+/// uses its row as the index
+const ETC2_T: [u8; 8] = [0xf2, 0x50, 0x88, 0x86, 0xcc, 0xcc, 0xaa, 0xaa];
+/// H mode with colours (12, 4, 14) and (2, 6, 5), distance 16
+const ETC2_H: [u8; 8] = [0x62, 0xeb, 0x13, 0x2b, 0xcc, 0xcc, 0xaa, 0xaa];
+/// Planar mode with origin (32, 64, 32), horizontal (63, 0, 63) and
+/// vertical (0, 127, 0)
+const ETC2_PLANAR: [u8; 8] = [0x41, 0x01, 0x04, 0x7f, 0x01, 0xf8, 0x1f, 0xc0];
+
+#[test]
+fn etc1() {
+    assert_eq!(decode(ktx::COMPRESSED_ETC1_RGB8_OES, &ETC1_INDIVIDUAL),
+               etc1_individual_texels());
+}
+
+#[test]
+fn etc2_rgb() {
+    assert_eq!(decode(gl::COMPRESSED_RGB8_ETC2, &ETC1_INDIVIDUAL), etc1_individual_texels());
+    assert_eq!(decode(gl::COMPRESSED_RGB8_ETC2, &ETC2_DIFFERENTIAL),
+               etc2_differential_texels());
+    assert_eq!(decode(gl::COMPRESSED_RGB8_ETC2, &ETC2_T),
+               same_columns([[170, 85, 0, 255], [147, 147, 147, 255], [136, 136, 136, 255],
+                             [125, 125, 125, 255]]));
+    assert_eq!(decode(gl::COMPRESSED_RGB8_ETC2, &ETC2_H),
+               same_columns([[220, 84, 254, 255], [188, 52, 222, 255], [50, 118, 101, 255],
+                             [18, 86, 69, 255]]));
+    let planar = [[130, 129, 130], [161, 97, 161], [193, 65, 193], [224, 32, 224],
+                  [98, 161, 98], [129, 128, 129], [160, 96, 160], [191, 64, 191],
+                  [65, 192, 65], [96, 160, 96], [128, 128, 128], [159, 95, 159],
+                  [33, 224, 33], [64, 191, 64], [95, 159, 95], [126, 127, 126]];
+    assert_eq!(decode(gl::COMPRESSED_RGB8_ETC2, &ETC2_PLANAR),
+               planar.iter().map(|c| [c[0], c[1], c[2], 255]).collect::<Vec<_>>());
+}
+
+#[test]
+fn etc2_eac() {
+    let mut block = vec![128, 0x20];
+    block.extend(eac_indices(&INDICES));
+    block.extend_from_slice(&ETC1_INDIVIDUAL);
+    let alphas = [122, 116, 110, 98, 132, 138, 144, 156];
+    // the indices run down each column
+    let expected = etc1_individual_texels().into_iter().enumerate().map(|(i, c)| {
+        let (x, y) = (i % 4, i / 4);
+        [c[0], c[1], c[2], alphas[(x * 4 + y) % 8]]
+    }).collect::<Vec<_>>();
+    assert_eq!(decode(gl::COMPRESSED_RGBA8_ETC2_EAC, &block), expected);
+}
+
+#[test]
+fn etc2_punchthrough() {
+    let format = gl::COMPRESSED_RGB8_PUNCHTHROUGH_ALPHA1_ETC2;
+    // opaque blocks decode as ETC2 RGB
+    assert_eq!(decode(format, &ETC2_DIFFERENTIAL), etc2_differential_texels());
+
+    // clearing the opaque bit makes index 2 transparent and removes the
+    // modifier for index 0
+    let mut block = ETC2_DIFFERENTIAL;
+    block[3] &= !2;
+    let top = [[132, 0, 255, 255], [161, 29, 255, 255], [0, 0, 0, 0], [103, 0, 226, 255]];
+    let bottom = [[123, 0, 255, 255], [131, 8, 255, 255], [0, 0, 0, 0], [115, 0, 247, 255]];
+    assert_eq!(decode(format, &block),
+               (0..16).map(|i| if i < 8 { top[i % 4] } else { bottom[i % 4] })
+                   .collect::<Vec<_>>());
+
+    let mut block = ETC2_T;
+    block[3] &= !2;
+    assert_eq!(decode(format, &block),
+               same_columns([[170, 85, 0, 255], [147, 147, 147, 255], [0, 0, 0, 0],
+                             [125, 125, 125, 255]]));
+}
+
+#[test]
+fn decompress_image() {
+    let header = Header {
+        gl_type: 0,
+        gl_type_size: 1,
+        gl_format: 0,
+        gl_internal_format: gl::COMPRESSED_SRGB8_PUNCHTHROUGH_ALPHA1_ETC2,
+        gl_base_internal_format: gl::RGBA,
+        pixel_width: 4,
+        pixel_height: 4,
+        pixel_depth: 0,
+        array_elements: 0,
+        faces: 0,
+        mip_levels: 1,
+        key_pair_bytes: 0
+    };
+    let image = KtxImage::new(header, KeyValueData::new(),
+                              vec![ETC2_DIFFERENTIAL.to_vec()]).unwrap();
+    let decompressed = decompress::decompress(&image).unwrap();
+    let h = decompressed.header();
+    assert_eq!((h.gl_type, h.gl_format, h.gl_internal_format),
+               (gl::UNSIGNED_BYTE, gl::RGBA, gl::SRGB8_ALPHA8));
+    assert_eq!(decompressed.level(0),
+               &etc2_differential_texels().concat()[..]);
+}
@@ -0,0 +1,64 @@
+/*
+ * Copyright (c) 2014 Cameron Hart
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a
+ * copy of this software and associated documentation files (the "Software"),
+ * to deal in the Software without restriction, including without limitation
+ * the rights to use, copy, modify, merge, publish, distribute, sublicense,
+ * and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+ * THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+ * FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+ * DEALINGS IN THE SOFTWARE.
+ */
+
+extern crate sb6;
+
+use sb6::convert::Format;
+use sb6::envmap;
+use sb6::envmap::CubeOptions;
+use sb6::image::Image;
+
+/// Six 2x2 sRGB faces with black and white columns
+fn striped_faces() -> Vec<Image> {
+    (0..6).map(|_| {
+        let mut face = Image::new(2, 2, false);
+        for y in 0..2 {
+            face.set(0, y, [0.0, 0.0, 0.0, 1.0]);
+            face.set(1, y, [1.0, 1.0, 1.0, 1.0]);
+        }
+        face
+    }).collect()
+}
+
+#[test]
+fn mipmaps_average_linear_values() {
+    let options = CubeOptions { format: Format::Srgb8Alpha8, prefilter: false,
+                                .. CubeOptions::default() };
+    let cube = envmap::cube_texture(&striped_faces(), &options).unwrap();
+    // half white in linear space is 0.735 sRGB, not 0.5
+    for face in 0..6 {
+        assert_eq!(cube.image(1, 0, face), &[188, 188, 188, 255][..]);
+    }
+}
+
+#[test]
+fn prefilter_without_samples() {
+    let options = CubeOptions { format: Format::Rgba16f, prefilter: true, samples: 0,
+                                .. CubeOptions::default() };
+    let cube = envmap::cube_texture(&striped_faces(), &options).unwrap();
+    let level = cube.image(1, 0, 0);
+    // a NaN half float has an all ones exponent
+    for h in level.chunks(2) {
+        assert!(h[1] & 0x7c != 0x7c);
+    }
+}
@@ -0,0 +1,210 @@
+/*
+ * Copyright (c) 2014 Cameron Hart
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a
+ * copy of this software and associated documentation files (the "Software"),
+ * to deal in the Software without restriction, including without limitation
+ * the rights to use, copy, modify, merge, publish, distribute, sublicense,
+ * and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+ * THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+ * FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+ * DEALINGS IN THE SOFTWARE.
+ */
+
+extern crate gl;
+extern crate sb6;
+
+use sb6::gltf;
+use sb6::gltf::{GltfModel, Primitive};
+use sb6::object::SubObject;
+use std::path::Path;
+
+fn push_floats(bytes: &mut Vec<u8>, values: &[f32]) {
+    for v in values {
+        bytes.extend_from_slice(&v.to_bits().to_le_bytes());
+    }
+}
+
+fn base64(data: &[u8]) -> String {
+    const DIGITS: &'static [u8] =
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(DIGITS[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+const IMAGE: &'static [u8] = b"not really a png";
+
+/// The buffer holds three position arrays, the indices of the first
+/// primitive and an embedded image
+fn buffer() -> Vec<u8> {
+    let mut data = Vec::new();
+    push_floats(&mut data, &[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+    push_floats(&mut data, &[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0]);
+    push_floats(&mut data, &[1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0]);
+    for &i in &[2u16, 0, 1, 0] {
+        data.extend_from_slice(&i.to_le_bytes());
+    }
+    data.extend_from_slice(IMAGE);
+    data
+}
+
+/// Two meshes, the first with an indexed triangle and a triangle fan quad
+/// under a translated parent and a scaled child, the second rotated a
+/// quarter turn about Z
+fn json(buffer_uri: Option<&str>, length: usize) -> String {
+    let uri = buffer_uri.map_or(String::new(), |uri| format!(r#", "uri": "{}""#, uri));
+    format!(r#"{{
+        "asset": {{"version": "2.0"}},
+        "scene": 0,
+        "scenes": [{{"nodes": [0, 2]}}],
+        "nodes": [
+            {{"translation": [1, 2, 3], "children": [1]}},
+            {{"scale": [2, 2, 2], "mesh": 0}},
+            {{"rotation": [0, 0, 0.70710678, 0.70710678], "mesh": 1}}
+        ],
+        "meshes": [
+            {{"name": "first", "primitives": [
+                {{"attributes": {{"POSITION": 0}}, "indices": 3, "material": 0}},
+                {{"attributes": {{"POSITION": 1}}, "mode": 6, "material": 1}}
+            ]}},
+            {{"primitives": [{{"attributes": {{"POSITION": 2}}}}]}}
+        ],
+        "materials": [
+            {{"name": "textured",
+              "pbrMetallicRoughness": {{"baseColorTexture": {{"index": 0}}}},
+              "normalTexture": {{"index": 1, "texCoord": 1, "scale": 0.5}}}},
+            {{"alphaMode": "MASK", "alphaCutoff": 0.25}}
+        ],
+        "samplers": [{{"magFilter": 9728, "wrapS": 33071}}],
+        "textures": [{{"source": 0, "sampler": 0}}, {{"source": 1}}],
+        "images": [{{"uri": "brick%20wall.png"}}, {{"bufferView": 4, "mimeType": "image/png"}}],
+        "buffers": [{{"byteLength": {}{}}}],
+        "bufferViews": [
+            {{"buffer": 0, "byteOffset": 0, "byteLength": 36}},
+            {{"buffer": 0, "byteOffset": 36, "byteLength": 48}},
+            {{"buffer": 0, "byteOffset": 84, "byteLength": 36}},
+            {{"buffer": 0, "byteOffset": 120, "byteLength": 6}},
+            {{"buffer": 0, "byteOffset": 128, "byteLength": {}}}
+        ],
+        "accessors": [
+            {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"}},
+            {{"bufferView": 1, "componentType": 5126, "count": 4, "type": "VEC3"}},
+            {{"bufferView": 2, "componentType": 5126, "count": 3, "type": "VEC3"}},
+            {{"bufferView": 3, "componentType": 5123, "count": 3, "type": "SCALAR"}}
+        ]
+    }}"#, length, uri, IMAGE.len())
+}
+
+fn positions(model: &GltfModel) -> Vec<[f32; 3]> {
+    let mesh = &model.mesh;
+    let attrib = mesh.attrib("position").unwrap();
+    assert_eq!((attrib.size, attrib.ty), (3, gl::FLOAT));
+    let data = &mesh.vertex_data()[attrib.data_offset as usize..];
+    (0..mesh.total_vertices() as usize).map(|i| {
+        let f = |c: usize| {
+            let b = &data[(i * 3 + c) * 4..];
+            f32::from_bits(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        };
+        [f(0), f(1), f(2)]
+    }).collect()
+}
+
+fn check_model(model: &GltfModel) {
+    // each primitive becomes a sub-object, the fan is split into triangles
+    assert_eq!(model.mesh.sub_objects(), &[SubObject { first: 0, count: 3 },
+                                            SubObject { first: 3, count: 6 },
+                                            SubObject { first: 9, count: 3 }][..]);
+    assert_eq!(model.mesh.indices(), vec![2, 0, 1, 3, 4, 5, 3, 5, 6, 7, 8, 9]);
+    assert_eq!(model.primitives, vec![
+        Primitive { mesh: 0, mesh_name: Some(String::from("first")), material: Some(0) },
+        Primitive { mesh: 0, mesh_name: Some(String::from("first")), material: Some(1) },
+        Primitive { mesh: 1, mesh_name: None, material: None }
+    ]);
+    assert!(model.material(2).is_none());
+
+    // the parent's translation applies after the child's scale
+    let expected = [[1.0, 2.0, 3.0], [3.0, 2.0, 3.0], [1.0, 4.0, 3.0],
+                    [1.0, 2.0, 3.0], [3.0, 2.0, 3.0], [3.0, 4.0, 3.0], [1.0, 4.0, 3.0],
+                    [0.0, 1.0, 0.0], [-1.0, 0.0, 0.0], [0.0, 0.0, 1.0]];
+    let actual = positions(model);
+    assert_eq!(actual.len(), expected.len());
+    for (a, e) in actual.iter().zip(expected.iter()) {
+        for c in 0..3 {
+            assert!((a[c] - e[c]).abs() < 1.0e-5, "{:?} != {:?}", actual, expected);
+        }
+    }
+    // normals are generated for primitives without them
+    assert!(model.mesh.attrib("normal").is_some());
+
+    let textured = model.material(0).unwrap();
+    assert_eq!(textured.name, Some(String::from("textured")));
+    let base = textured.base_color_texture.as_ref().unwrap();
+    assert_eq!((base.image, base.texcoord), (Some(0), 0));
+    assert_eq!(base.options.srgb, Some(true));
+    assert_eq!(base.options.mag_filter, gl::NEAREST);
+    assert_eq!((base.options.wrap_s, base.options.wrap_t), (gl::CLAMP_TO_EDGE, gl::REPEAT));
+    let normal = textured.normal_texture.as_ref().unwrap();
+    assert_eq!((normal.image, normal.texcoord), (Some(1), 1));
+    assert_eq!(normal.options.srgb, Some(false));
+    assert_eq!(textured.normal_scale, 0.5);
+    assert!(textured.metallic_roughness_texture.is_none());
+    assert_eq!(model.material(1).unwrap().alpha_mode, gltf::AlphaMode::Mask(0.25));
+
+    assert_eq!(model.images[0].uri, Some(String::from("brick wall.png")));
+    assert!(model.images[0].data.is_none());
+    assert_eq!(model.images[1].data, Some(IMAGE.to_vec()));
+    assert_eq!(model.images[1].mime_type, Some(String::from("image/png")));
+}
+
+#[test]
+fn parse_embedded_gltf() {
+    let data = buffer();
+    let uri = format!("data:application/octet-stream;base64,{}", base64(&data));
+    let text = json(Some(&uri), data.len());
+    check_model(&gltf::parse(text.as_bytes(), Path::new(".")).unwrap());
+}
+
+#[test]
+fn parse_glb() {
+    let mut data = buffer();
+    let mut text = json(None, data.len()).into_bytes();
+    // chunks are padded to four bytes, JSON with spaces
+    while text.len() % 4 != 0 {
+        text.push(b' ');
+    }
+    while data.len() % 4 != 0 {
+        data.push(0);
+    }
+    let mut glb = b"glTF".to_vec();
+    for &value in &[2, 12 + 8 + text.len() + 8 + data.len()] {
+        glb.extend_from_slice(&(value as u32).to_le_bytes());
+    }
+    glb.extend_from_slice(&(text.len() as u32).to_le_bytes());
+    glb.extend_from_slice(b"JSON");
+    glb.extend_from_slice(&text);
+    glb.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    glb.extend_from_slice(b"BIN\0");
+    glb.extend_from_slice(&data);
+    check_model(&gltf::parse(&glb, Path::new(".")).unwrap());
+}
@@ -0,0 +1,196 @@
+/*
+ * Copyright (c) 2014 Cameron Hart
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a
+ * copy of this software and associated documentation files (the "Software"),
+ * to deal in the Software without restriction, including without limitation
+ * the rights to use, copy, modify, merge, publish, distribute, sublicense,
+ * and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+ * THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+ * FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+ * DEALINGS IN THE SOFTWARE.
+ */
+
+extern crate flate2;
+extern crate gl;
+extern crate sb6;
+
+use flate2::Compression;
+use flate2::Crc;
+use flate2::write::ZlibEncoder;
+use sb6::image;
+use sb6::image::{Image, ImageError};
+use sb6::ktx;
+use sb6::ktx::KtxImage;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::process::Command;
+
+fn png_chunk(bytes: &mut Vec<u8>, name: &[u8], data: &[u8]) {
+    let mut crc = Crc::new();
+    crc.update(name);
+    crc.update(data);
+    bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(name);
+    bytes.extend_from_slice(data);
+    bytes.extend_from_slice(&crc.sum().to_be_bytes());
+}
+
+/// Builds a PNG from filtered rows, with any chunks in `extra` before the
+/// image data
+fn png(width: u32, height: u32, depth: u8, color_type: u8, extra: &[(&[u8], &[u8])],
+       raw: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![137, 80, 78, 71, 13, 10, 26, 10];
+    let mut header = width.to_be_bytes().to_vec();
+    header.extend_from_slice(&height.to_be_bytes());
+    header.extend_from_slice(&[depth, color_type, 0, 0, 0]);
+    png_chunk(&mut bytes, b"IHDR", &header);
+    for &(name, data) in extra {
+        png_chunk(&mut bytes, name, data);
+    }
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(raw).unwrap();
+    png_chunk(&mut bytes, b"IDAT", &encoder.finish().unwrap());
+    png_chunk(&mut bytes, b"IEND", &[]);
+    bytes
+}
+
+fn assert_pixels(image: &Image, width: usize, height: usize, pixels: &[[f32; 4]]) {
+    assert_eq!((image.width, image.height), (width, height));
+    for (i, expected) in pixels.iter().enumerate() {
+        let actual = image.get(i % width, i / width);
+        for c in 0..4 {
+            assert!((actual[c] - expected[c]).abs() < 1.0e-6,
+                    "pixel {}: {:?} != {:?}", i, actual, expected);
+        }
+    }
+}
+
+#[test]
+fn decode_png() {
+    // the first row uses the sub filter, the second the up filter
+    let raw = [1, 255, 0, 0, 255, 1, 255, 0, 129,
+               2, 1, 0, 255, 0, 255, 0, 255, 128];
+    let image = image::decode(&png(2, 2, 8, 6, &[], &raw)).unwrap();
+    assert!(!image.linear);
+    assert_pixels(&image, 2, 2, &[[1.0, 0.0, 0.0, 1.0], [0.0, 1.0, 0.0, 128.0 / 255.0],
+                                  [0.0, 0.0, 1.0, 1.0], [1.0, 1.0, 1.0, 0.0]]);
+
+    // one bit palette indices with transparency for the first entry
+    let palette: &[u8] = &[10, 20, 30, 40, 50, 60];
+    let image = image::decode(&png(2, 1, 1, 3, &[(b"PLTE", palette), (b"tRNS", &[0])],
+                                   &[0, 0x40])).unwrap();
+    assert_pixels(&image, 2, 1, &[[10.0 / 255.0, 20.0 / 255.0, 30.0 / 255.0, 0.0],
+                                  [40.0 / 255.0, 50.0 / 255.0, 60.0 / 255.0, 1.0]]);
+
+    let image = image::decode(&png(1, 1, 16, 0, &[], &[0, 0x80, 0x00])).unwrap();
+    let g = 32768.0 / 65535.0;
+    assert_pixels(&image, 1, 1, &[[g, g, g, 1.0]]);
+}
+
+#[test]
+fn decode_png_overrun() {
+    // a small stream expanding far beyond the five bytes a 1x1 image needs
+    let bytes = png(1, 1, 8, 6, &[], &vec![0; 64 << 20]);
+    assert!(bytes.len() < 1 << 20);
+    match image::decode(&bytes) {
+        Err(ImageError::FormatError(_)) => {},
+        r => panic!("expected a format error, got {:?}", r)
+    }
+    match image::decode(&png(1, 1, 8, 6, &[], &[0, 1, 2, 3])) {
+        Err(ImageError::FormatError(_)) => {},
+        r => panic!("expected a format error, got {:?}", r)
+    }
+}
+
+fn tga(image_type: u8, width: u16, height: u16, bits: u8, descriptor: u8, data: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![0, 0, image_type, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    bytes.extend_from_slice(&width.to_le_bytes());
+    bytes.extend_from_slice(&height.to_le_bytes());
+    bytes.extend_from_slice(&[bits, descriptor]);
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+#[test]
+fn decode_tga() {
+    // BGR rows from the bottom up
+    let data = [255, 0, 0, 0, 255, 0,
+                0, 0, 255, 255, 255, 255];
+    let image = image::decode(&tga(2, 2, 2, 24, 0, &data)).unwrap();
+    assert_pixels(&image, 2, 2, &[[1.0, 0.0, 0.0, 1.0], [1.0, 1.0, 1.0, 1.0],
+                                  [0.0, 0.0, 1.0, 1.0], [0.0, 1.0, 0.0, 1.0]]);
+
+    // run length encoded BGRA from the top down, a run then two raw pixels
+    let data = [0x81, 0, 0, 255, 128,
+                0x01, 0, 255, 0, 255, 255, 0, 0, 0];
+    let image = image::decode(&tga(10, 2, 2, 32, 0x28, &data)).unwrap();
+    assert_pixels(&image, 2, 2, &[[1.0, 0.0, 0.0, 128.0 / 255.0], [1.0, 0.0, 0.0, 128.0 / 255.0],
+                                  [0.0, 1.0, 0.0, 1.0], [0.0, 0.0, 1.0, 0.0]]);
+}
+
+#[test]
+fn decode_hdr() {
+    let mut bytes = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 2\n".to_vec();
+    bytes.extend_from_slice(&[128, 64, 0, 129, 0, 0, 0, 0]);
+    let image = image::decode(&bytes).unwrap();
+    assert!(image.linear);
+    assert_pixels(&image, 2, 1, &[[1.0, 0.5, 0.0, 1.0], [0.0, 0.0, 0.0, 1.0]]);
+
+    // run length encoded scanlines, the bottom row first
+    let mut bytes = b"#?RADIANCE\n\n+Y 2 +X 8\n".to_vec();
+    for &(r, e) in &[(128, 130), (128, 129)] {
+        bytes.extend_from_slice(&[2, 2, 0, 8]);
+        // runs for red, raw values for green, runs for blue and exponent
+        bytes.extend_from_slice(&[128 + 8, r]);
+        bytes.push(8);
+        bytes.extend_from_slice(&[0, 32, 64, 96, 128, 160, 192, 224]);
+        bytes.extend_from_slice(&[128 + 8, 0, 128 + 8, e]);
+    }
+    let image = image::decode(&bytes).unwrap();
+    assert_eq!((image.width, image.height), (8, 2));
+    assert_eq!(image.get(0, 0), [1.0, 0.0, 0.0, 1.0]);
+    assert_eq!(image.get(7, 0), [1.0, 224.0 / 128.0, 0.0, 1.0]);
+    assert_eq!(image.get(3, 1), [2.0, 96.0 / 64.0, 0.0, 1.0]);
+}
+
+#[test]
+fn ktxconvert() {
+    let dir = env::temp_dir().join(format!("sb6-ktxconvert-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("input.tga");
+    let output = dir.join("output.ktx");
+    fs::write(&input, tga(2, 2, 1, 24, 0x20, &[0, 0, 255, 255, 0, 0])).unwrap();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_ktxconvert"))
+        .args(&["-f", "rgba8", "-m"]).arg(&output).arg(&input)
+        .status().unwrap();
+    assert!(status.success());
+    let ktx = KtxImage::open(output.to_str().unwrap()).unwrap();
+    assert_eq!(ktx.header().gl_internal_format, gl::RGBA8);
+    assert_eq!(ktx.num_levels(), 2);
+    assert_eq!(ktx.level(0), &[255, 0, 0, 255, 0, 0, 255, 255][..]);
+    assert_eq!(ktx.level(1), &[128, 0, 128, 255][..]);
+    assert_eq!(ktx.key_values().get_str(ktx::KEY_ORIENTATION), Some("S=r,T=d"));
+
+    // unreadable input fails without writing anything
+    fs::remove_file(&output).unwrap();
+    let result = Command::new(env!("CARGO_BIN_EXE_ktxconvert"))
+        .arg(&output).arg(dir.join("missing.tga"))
+        .output().unwrap();
+    assert_eq!(result.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&result.stdout).starts_with("Error reading"));
+    assert!(!output.exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,626 @@
+/*
+ * Copyright (c) 2014 Cameron Hart
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a
+ * copy of this software and associated documentation files (the "Software"),
+ * to deal in the Software without restriction, including without limitation
+ * the rights to use, copy, modify, merge, publish, distribute, sublicense,
+ * and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+ * THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+ * FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+ * DEALINGS IN THE SOFTWARE.
+ */
+
+//! Headless tests for KTX parsing, none of these need an OpenGL context.
+
+extern crate gl;
+extern crate sb6;
+
+use sb6::decompress;
+use sb6::ktx;
+use sb6::ktx::{Direction, Header, KeyValueData, KtxImage, LoadError, Orientation};
+use sb6::ktx2;
+use sb6::mipmap;
+use std::cmp;
+use std::env;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::process;
+use std::process::Command;
+
+const IDENTIFIER: [u8; 12] =
+    [ 0xAB, 0x4B, 0x54, 0x58, 0x20, 0x31, 0x31, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A ];
+
+fn push_u32(bytes: &mut Vec<u8>, value: u32) {
+    bytes.extend_from_slice(&[value as u8, (value >> 8) as u8, (value >> 16) as u8,
+                              (value >> 24) as u8]);
+}
+
+fn pad(bytes: &mut Vec<u8>) {
+    while bytes.len() % 4 != 0 {
+        bytes.push(0);
+    }
+}
+
+fn rgba8_header(width: i32, height: i32, array_elements: i32, faces: i32,
+                mip_levels: i32) -> Header {
+    Header {
+        gl_type: gl::UNSIGNED_BYTE,
+        gl_type_size: 1,
+        gl_format: gl::RGBA,
+        gl_internal_format: gl::RGBA8,
+        gl_base_internal_format: gl::RGBA,
+        pixel_width: width,
+        pixel_height: height,
+        pixel_depth: 0,
+        array_elements: array_elements,
+        faces: faces,
+        mip_levels: mip_levels,
+        key_pair_bytes: 0
+    }
+}
+
+/// Builds a little endian KTX file by hand, `levels` holds the images of
+/// each level in array layer then cube face order.
+fn build(h: &Header, key_values: &[(&str, &[u8])], levels: &[Vec<Vec<u8>>]) -> Vec<u8> {
+    let mut kv = Vec::new();
+    for &(key, value) in key_values.iter() {
+        push_u32(&mut kv, (key.len() + 1 + value.len()) as u32);
+        kv.extend_from_slice(key.as_bytes());
+        kv.push(0);
+        kv.extend_from_slice(value);
+        pad(&mut kv);
+    }
+
+    let mut bytes = IDENTIFIER.to_vec();
+    for value in [0x04030201, h.gl_type, h.gl_type_size, h.gl_format,
+                  h.gl_internal_format, h.gl_base_internal_format,
+                  h.pixel_width as u32, h.pixel_height as u32, h.pixel_depth as u32,
+                  h.array_elements as u32, h.faces as u32, h.mip_levels as u32,
+                  kv.len() as u32].iter() {
+        push_u32(&mut bytes, *value);
+    }
+    bytes.extend_from_slice(&kv);
+
+    let is_cube = h.array_elements == 0 && h.faces == 6;
+    for images in levels.iter() {
+        if is_cube {
+            push_u32(&mut bytes, images[0].len() as u32);
+            for face in images.iter() {
+                bytes.extend_from_slice(face);
+                pad(&mut bytes);
+            }
+        }
+        else {
+            let size = images.iter().fold(0, |total, image| total + image.len());
+            push_u32(&mut bytes, size as u32);
+            for image in images.iter() {
+                bytes.extend_from_slice(image);
+            }
+            pad(&mut bytes);
+        }
+    }
+    bytes
+}
+
+/// An RGBA8 image of the given size where every byte holds `value`
+fn solid(width: usize, height: usize, value: u8) -> Vec<u8> {
+    vec![value; width * height * 4]
+}
+
+#[test]
+fn parse_2d_mip_chain() {
+    let h = rgba8_header(4, 2, 0, 1, 3);
+    let levels = vec![vec![(0..32).collect::<Vec<u8>>()],
+                      vec![solid(2, 1, 1)],
+                      vec![solid(1, 1, 2)]];
+    let bytes = build(&h, &[("KTXorientation", b"S=r,T=d\0"), ("empty", b"")], &levels);
+    let image = KtxImage::parse(bytes).unwrap();
+
+    assert_eq!(image.target(), gl::TEXTURE_2D);
+    assert!(!image.is_swapped());
+    assert_eq!(image.num_levels(), 3);
+    assert_eq!(image.dimensions(0), (4, 2, 0));
+    assert_eq!(image.dimensions(2), (1, 1, 0));
+    assert_eq!(image.level(0), &levels[0][0][..]);
+    assert_eq!(image.level(1), &levels[1][0][..]);
+    assert_eq!(image.level(2), &levels[2][0][..]);
+    assert_eq!(image.key_values().len(), 2);
+    assert_eq!(image.key_values().get_str("KTXorientation"), Some("S=r,T=d"));
+    assert_eq!(image.key_values().get("empty"), Some(&b""[..]));
+}
+
+#[test]
+fn parse_cube_map() {
+    let h = rgba8_header(2, 2, 0, 6, 2);
+    let levels = vec![(0..6).map(|face| solid(2, 2, face)).collect::<Vec<_>>(),
+                      (0..6).map(|face| solid(1, 1, 10 + face)).collect::<Vec<_>>()];
+    let image = KtxImage::parse(build(&h, &[], &levels)).unwrap();
+
+    assert_eq!(image.target(), gl::TEXTURE_CUBE_MAP);
+    assert_eq!(image.num_levels(), 2);
+    for face in 0..6 {
+        assert_eq!(image.image(0, 0, face), &levels[0][face][..]);
+        assert_eq!(image.image(1, 0, face), &levels[1][face][..]);
+    }
+}
+
+#[test]
+fn parse_array() {
+    let h = rgba8_header(1, 1, 3, 0, 1);
+    let levels = vec![(0..3).map(|layer| solid(1, 1, layer)).collect::<Vec<_>>()];
+    let image = KtxImage::parse(build(&h, &[], &levels)).unwrap();
+
+    assert_eq!(image.target(), gl::TEXTURE_2D_ARRAY);
+    assert_eq!(image.header().num_layers(), 3);
+    for layer in 0..3 {
+        assert_eq!(image.image(0, layer, 0), &levels[0][layer][..]);
+    }
+}
+
+#[test]
+fn parse_3d() {
+    // the depth halves with each level like the width and height
+    let mut h = rgba8_header(2, 1, 0, 1, 3);
+    h.pixel_depth = 4;
+    let levels = vec![vec![(0..32).collect::<Vec<u8>>()],
+                      vec![solid(1, 2, 1)],
+                      vec![solid(1, 1, 2)]];
+    let image = KtxImage::parse(build(&h, &[], &levels)).unwrap();
+
+    assert_eq!(image.target(), gl::TEXTURE_3D);
+    assert_eq!(image.num_levels(), 3);
+    assert_eq!(image.dimensions(0), (2, 1, 4));
+    assert_eq!(image.dimensions(1), (1, 1, 2));
+    assert_eq!(image.dimensions(2), (1, 1, 1));
+    for level in 0..3 {
+        assert_eq!(h.image_size(level), Some(levels[level][0].len()));
+        assert_eq!(image.image(level, 0, 0), &levels[level][0][..]);
+    }
+
+    // the size of a level counts every depth slice
+    let mut short = levels.clone();
+    short[0][0].truncate(8);
+    match KtxImage::parse(build(&h, &[], &short)) {
+        Err(LoadError::LevelSizeError(0, _, 8, 32)) => (),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("parsed a level with one depth slice")
+    }
+
+    // there are no 3D array or cube map textures
+    for &(array_elements, faces) in [(2, 1), (0, 6)].iter() {
+        let mut h = rgba8_header(2, 2, array_elements, faces, 1);
+        h.pixel_depth = 2;
+        match KtxImage::parse(build(&h, &[], &[vec![solid(2, 4, 0)]])) {
+            Err(LoadError::HeaderError("pixel_depth", 2)) => (),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("parsed a 3D texture with {} layers and {} faces",
+                            array_elements, faces)
+        }
+    }
+}
+
+#[test]
+fn reject_bad_identifier() {
+    let h = rgba8_header(1, 1, 0, 1, 1);
+    let mut bytes = build(&h, &[], &[vec![solid(1, 1, 0)]]);
+    bytes[1] = b'X';
+    match KtxImage::parse(bytes) {
+        Err(LoadError::MagicError) => (),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("parsed a file with a bad identifier")
+    }
+}
+
+#[test]
+fn reject_truncated_level() {
+    let h = rgba8_header(2, 2, 0, 1, 2);
+    let mut bytes = build(&h, &[], &[vec![solid(2, 2, 0)], vec![solid(1, 1, 0)]]);
+    let len = bytes.len();
+    bytes.truncate(len - 2);
+    match KtxImage::parse(bytes) {
+        Err(LoadError::TruncatedError(..)) => (),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("parsed a truncated file")
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> usize {
+    (bytes[offset] as usize) | (bytes[offset + 1] as usize) << 8 |
+        (bytes[offset + 2] as usize) << 16 | (bytes[offset + 3] as usize) << 24
+}
+
+/// Converts a little endian file from `build` into the big endian file a
+/// writer on a big endian host would produce.
+fn to_big_endian(mut bytes: Vec<u8>, type_size: usize) -> Vec<u8> {
+    let is_cube = read_u32(&bytes, 48) == 0 && read_u32(&bytes, 52) == 6;
+    let key_pair_bytes = read_u32(&bytes, 60);
+
+    // endianness and header
+    for field in 0..13 {
+        bytes[12 + field * 4..16 + field * 4].reverse();
+    }
+    let mut pos = 64;
+    while pos < 64 + key_pair_bytes {
+        let size = read_u32(&bytes, pos);
+        bytes[pos..pos + 4].reverse();
+        pos += 4 + (size + 3) / 4 * 4;
+    }
+    while pos < bytes.len() {
+        let image_size = read_u32(&bytes, pos);
+        bytes[pos..pos + 4].reverse();
+        pos += 4;
+        let images = if is_cube { 6 } else { 1 };
+        for _ in 0..images {
+            for element in bytes[pos..pos + image_size].chunks_mut(type_size) {
+                element.reverse();
+            }
+            pos += (image_size + 3) / 4 * 4;
+        }
+    }
+    bytes
+}
+
+fn assert_same_image(a: &KtxImage, b: &KtxImage) {
+    assert_eq!(a.header().gl_type, b.header().gl_type);
+    assert_eq!(a.header().gl_internal_format, b.header().gl_internal_format);
+    assert_eq!(a.dimensions(0), b.dimensions(0));
+    assert_eq!(a.num_levels(), b.num_levels());
+    assert_eq!(a.key_values().iter().collect::<Vec<_>>(),
+               b.key_values().iter().collect::<Vec<_>>());
+    for level in 0..a.num_levels() {
+        assert_eq!(a.level(level), b.level(level));
+    }
+}
+
+fn check_swapped(h: &Header, levels: &[Vec<Vec<u8>>]) {
+    let little = build(h, &[("KTXorientation", b"S=r,T=u\0")], levels);
+    let big = to_big_endian(little.clone(), h.gl_type_size as usize);
+    let expected = KtxImage::parse(little).unwrap();
+    let image = KtxImage::parse(big).unwrap();
+    assert!(image.is_swapped());
+    assert_same_image(&expected, &image);
+}
+
+#[test]
+fn parse_swapped() {
+    // one byte elements only swap the header
+    check_swapped(&rgba8_header(2, 2, 0, 1, 2),
+                  &[vec![(0..16).collect()], vec![vec![1, 2, 3, 4]]]);
+    check_swapped(&rgba8_header(2, 2, 0, 6, 1),
+                  &[(0..6).map(|face| solid(2, 2, face)).collect()]);
+
+    let mut h = rgba8_header(3, 1, 0, 1, 1);
+    h.gl_type = gl::UNSIGNED_SHORT;
+    h.gl_type_size = 2;
+    h.gl_format = gl::RGB;
+    h.gl_internal_format = gl::RGB16;
+    h.gl_base_internal_format = gl::RGB;
+    check_swapped(&h, &[vec![(0..18).chain(0..2).collect()]]);
+
+    let mut h = rgba8_header(2, 1, 2, 0, 1);
+    h.gl_type = gl::FLOAT;
+    h.gl_type_size = 4;
+    h.gl_internal_format = gl::RGBA32F;
+    check_swapped(&h, &[vec![(0..32).collect(), (32..64).collect()]]);
+
+    let mut h = rgba8_header(1, 1, 0, 1, 1);
+    h.gl_type = gl::DOUBLE;
+    h.gl_type_size = 8;
+    h.gl_format = gl::RG;
+    h.gl_internal_format = gl::RG32F;
+    h.gl_base_internal_format = gl::RG;
+    check_swapped(&h, &[vec![(0..16).collect()]]);
+}
+
+fn round_trip(image: &KtxImage) -> KtxImage {
+    let mut bytes = Vec::new();
+    ktx::write(&mut bytes, image).unwrap();
+    KtxImage::parse(bytes).unwrap()
+}
+
+#[test]
+fn write_round_trip() {
+    let mut h = rgba8_header(3, 2, 0, 1, 2);
+    h.gl_type = gl::UNSIGNED_SHORT;
+    h.gl_type_size = 2;
+    h.gl_internal_format = gl::RGBA16;
+    let mut kv = KeyValueData::new();
+    kv.insert_str("KTXorientation", "S=r,T=d");
+    kv.insert("odd", vec![1, 2, 3]);
+    let levels = vec![(0..48).collect::<Vec<u8>>(), (0..8).collect()];
+    let image = KtxImage::new(h, kv, levels.clone()).unwrap();
+    assert!(!image.is_swapped());
+    assert_eq!(image.level(0), &levels[0][..]);
+    assert_eq!(image.level(1), &levels[1][..]);
+
+    // the data read back must be in native order without being swapped
+    let written = round_trip(&image);
+    assert!(!written.is_swapped());
+    assert_same_image(&image, &written);
+
+    let cube = KtxImage::parse(build(&rgba8_header(2, 2, 0, 6, 1), &[],
+        &[(0..6).map(|face| solid(2, 2, face)).collect()])).unwrap();
+    assert_same_image(&cube, &round_trip(&cube));
+
+    // swapped files are written back in native order
+    let mut h = rgba8_header(2, 1, 0, 1, 1);
+    h.gl_type = gl::FLOAT;
+    h.gl_type_size = 4;
+    h.gl_internal_format = gl::RGBA32F;
+    let little = build(&h, &[], &[vec![(0..32).collect()]]);
+    let swapped = KtxImage::parse(to_big_endian(little.clone(), 4)).unwrap();
+    let written = round_trip(&swapped);
+    assert!(!written.is_swapped());
+    assert_same_image(&KtxImage::parse(little).unwrap(), &written);
+}
+
+/// Builds a little endian KTX2 file of a 2D texture by hand, with the levels
+/// tightly packed and stored smallest first
+fn build_ktx2(vk_format: u32, width: u32, height: u32, key_values: &[(&str, &[u8])],
+              levels: &[Vec<u8>]) -> Vec<u8> {
+    let mut kv = Vec::new();
+    for &(key, value) in key_values.iter() {
+        push_u32(&mut kv, (key.len() + 1 + value.len()) as u32);
+        kv.extend_from_slice(key.as_bytes());
+        kv.push(0);
+        kv.extend_from_slice(value);
+        pad(&mut kv);
+    }
+
+    let mut bytes = ktx2::IDENTIFIER.to_vec();
+    for value in [vk_format, 1, width, height, 0, 0, 1, levels.len() as u32, 0].iter() {
+        push_u32(&mut bytes, *value);
+    }
+    let kvd_offset = 80 + 24 * levels.len();
+    for value in [0, 0, kvd_offset as u32, kv.len() as u32, 0, 0, 0, 0].iter() {
+        push_u32(&mut bytes, *value);
+    }
+    let mut offset = kvd_offset + kv.len();
+    let mut offsets = vec![0; levels.len()];
+    for (i, level) in levels.iter().enumerate().rev() {
+        offsets[i] = offset;
+        offset += (level.len() + 3) / 4 * 4;
+    }
+    for (level, &offset) in levels.iter().zip(offsets.iter()) {
+        for value in [offset, level.len(), level.len()].iter() {
+            push_u32(&mut bytes, *value as u32);
+            push_u32(&mut bytes, 0);
+        }
+    }
+    bytes.extend_from_slice(&kv);
+    for level in levels.iter().rev() {
+        bytes.extend_from_slice(level);
+        pad(&mut bytes);
+    }
+    bytes
+}
+
+#[test]
+fn parse_ktx2() {
+    // RGB8 rows of a 3 pixel wide level are padded from 9 to 12 bytes
+    let levels = vec![(0..18).collect::<Vec<u8>>(), vec![100, 101, 102]];
+    let image = KtxImage::parse(build_ktx2(23, 3, 2, &[("KTXorientation", b"rd\0")],
+                                           &levels)).unwrap();
+
+    let mut h = rgba8_header(3, 2, 0, 1, 2);
+    h.gl_format = gl::RGB;
+    h.gl_internal_format = gl::RGB8;
+    h.gl_base_internal_format = gl::RGB;
+    let padded = vec![vec![(0..9).chain(vec![0; 3]).chain(9..18).chain(vec![0; 3]).collect()],
+                      vec![vec![100, 101, 102, 0]]];
+    let expected = KtxImage::parse(build(&h, &[("KTXorientation", b"S=r,T=d\0")],
+                                         &padded)).unwrap();
+    assert_same_image(&expected, &image);
+    assert_eq!(image.target(), gl::TEXTURE_2D);
+    assert_eq!(image.key_values().orientation(),
+               Some(Orientation { s: Direction::Right, t: Direction::Down, r: None }));
+
+    assert_eq!(Orientation::parse_ktx2("lui"),
+               Some(Orientation { s: Direction::Left, t: Direction::Up, r: Some(Direction::In) }));
+    assert_eq!(Orientation::parse_ktx2("rdo").unwrap().to_string(), "S=r,T=d,R=o");
+    assert_eq!(Orientation::parse_ktx2("S=r,T=d"), None);
+    assert_eq!(Orientation::parse_ktx2("rdix"), None);
+
+    // a level one byte short of its last padded row
+    let short = vec![(0..17).collect::<Vec<u8>>(), vec![100, 101, 102]];
+    match KtxImage::parse(build_ktx2(23, 3, 2, &[], &short)) {
+        Err(LoadError::LevelSizeError(0, _, 12, 24)) => (),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("parsed a truncated level")
+    }
+}
+
+/// A reader handing out a few bytes at a time
+struct Trickle<'a>(&'a [u8]);
+
+impl<'a> Read for Trickle<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = cmp::min(3, cmp::min(buf.len(), self.0.len()));
+        buf[..n].copy_from_slice(&self.0[..n]);
+        self.0 = &self.0[n..];
+        Ok(n)
+    }
+}
+
+#[test]
+fn parse_read_open() {
+    // the file, memory and reader loaders all see the same image
+    let h = rgba8_header(2, 2, 3, 1, 2);
+    let levels = vec![(0..3).map(|layer| solid(2, 2, layer)).collect::<Vec<_>>(),
+                      (0..3).map(|layer| solid(1, 1, 10 + layer)).collect::<Vec<_>>()];
+    let ktx1 = build(&h, &[("KTXorientation", b"S=r,T=d\0")], &levels);
+    let ktx2 = build_ktx2(43, 3, 1, &[], &[(0..12).collect(), vec![1, 2, 3, 4]]);
+    for bytes in [ktx1, ktx2].iter() {
+        let path = env::temp_dir().join(format!("sb6-parse-read-open-{}.ktx", process::id()));
+        fs::write(&path, bytes).unwrap();
+        let opened = KtxImage::open(path.to_str().unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let parsed = KtxImage::parse(bytes.clone()).unwrap();
+        assert_same_image(&parsed, &opened);
+        assert_same_image(&parsed, &KtxImage::read(&bytes[..]).unwrap());
+        assert_same_image(&parsed, &KtxImage::read(Trickle(bytes)).unwrap());
+    }
+
+    let bytes = build(&h, &[], &levels);
+    match KtxImage::read(Trickle(&bytes[..bytes.len() - 1])) {
+        Err(LoadError::TruncatedError(..)) => (),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("read a truncated file")
+    }
+}
+
+#[test]
+fn upload_orientation() {
+    // uncompressed T=d images are flipped so the bottom row comes first
+    let h = rgba8_header(1, 2, 0, 1, 1);
+    let rows = vec![1, 1, 1, 1, 2, 2, 2, 2];
+    let image = KtxImage::parse(build(&h, &[("KTXorientation", b"S=r,T=d\0")],
+                                      &[vec![rows.clone()]])).unwrap();
+    assert!(ktx::flips_on_upload(&image));
+    assert_eq!(&ktx::sub_images(&image)[0].data[..], &[2, 2, 2, 2, 1, 1, 1, 1]);
+
+    // compressed images can't be flipped, decompressing them must not
+    // change the orientation
+    let mut h = rgba8_header(4, 4, 0, 1, 1);
+    h.gl_type = 0;
+    h.gl_format = 0;
+    h.gl_internal_format = ktx::COMPRESSED_RGB_S3TC_DXT1_EXT;
+    h.gl_base_internal_format = gl::RGB;
+    let block = vec![0x00, 0xf8, 0x1f, 0x00, 0x00, 0x55, 0xaa, 0xff];
+    let image = KtxImage::parse(build(&h, &[("KTXorientation", b"S=r,T=d\0")],
+                                      &[vec![block.clone()]])).unwrap();
+    assert!(!ktx::flips_on_upload(&image));
+    assert_eq!(&ktx::sub_images(&image)[0].data[..], &block[..]);
+    let decompressed = decompress::decompress(&image).unwrap();
+    let expected = decompress::decompress_image(h.gl_internal_format, 4, 4, 1, &block).unwrap();
+    let flip = ktx::flips_on_upload(&image);
+    assert_eq!(&ktx::sub_images_flipped(&decompressed, flip)[0].data[..], &expected[..]);
+}
+
+#[test]
+fn ktxinfo_validation() {
+    let dir = env::temp_dir().join(format!("sb6-ktxinfo-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let good = dir.join("good.ktx");
+    let bad = dir.join("bad.ktx");
+    let truncated = dir.join("truncated.ktx");
+    let h = rgba8_header(1, 1, 0, 1, 1);
+    fs::write(&good, build(&h, &[("KTXorientation", b"S=r,T=d\0")],
+                           &[vec![solid(1, 1, 0)]])).unwrap();
+    // loadable, but breaking the specification
+    let mut mismatched = h;
+    mismatched.gl_base_internal_format = gl::RGB;
+    fs::write(&bad, build(&mismatched, &[("KTXorientation", b"S=up\0")],
+                          &[vec![solid(1, 1, 0)]])).unwrap();
+    let mut bytes = build(&h, &[], &[vec![solid(1, 1, 0)]]);
+    bytes.pop();
+    fs::write(&truncated, bytes).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_ktxinfo"))
+        .arg(&good).arg(&bad).arg(&truncated)
+        .output().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let reports: Vec<&str> = stdout.split(dir.to_str().unwrap()).skip(1).collect();
+    assert_eq!(reports.len(), 3);
+    assert!(reports[0].starts_with("/good.ktx\n"));
+    assert!(!reports[0].contains("error"));
+    assert!(reports[0].contains("KTXorientation = \"S=r,T=d\""));
+    assert!(reports[1].contains(
+        "error: glBaseInternalFormat 0x1907 doesn't match glFormat 0x1908\n"));
+    assert!(reports[1].contains("error: malformed KTXorientation value\n"));
+    assert!(reports[2].starts_with("/truncated.ktx\nerror: "));
+}
+
+#[test]
+fn generated_mip_levels() {
+    // a numberOfMipmapLevels of 0 asks for a generated chain, 1 is a single
+    // level texture
+    let requested = rgba8_header(8, 4, 0, 1, 0);
+    assert!(ktx::needs_generated_mipmaps(&requested));
+    assert_eq!(ktx::storage_levels(&requested), 4);
+    let single = rgba8_header(8, 4, 0, 1, 1);
+    assert!(!ktx::needs_generated_mipmaps(&single));
+    assert_eq!(ktx::storage_levels(&single), 1);
+
+    let image = |h: &Header| KtxImage::parse(build(h, &[], &[vec![solid(8, 4, 7)]])).unwrap();
+    let completed = mipmap::complete(image(&requested), mipmap::Filter::Box).unwrap();
+    assert_eq!(completed.num_levels(), 4);
+    assert_eq!(completed.image(3, 0, 0), &[7, 7, 7, 7][..]);
+    let kept = mipmap::complete(image(&single), mipmap::Filter::Box).unwrap();
+    assert_eq!(kept.num_levels(), 1);
+}
+
+#[test]
+fn texture_options() {
+    let h = rgba8_header(4, 4, 0, 1, 3);
+    let levels = vec![vec![solid(4, 4, 1)], vec![solid(2, 2, 2)], vec![solid(1, 1, 3)]];
+    let image = || KtxImage::parse(build(&h, &[], &levels)).unwrap();
+    let with = |mipmaps, srgb| ktx::TextureOptions {
+        mipmaps: mipmaps,
+        srgb: srgb,
+        .. ktx::TextureOptions::default()
+    };
+
+    // the file's levels are kept unless only the base level is wanted
+    let kept = ktx::prepare_image(image(), &with(ktx::MipPolicy::Auto, None)).unwrap();
+    assert_eq!(kept.num_levels(), 3);
+    assert_eq!(kept.header().gl_internal_format, gl::RGBA8);
+    let base = ktx::prepare_image(image(), &with(ktx::MipPolicy::BaseLevel, None)).unwrap();
+    assert_eq!(base.num_levels(), 1);
+    assert_eq!(base.header().mip_levels, 1);
+    assert_eq!(base.level(0), &levels[0][0][..]);
+    let cpu = ktx::MipPolicy::Cpu(mipmap::Filter::Box);
+    assert_eq!(ktx::prepare_image(image(), &with(cpu, None)).unwrap().num_levels(), 3);
+
+    // only a numberOfMipmapLevels of 0 has levels generated
+    let requested = rgba8_header(4, 4, 0, 1, 0);
+    let image = || KtxImage::parse(build(&requested, &[], &[vec![solid(4, 4, 9)]])).unwrap();
+    let auto = ktx::prepare_image(image(), &with(ktx::MipPolicy::Auto, None)).unwrap();
+    assert_eq!(auto.num_levels(), 1);
+    assert!(ktx::needs_generated_mipmaps(auto.header()));
+    let generated = ktx::prepare_image(image(), &with(cpu, None)).unwrap();
+    assert_eq!(generated.num_levels(), 3);
+    assert_eq!(generated.level(2), &[9, 9, 9, 9][..]);
+    assert!(!ktx::needs_generated_mipmaps(generated.header()));
+
+    // the sRGB override picks the matching format, when there is one
+    let srgb = ktx::prepare_image(image(), &with(ktx::MipPolicy::Auto, Some(true))).unwrap();
+    assert_eq!(srgb.header().gl_internal_format, gl::SRGB8_ALPHA8);
+    let mut h = rgba8_header(1, 1, 0, 1, 1);
+    h.gl_internal_format = gl::SRGB8_ALPHA8;
+    let image = |h: &Header| KtxImage::parse(build(h, &[], &[vec![solid(1, 1, 0)]])).unwrap();
+    let linear = ktx::prepare_image(image(&h), &with(ktx::MipPolicy::Auto, Some(false))).unwrap();
+    assert_eq!(linear.header().gl_internal_format, gl::RGBA8);
+    h.gl_internal_format = gl::RGBA16F;
+    h.gl_type = gl::HALF_FLOAT;
+    h.gl_type_size = 2;
+    let float = KtxImage::parse(build(&h, &[], &[vec![vec![0; 8]]])).unwrap();
+    let float = ktx::prepare_image(float, &with(ktx::MipPolicy::Auto, Some(true))).unwrap();
+    assert_eq!(float.header().gl_internal_format, gl::RGBA16F);
+
+    assert_eq!(ktx::srgb_format(gl::RGB8, true), Some(gl::SRGB8));
+    assert_eq!(ktx::srgb_format(gl::SRGB8, true), Some(gl::SRGB8));
+    assert_eq!(ktx::srgb_format(gl::SRGB8, false), Some(gl::RGB8));
+    assert_eq!(ktx::srgb_format(ktx::COMPRESSED_RGBA_S3TC_DXT5_EXT, true),
+               Some(ktx::COMPRESSED_SRGB_ALPHA_S3TC_DXT5_EXT));
+    assert_eq!(ktx::srgb_format(ktx::COMPRESSED_RGBA_ASTC_12x12_KHR, true),
+               Some(ktx::COMPRESSED_SRGB8_ALPHA8_ASTC_12x12_KHR));
+    assert_eq!(ktx::srgb_format(ktx::COMPRESSED_SRGB8_ALPHA8_ASTC_4x4_KHR, false),
+               Some(ktx::COMPRESSED_RGBA_ASTC_4x4_KHR));
+    assert_eq!(ktx::srgb_format(gl::RGBA16F, true), None);
+}
@@ -0,0 +1,120 @@
+/*
+ * Copyright (c) 2014 Cameron Hart
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a
+ * copy of this software and associated documentation files (the "Software"),
+ * to deal in the Software without restriction, including without limitation
+ * the rights to use, copy, modify, merge, publish, distribute, sublicense,
+ * and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+ * THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+ * FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+ * DEALINGS IN THE SOFTWARE.
+ */
+
+//! Runs every loader over a corpus of truncated and corrupted files. The
+//! loaders may reject them but must never panic.
+
+extern crate sb6;
+
+use sb6::convert;
+use sb6::decompress;
+use sb6::gltf;
+use sb6::image;
+use sb6::ktx;
+use sb6::ktx::KtxImage;
+use sb6::obj;
+use sb6::object;
+use sb6::object::SbmMesh;
+use sb6::ply;
+use sb6::stl;
+use std::fs;
+use std::path::Path;
+use std::str;
+
+const CORPUS: &'static str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/malformed");
+
+fn use_ktx(image: &KtxImage) {
+    let h = image.header();
+    for level in 0..image.num_levels() {
+        image.level(level);
+        for layer in 0..h.num_layers() {
+            for face in 0..h.num_faces() {
+                image.image(level, layer, face);
+            }
+        }
+        let _ = convert::ktx_images(image, level);
+    }
+    ktx::sub_images(image);
+    let _ = decompress::decompress(image);
+    ktx::write(&mut Vec::new(), image).unwrap();
+}
+
+fn use_sbm(mesh: &SbmMesh) {
+    mesh.vertex_data();
+    mesh.indices();
+    object::write(&mut Vec::new(), mesh).unwrap();
+}
+
+/// Runs every loader over the file's contents, and the ones that map files
+/// over the file itself if a path is given
+pub fn load_all(bytes: &[u8], path: Option<&Path>) {
+    if let Ok(image) = KtxImage::parse(bytes.to_vec()) {
+        use_ktx(&image);
+    }
+    if let Ok(mesh) = SbmMesh::parse(bytes.to_vec()) {
+        use_sbm(&mesh);
+    }
+    if let Some(path) = path {
+        let filename = path.to_str().unwrap();
+        if let Ok(image) = KtxImage::open(filename) {
+            use_ktx(&image);
+        }
+        if let Ok(mesh) = SbmMesh::open(filename) {
+            use_sbm(&mesh);
+        }
+    }
+    if let Ok(text) = str::from_utf8(bytes) {
+        if let Ok(model) = obj::parse(text) {
+            if let Ok(mesh) = model.to_sbm() {
+                use_sbm(&mesh);
+            }
+        }
+        let _ = obj::parse_mtl(text);
+    }
+    if let Ok(model) = gltf::parse(bytes, Path::new(CORPUS)) {
+        use_sbm(&model.mesh);
+        for i in 0..model.images.len() {
+            let _ = model.load_image(i);
+        }
+    }
+    if let Ok(mesh) = ply::parse(bytes) {
+        let _ = mesh.to_sbm(vec![]);
+    }
+    if let Ok(mesh) = stl::parse(bytes) {
+        let _ = mesh.to_sbm(vec![]);
+    }
+    let _ = image::decode(bytes);
+}
+
+#[test]
+fn malformed_files() {
+    let mut count = 0;
+    for entry in fs::read_dir(CORPUS).unwrap() {
+        let path = entry.unwrap().path();
+        let bytes = fs::read(&path).unwrap();
+        println!("{}", path.display());
+        load_all(&bytes, Some(&path));
+        count += 1;
+    }
+    assert!(count > 0);
+}
@@ -0,0 +1,76 @@
+/*
+ * Copyright (c) 2014 Cameron Hart
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a
+ * copy of this software and associated documentation files (the "Software"),
+ * to deal in the Software without restriction, including without limitation
+ * the rights to use, copy, modify, merge, publish, distribute, sublicense,
+ * and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+ * THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+ * FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+ * DEALINGS IN THE SOFTWARE.
+ */
+
+extern crate sb6;
+
+use sb6::convert::Format;
+use sb6::ktx::{KeyValueData, KtxImage};
+use sb6::mipmap;
+use sb6::mipmap::Filter;
+
+const FILTERS: [Filter; 3] = [Filter::Box, Filter::Kaiser, Filter::Lanczos];
+
+#[test]
+fn weights_sum_to_one() {
+    for &filter in FILTERS.iter() {
+        for &(src_size, dst_size) in [(16, 8), (7, 3), (5, 2), (3, 1), (2, 1), (1, 1)].iter() {
+            let taps = mipmap::weights(src_size, dst_size, filter);
+            assert_eq!(taps.len(), dst_size);
+            for pixel in &taps {
+                assert!(pixel.iter().all(|&(j, _)| j < src_size));
+                let total = pixel.iter().fold(0.0, |sum, t| sum + t.1);
+                assert!((total - 1.0).abs() < 1.0e-5, "{:?} {}->{}: {:?}",
+                        filter, src_size, dst_size, pixel);
+            }
+        }
+    }
+}
+
+fn constant_image(format: Format, width: usize, height: usize, layers: i32, value: u8)
+                  -> KtxImage {
+    let mut header = format.header(width, height);
+    header.array_elements = layers;
+    header.faces = 1;
+    header.mip_levels = 1;
+    let size = width * height * 4 * layers.max(1) as usize;
+    KtxImage::new(header, KeyValueData::new(), vec![vec![value; size]]).unwrap()
+}
+
+#[test]
+fn constant_images_stay_constant() {
+    for &filter in FILTERS.iter() {
+        for &format in [Format::Rgba8, Format::Srgb8Alpha8].iter() {
+            let image = constant_image(format, 7, 5, 2, 77);
+            let generated = mipmap::generate(&image, filter).unwrap();
+            // 7x5, 3x2 and 1x1
+            assert_eq!(generated.num_levels(), 3);
+            assert_eq!(generated.dimensions(2), (1, 1, 0));
+            for level in 0..generated.num_levels() {
+                let data = generated.level(level);
+                assert!(!data.is_empty());
+                assert!(data.iter().all(|&b| b == 77), "{:?} {:?} level {}: {:?}",
+                        filter, format, level, data);
+            }
+        }
+    }
+}
@@ -0,0 +1,185 @@
+/*
+ * Copyright (c) 2014 Cameron Hart
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a
+ * copy of this software and associated documentation files (the "Software"),
+ * to deal in the Software without restriction, including without limitation
+ * the rights to use, copy, modify, merge, publish, distribute, sublicense,
+ * and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+ * THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+ * FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+ * DEALINGS IN THE SOFTWARE.
+ */
+
+extern crate sb6;
+
+use sb6::obj;
+use sb6::obj::{Group, ObjError};
+use sb6::object::SubObject;
+use std::env;
+use std::fs;
+
+#[test]
+fn parse_faces() {
+    let model = obj::parse("# a quad and a triangle sharing an edge
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+f 1/1/1 2/2/1 3/3/1 4/4/1
+v 2 0 0
+f -4/2/-1 5/2/1 -3/-2/1
+").unwrap();
+    let mesh = &model.mesh;
+    // the quad is a fan around its first vertex, the triangle's relative
+    // indices refer back to the quad's second and third vertices
+    assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3, 1, 4, 2]);
+    assert_eq!(mesh.positions, vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0],
+                                    [0.0, 1.0, 0.0], [2.0, 0.0, 0.0]]);
+    assert_eq!(mesh.texcoords, vec![[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0], [1.0, 0.0]]);
+    assert_eq!(mesh.normals, vec![[0.0, 0.0, 1.0]; 5]);
+    assert_eq!(mesh.sub_objects, vec![SubObject { first: 0, count: 9 }]);
+    assert_eq!(model.groups, vec![Group { name: String::from("default"), material: None }]);
+}
+
+#[test]
+fn parse_vertex_combinations() {
+    // a position is split into a vertex for each texcoord it's used with,
+    // and normals are generated when the file has none
+    let model = obj::parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nv 0 0 -1\n\
+                            vt 0 0\nvt 1 1\n\
+                            f 1/1 2/1 3/1\nf 1/2 3/1 4/1\nf 1/1 2/1 3/1\n").unwrap();
+    let mesh = &model.mesh;
+    assert_eq!(mesh.indices, vec![0, 1, 2, 3, 2, 4, 0, 1, 2]);
+    assert_eq!(mesh.positions.len(), 5);
+    assert_eq!(mesh.positions[3], mesh.positions[0]);
+    assert_eq!(mesh.texcoords[3], [1.0, 1.0]);
+    assert_eq!(mesh.normals.len(), 5);
+    assert_eq!(mesh.normals[0], [0.0, 0.0, 1.0]);
+    assert_eq!(mesh.normals[3], [-1.0, 0.0, 0.0]);
+
+    // vertices without a texcoord get zero when others have one
+    let model = obj::parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0.5 0.5\nvn 0 0 1\n\
+                            f 1//1 2/1/1 3//1\n").unwrap();
+    assert_eq!(model.mesh.texcoords, vec![[0.0, 0.0], [0.5, 0.5], [0.0, 0.0]]);
+}
+
+#[test]
+fn parse_groups() {
+    let model = obj::parse("mtllib first.mtl
+v 0 0 0
+v 1 0 0
+v 0 1 0
+o ignored
+g body
+usemtl red
+f 1 2 3
+f 1 3 2
+usemtl blue
+f 1 2 3
+g
+usemtl
+f 3 2 1
+mtllib second.mtl
+").unwrap();
+    assert_eq!(model.mesh.sub_objects, vec![SubObject { first: 0, count: 6 },
+                                            SubObject { first: 6, count: 3 },
+                                            SubObject { first: 9, count: 3 }]);
+    assert_eq!(model.groups, vec![
+        Group { name: String::from("body"), material: Some(String::from("red")) },
+        Group { name: String::from("body"), material: Some(String::from("blue")) },
+        Group { name: String::from("default"), material: None }
+    ]);
+    assert_eq!(model.material_libraries, vec!["first.mtl", "second.mtl"]);
+    let comments = model.to_sbm().unwrap().comments().to_vec();
+    assert_eq!(comments, vec!["g body\nusemtl red", "g body\nusemtl blue", "g default"]);
+}
+
+#[test]
+fn parse_mtl() {
+    let materials = obj::parse_mtl("# two materials
+newmtl red
+Ka 0.1 0 0
+Kd 1 0 0
+Ks 0.5 0.5 0.5
+Ns 32
+d 0.5
+map_Kd -s 2 2 1 textures/red brick.png
+
+newmtl plain
+Tr 0.25
+").unwrap();
+    assert_eq!(materials.len(), 2);
+    let red = &materials[0];
+    assert_eq!(red.name, "red");
+    assert_eq!(red.ambient, [0.1, 0.0, 0.0]);
+    assert_eq!(red.diffuse, [1.0, 0.0, 0.0]);
+    assert_eq!(red.specular, [0.5; 3]);
+    assert_eq!(red.shininess, 32.0);
+    assert_eq!(red.dissolve, 0.5);
+    assert_eq!(red.diffuse_map, Some(String::from("brick.png")));
+    let plain = &materials[1];
+    assert_eq!((plain.ambient, plain.diffuse, plain.specular), ([0.2; 3], [0.8; 3], [1.0; 3]));
+    assert_eq!(plain.dissolve, 0.75);
+    assert_eq!(plain.diffuse_map, None);
+}
+
+fn expect_error(text: &str, expected: &str) {
+    match obj::parse(text) {
+        Err(e) => assert_eq!(format!("{:?}", e), expected),
+        Ok(_) => panic!("parsed {:?}", text)
+    }
+}
+
+#[test]
+fn parse_errors() {
+    let triangle = "v 0 0 0\nv 1 0 0\nv 0 1 0\n";
+    expect_error(&format!("{}\nf 1 2 4\n", triangle), "IndexError(5)");
+    expect_error(&format!("{}f 1 2 -4\n", triangle), "IndexError(4)");
+    expect_error(&format!("{}f 0 1 2\n", triangle), "IndexError(4)");
+    expect_error(&format!("{}f 1/1 2 3\n", triangle), "IndexError(4)");
+    expect_error(&format!("# comment\n{}f 1 2\n", triangle),
+                 "ParseError(5, \"face with fewer than three vertices\")");
+    expect_error("v 0 0\n", "ParseError(1, \"missing number\")");
+    expect_error("v 0 x 0\n", "ParseError(1, \"invalid number\")");
+    expect_error(&format!("{}f 1 a 3\n", triangle), "ParseError(4, \"invalid index\")");
+    expect_error(triangle, "NoFacesError");
+    match obj::parse_mtl("\nKd 1 1 1\n") {
+        Err(ObjError::ParseError(2, "statement before newmtl")) => {},
+        r => panic!("expected a parse error, got {:?}", r)
+    }
+}
+
+#[test]
+fn open_with_materials() {
+    let dir = env::temp_dir().join(format!("sb6-obj-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("model.obj"), "mtllib model.mtl\nmtllib missing.mtl\n\
+                                      v 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl shiny\nf 1 2 3\n").unwrap();
+    fs::write(dir.join("model.mtl"), "newmtl shiny\nNs 100\n").unwrap();
+
+    // libraries are found next to the model, ones that can't be read are
+    // skipped
+    let model = obj::open(dir.join("model.obj").to_str().unwrap()).unwrap();
+    assert_eq!(model.material_libraries, vec!["model.mtl", "missing.mtl"]);
+    assert_eq!(model.materials.len(), 1);
+    assert_eq!(model.material("shiny").unwrap().shininess, 100.0);
+    assert!(model.material("dull").is_none());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
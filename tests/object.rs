@@ -0,0 +1,176 @@
+/*
+ * Copyright (c) 2014 Cameron Hart
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a
+ * copy of this software and associated documentation files (the "Software"),
+ * to deal in the Software without restriction, including without limitation
+ * the rights to use, copy, modify, merge, publish, distribute, sublicense,
+ * and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+ * THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+ * FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+ * DEALINGS IN THE SOFTWARE.
+ */
+
+extern crate gl;
+extern crate sb6;
+
+use sb6::obj;
+use sb6::object;
+use sb6::object::{SbmMesh, SubObject, VertexAttrib};
+
+fn push_u32(bytes: &mut Vec<u8>, value: u32) {
+    bytes.extend_from_slice(&[value as u8, (value >> 8) as u8, (value >> 16) as u8,
+        (value >> 24) as u8]);
+}
+
+fn push_chunk(bytes: &mut Vec<u8>, chunk_type: &[u8], payload: &[u32]) {
+    bytes.extend_from_slice(chunk_type);
+    push_u32(bytes, 8 + payload.len() as u32 * 4);
+    for &value in payload {
+        push_u32(bytes, value);
+    }
+}
+
+/// Builds an sb6 style SBM file by hand, an indexed quad with two float
+/// position components and no sub-object list
+fn legacy_quad() -> Vec<u8> {
+    let positions = [0.0f32, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 1.0];
+    let indices = [0u32, 1, 2, 2, 1, 3];
+    // magic, header, ATRB with one 84 byte declaration, VRTX and INDX
+    let vertex_offset = 16 + 12 + 84 + 20 + 20;
+    let index_offset = vertex_offset + positions.len() as u32 * 4;
+
+    let mut bytes = b"SB6M".to_vec();
+    for &value in &[16, 3, 0] {
+        push_u32(&mut bytes, value);
+    }
+    bytes.extend_from_slice(b"ATRB");
+    push_u32(&mut bytes, 12 + 84);
+    push_u32(&mut bytes, 1);
+    let mut name = b"position".to_vec();
+    name.resize(64, 0);
+    bytes.extend_from_slice(&name);
+    for &value in &[2, gl::FLOAT, 0, 0, 0] {
+        push_u32(&mut bytes, value);
+    }
+    push_chunk(&mut bytes, b"VRTX", &[positions.len() as u32 * 4, vertex_offset, 4]);
+    push_chunk(&mut bytes, b"INDX", &[gl::UNSIGNED_INT, indices.len() as u32, index_offset]);
+    assert_eq!(bytes.len(), vertex_offset as usize);
+    for &p in &positions {
+        push_u32(&mut bytes, p.to_bits());
+    }
+    for &i in &indices {
+        push_u32(&mut bytes, i);
+    }
+    bytes
+}
+
+fn round_trip(mesh: &SbmMesh) -> SbmMesh {
+    let mut bytes = Vec::new();
+    object::write(&mut bytes, mesh).unwrap();
+    SbmMesh::parse(bytes).unwrap()
+}
+
+#[test]
+fn legacy_sub_objects() {
+    // sb6 draws the whole index buffer, sub-objects are vertex ranges
+    let mesh = SbmMesh::parse(legacy_quad()).unwrap();
+    assert!(mesh.is_indexed());
+    assert!(!mesh.has_index_ranges());
+    assert_eq!(mesh.indices(), vec![0, 1, 2, 2, 1, 3]);
+    assert_eq!(mesh.sub_objects(), &[SubObject { first: 0, count: 4 }][..]);
+    assert!(!round_trip(&mesh).has_index_ranges());
+}
+
+#[test]
+fn new_mesh_index_ranges() {
+    let attribs = vec![VertexAttrib {
+        name: String::from("position"),
+        size: 2,
+        ty: gl::FLOAT,
+        stride: 0,
+        normalized: false,
+        data_offset: 0
+    }];
+    let data = vec![0; 4 * 8];
+    let indexed = SbmMesh::new(attribs.clone(), data.clone(), 4, Some(&[0, 1, 2, 2, 1, 3]),
+                               Vec::new(), Vec::new()).unwrap();
+    assert!(indexed.has_index_ranges());
+    assert_eq!(indexed.flags() & object::MESH_FLAG_INDEX_RANGES, object::MESH_FLAG_INDEX_RANGES);
+    assert_eq!(indexed.sub_objects(), &[SubObject { first: 0, count: 6 }][..]);
+    let parsed = round_trip(&indexed);
+    assert!(parsed.has_index_ranges());
+    assert_eq!(parsed.sub_objects(), &[SubObject { first: 0, count: 6 }][..]);
+
+    let unindexed = SbmMesh::new(attribs, data, 4, None, Vec::new(), Vec::new()).unwrap();
+    assert!(!unindexed.has_index_ranges());
+    assert_eq!(unindexed.sub_objects(), &[SubObject { first: 0, count: 4 }][..]);
+}
+
+fn assert_same_mesh(a: &SbmMesh, b: &SbmMesh) {
+    assert_eq!(a.flags(), b.flags());
+    assert_eq!(a.attribs(), b.attribs());
+    assert_eq!(a.total_vertices(), b.total_vertices());
+    assert_eq!(a.vertex_data(), b.vertex_data());
+    assert_eq!(a.index_type(), b.index_type());
+    assert_eq!(a.indices(), b.indices());
+    assert_eq!(a.sub_objects(), b.sub_objects());
+    assert_eq!(a.comments(), b.comments());
+}
+
+/// Checks a mesh survives being written and parsed, and that writing it
+/// again gives the same bytes
+fn check_round_trip(mesh: &SbmMesh) {
+    let parsed = round_trip(mesh);
+    assert_same_mesh(mesh, &parsed);
+    let mut first = Vec::new();
+    object::write(&mut first, mesh).unwrap();
+    let mut second = Vec::new();
+    object::write(&mut second, &parsed).unwrap();
+    assert_eq!(first, second);
+}
+
+#[test]
+fn write_round_trip() {
+    check_round_trip(&SbmMesh::parse(legacy_quad()).unwrap());
+
+    // interleaved attributes, 32 bit indices, sub-objects and comments of
+    // every padding length
+    let attribs = vec![
+        VertexAttrib { name: String::from("position"), size: 3, ty: gl::FLOAT, stride: 16,
+                       normalized: false, data_offset: 0 },
+        VertexAttrib { name: String::from("color"), size: 4, ty: gl::UNSIGNED_BYTE, stride: 16,
+                       normalized: true, data_offset: 12 }
+    ];
+    let data: Vec<u8> = (0..3 * 16).map(|i| i as u8).collect();
+    let indices = [0, 1, 2, 2, 1, 0x10000];
+    let sub_objects = vec![SubObject { first: 0, count: 3 }, SubObject { first: 3, count: 3 }];
+    let comments = vec![String::new(), String::from("a"), String::from("ab"),
+                        String::from("abc"), String::from("g quad\nusemtl red")];
+    let mesh = SbmMesh::new(attribs.clone(), data.clone(), 3, Some(&indices),
+                            sub_objects.clone(), comments.clone()).unwrap();
+    assert_eq!(mesh.index_type(), gl::UNSIGNED_INT);
+    check_round_trip(&mesh);
+
+    let unindexed = SbmMesh::new(attribs, data, 3, None, vec![SubObject { first: 1, count: 2 }],
+                                 comments).unwrap();
+    check_round_trip(&unindexed);
+
+    // an imported model with a sub-object and comment per group
+    let model = obj::parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nv 1 1 0\nvt 0 0\n\
+                            g front\nusemtl red\nf 1/1 2/1 3/1\n\
+                            g back\nf 3 2 4\n").unwrap();
+    let imported = model.to_sbm().unwrap();
+    assert_eq!(imported.sub_objects().len(), 2);
+    check_round_trip(&imported);
+}
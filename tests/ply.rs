@@ -0,0 +1,149 @@
+/*
+ * Copyright (c) 2014 Cameron Hart
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a
+ * copy of this software and associated documentation files (the "Software"),
+ * to deal in the Software without restriction, including without limitation
+ * the rights to use, copy, modify, merge, publish, distribute, sublicense,
+ * and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+ * THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+ * FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+ * DEALINGS IN THE SOFTWARE.
+ */
+
+extern crate sb6;
+
+use sb6::mesh::MeshData;
+use sb6::ply;
+use sb6::ply::PlyError;
+
+const HEADER: &'static str = "element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property float s
+property float t
+element face 1
+property list uchar int vertex_indices
+element edge 1
+property int vertex1
+property int vertex2
+end_header
+";
+
+fn check_quad(mesh: &MeshData) {
+    assert_eq!(mesh.positions, vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0],
+                                    [0.0, 1.0, 0.0]]);
+    assert_eq!(mesh.texcoords, vec![[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]);
+    // the quad is split into a fan
+    assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3]);
+    assert_eq!(mesh.normals, vec![[0.0, 0.0, 1.0]; 4]);
+}
+
+fn binary(big_endian: bool) -> Vec<u8> {
+    let format = if big_endian { "binary_big_endian" } else { "binary_little_endian" };
+    let mut bytes = format!("ply\nformat {} 1.0\ncomment binary\n{}", format, HEADER).into_bytes();
+    let float = |bytes: &mut Vec<u8>, v: f32| if big_endian {
+        bytes.extend_from_slice(&v.to_be_bytes())
+    } else {
+        bytes.extend_from_slice(&v.to_le_bytes())
+    };
+    let int = |bytes: &mut Vec<u8>, v: i32| if big_endian {
+        bytes.extend_from_slice(&v.to_be_bytes())
+    } else {
+        bytes.extend_from_slice(&v.to_le_bytes())
+    };
+    for &(x, y) in &[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)] {
+        float(&mut bytes, x);
+        float(&mut bytes, y);
+        float(&mut bytes, 0.0);
+        bytes.push(255);
+        float(&mut bytes, x);
+        float(&mut bytes, y);
+    }
+    bytes.push(4);
+    for i in 0..4 {
+        int(&mut bytes, i);
+    }
+    int(&mut bytes, 0);
+    int(&mut bytes, 1);
+    bytes
+}
+
+#[test]
+fn parse_ascii() {
+    let text = format!("ply\r\nformat ascii 1.0\ncomment made by hand\n{}\
+                        0 0 0 255 0 0\n1 0 0 255 1 0\n1 1 0 255 1 1\n0 1 0 255 0 1\n\
+                        4 0 1 2 3\n\
+                        0 1\n", HEADER);
+    check_quad(&ply::parse(text.as_bytes()).unwrap());
+}
+
+#[test]
+fn parse_binary() {
+    check_quad(&ply::parse(&binary(false)).unwrap());
+    check_quad(&ply::parse(&binary(true)).unwrap());
+}
+
+#[test]
+fn normals() {
+    // a tent of two triangles sharing an edge along the y axis
+    let text = "ply\nformat ascii 1.0\nelement vertex 4\n\
+                property float x\nproperty float y\nproperty float z\n\
+                element face 2\nproperty list uchar uint vertex_index\nend_header\n\
+                0 0 0\n0 1 0\n-1 0 -1\n1 0 -1\n\
+                3 0 1 2\n3 0 3 1\n";
+    let mesh = ply::parse(text.as_bytes()).unwrap();
+    let s = 0.5f32.sqrt();
+    let expected = [[0.0, 0.0, 1.0], [0.0, 0.0, 1.0], [-s, 0.0, s], [s, 0.0, s]];
+    assert_eq!(mesh.normals.len(), expected.len());
+    for (n, e) in mesh.normals.iter().zip(expected.iter()) {
+        for c in 0..3 {
+            assert!((n[c] - e[c]).abs() < 1.0e-6, "{:?} != {:?}", mesh.normals, expected);
+        }
+    }
+
+    // normals in the file are kept
+    let text = "ply\nformat ascii 1.0\nelement vertex 3\n\
+                property float x\nproperty float y\nproperty float z\n\
+                property float nx\nproperty float ny\nproperty float nz\n\
+                element face 1\nproperty list uchar int vertex_indices\nend_header\n\
+                0 0 0 1 0 0\n1 0 0 1 0 0\n0 1 0 1 0 0\n3 0 1 2\n";
+    assert_eq!(ply::parse(text.as_bytes()).unwrap().normals, vec![[1.0, 0.0, 0.0]; 3]);
+}
+
+#[test]
+fn malformed() {
+    let header = "ply\nformat binary_little_endian 1.0\n\
+                  element junk 18446744073709551615\nend_header\n";
+    match ply::parse(header.as_bytes()) {
+        Err(PlyError::HeaderError(_)) => {},
+        r => panic!("expected a header error, got {:?}", r)
+    }
+
+    let text = "ply\nformat ascii 1.0\nelement vertex 3\n\
+                property float x\nproperty float y\nproperty float z\n\
+                element face 2\nproperty list uchar int vertex_indices\nend_header\n\
+                0 0 0\n1 0 0\n0 1 0\n3 0 1 2\n3 0 1 3\n";
+    match ply::parse(text.as_bytes()) {
+        Err(PlyError::IndexError(1)) => {},
+        r => panic!("expected an index error, got {:?}", r)
+    }
+
+    let bytes = binary(false);
+    match ply::parse(&bytes[..bytes.len() - 10]) {
+        Err(PlyError::DataError) => {},
+        r => panic!("expected a data error, got {:?}", r)
+    }
+}
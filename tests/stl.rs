@@ -0,0 +1,134 @@
+/*
+ * Copyright (c) 2014 Cameron Hart
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a
+ * copy of this software and associated documentation files (the "Software"),
+ * to deal in the Software without restriction, including without limitation
+ * the rights to use, copy, modify, merge, publish, distribute, sublicense,
+ * and/or sell copies of the Software, and to permit persons to whom the
+ * Software is furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice (including the next
+ * paragraph) shall be included in all copies or substantial portions of the
+ * Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT.  IN NO EVENT SHALL
+ * THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+ * FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+ * DEALINGS IN THE SOFTWARE.
+ */
+
+extern crate sb6;
+
+use sb6::mesh::MeshData;
+use sb6::object::SubObject;
+use sb6::stl;
+use sb6::stl::StlError;
+
+fn binary(header: &[u8], triangles: &[[[f32; 3]; 4]]) -> Vec<u8> {
+    let mut bytes = header.to_vec();
+    bytes.resize(80, b' ');
+    bytes.extend_from_slice(&(triangles.len() as u32).to_le_bytes());
+    for triangle in triangles {
+        for v in triangle {
+            for c in v {
+                bytes.extend_from_slice(&c.to_le_bytes());
+            }
+        }
+        bytes.extend_from_slice(&[0, 0]);
+    }
+    bytes
+}
+
+const TRIANGLES: [[[f32; 3]; 4]; 2] = [
+    [[0.0, 0.0, 2.0], [0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+    // a zero normal is computed from the winding
+    [[0.0, 0.0, 0.0], [0.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]
+];
+
+fn check_triangles(mesh: &MeshData) {
+    assert_eq!(mesh.positions, vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0],
+                                    [0.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]);
+    assert_eq!(mesh.normals, vec![[0.0, 0.0, 1.0], [0.0, 0.0, 1.0], [0.0, 0.0, 1.0],
+                                  [1.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 0.0, 0.0]]);
+    assert_eq!(mesh.indices, vec![0, 1, 2, 3, 4, 5]);
+}
+
+#[test]
+fn parse_binary() {
+    check_triangles(&stl::parse(&binary(b"exported", &TRIANGLES)).unwrap());
+    // the size tells a binary file from an ASCII one starting with "solid"
+    check_triangles(&stl::parse(&binary(b"solid cube", &TRIANGLES)).unwrap());
+
+    let bytes = binary(b"exported", &TRIANGLES);
+    match stl::parse(&bytes[..bytes.len() - 1]) {
+        Err(StlError::SizeError(183, 184)) => {},
+        r => panic!("expected a size error, got {:?}", r)
+    }
+}
+
+#[test]
+fn parse_ascii() {
+    let text = "solid one
+  facet normal 0 0 2
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 0 1 0
+    endloop
+  endfacet
+  facet normal 0 0 0
+    outer loop
+      vertex 0 0 0
+      vertex 0 1 0
+      vertex 0 0 1
+    endloop
+  endfacet
+endsolid one
+";
+    let mesh = stl::parse(text.as_bytes()).unwrap();
+    check_triangles(&mesh);
+    assert!(mesh.sub_objects.is_empty());
+
+    // each of several solids is a sub-object, polygons are split into fans
+    let text = format!("{}solid two
+  facet normal 0 0 1
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 1 1 0
+      vertex 0 1 0
+    endloop
+  endfacet
+endsolid two
+", text);
+    let mesh = stl::parse(text.as_bytes()).unwrap();
+    assert_eq!(mesh.sub_objects, vec![SubObject { first: 0, count: 6 },
+                                      SubObject { first: 6, count: 6 }]);
+    assert_eq!(&mesh.positions[6..], &[[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0],
+                                       [0.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]][..]);
+
+    match stl::parse(b"solid bad\nfacet normal 0 0 1\nouter loop\nvertex 0 0\n") {
+        Err(StlError::ParseError(4, _)) => {},
+        r => panic!("expected a parse error, got {:?}", r)
+    }
+}
+
+#[test]
+fn smooth_normals() {
+    // two facets sharing an edge get the same normal there once welded
+    let mut mesh = stl::parse(&binary(b"", &[
+        [[0.0; 3], [0.0, 0.0, 0.0], [0.0, 1.0, 0.0], [-1.0, 0.0, 1.0]],
+        [[0.0; 3], [0.0, 0.0, 0.0], [1.0, 0.0, 1.0], [0.0, 1.0, 0.0]]
+    ])).unwrap();
+    mesh.normals.clear();
+    mesh.weld();
+    assert_eq!(mesh.positions.len(), 4);
+    assert_eq!(mesh.indices, vec![0, 1, 2, 0, 3, 1]);
+    mesh.generate_normals();
+    assert_eq!(mesh.normals[0], [0.0, 0.0, 1.0]);
+    assert_eq!(mesh.normals[1], [0.0, 0.0, 1.0]);
+}